@@ -180,6 +180,10 @@ function! padre#debugger#StepOver()
   call padre#socket#Send({"cmd": "stepOver"}, function('padre#debugger#StepOverCallback'))
 endfunction
 
+function! padre#debugger#Finish()
+  call padre#socket#Send({"cmd": "finish"}, function('padre#debugger#FinishCallback'))
+endfunction
+
 function! padre#debugger#PrintVariable(variable)
   call padre#socket#Send({"cmd": "print", "variable": a:variable}, function('padre#debugger#PrintVariableCallback'))
 endfunction
@@ -188,6 +192,18 @@ function! padre#debugger#Continue()
   call padre#socket#Send({"cmd": "continue"}, function('padre#debugger#ContinueCallback'))
 endfunction
 
+function! padre#debugger#Interrupt()
+  call padre#socket#Send({"cmd": "interrupt"}, function('padre#debugger#InterruptCallback'))
+endfunction
+
+function! padre#debugger#AddWatch(expression)
+  call padre#socket#Send({"cmd": "addWatch", "expression": a:expression}, function('padre#debugger#WatchCallback'))
+endfunction
+
+function! padre#debugger#RemoveWatch(expression)
+  call padre#socket#Send({"cmd": "removeWatch", "expression": a:expression}, function('padre#debugger#WatchCallback'))
+endfunction
+
 """"""""""""""""
 " API functions
 
@@ -212,17 +228,26 @@ endfunction
 function! padre#debugger#BreakpointCallback(channel_id, data)
   if a:data['status'] == 'OK'
   elseif a:data['status'] == 'PENDING'
-    call padre#debugger#Log(4, 'Breakpoint pending')
   else
     call padre#debugger#Log(2, 'Error: ' . string(a:data))
   endif
 endfunction
 
-function! padre#debugger#BreakpointSet(fileName, lineNum)
+function! padre#debugger#BreakpointSet(fileName, lineNum, ...)
   let l:msg = 'Breakpoint set file=' . a:fileName . ', line=' . a:lineNum
   call padre#debugger#Log(4, l:msg)
 endfunction
 
+function! padre#debugger#BreakpointPending(fileName, lineNum)
+  let l:msg = 'Breakpoint pending file=' . a:fileName . ', line=' . a:lineNum
+  call padre#debugger#Log(4, l:msg)
+endfunction
+
+function! padre#debugger#BreakpointVerified(fileName, lineNum, resolvedLineNum)
+  let l:msg = 'Breakpoint verified file=' . a:fileName . ', line=' . a:lineNum . ', resolved line=' . a:resolvedLineNum
+  call padre#debugger#Log(4, l:msg)
+endfunction
+
 function! padre#debugger#StepInCallback(channel_id, data)
   if a:data['status'] != 'OK'
     call padre#debugger#Log(2, 'Error: ' . string(a:data))
@@ -235,12 +260,32 @@ function! padre#debugger#StepOverCallback(channel_id, data)
   endif
 endfunction
 
+function! padre#debugger#FinishCallback(channel_id, data)
+  if a:data['status'] != 'OK'
+    call padre#debugger#Log(2, 'Error: ' . string(a:data))
+  elseif has_key(a:data, 'returnValue') && a:data['returnValue'] isnot v:null
+    call padre#debugger#Log(4, 'Returned ' . a:data['returnValue'])
+  endif
+endfunction
+
 function! padre#debugger#ContinueCallback(channel_id, data)
   if a:data['status'] != 'OK'
     call padre#debugger#Log(2, 'Error: ' . string(a:data))
   endif
 endfunction
 
+function! padre#debugger#InterruptCallback(channel_id, data)
+  if a:data['status'] != 'OK'
+    call padre#debugger#Log(2, 'Error: ' . string(a:data))
+  endif
+endfunction
+
+function! padre#debugger#WatchCallback(channel_id, data)
+  if a:data['status'] != 'OK'
+    call padre#debugger#Log(2, 'Error: ' . string(a:data))
+  endif
+endfunction
+
 function! padre#debugger#PrintVariableCallback(channel_id, data)
   let l:status = remove(a:data, 'status')
   if l:status != 'OK'
@@ -255,7 +300,7 @@ function! padre#debugger#PrintVariableCallback(channel_id, data)
   call padre#debugger#Log(4, l:msg)
 endfunction
 
-function! padre#debugger#JumpToPosition(file, line)
+function! padre#debugger#JumpToPosition(file, line, ...)
   let l:msg = 'Stopped file=' . a:file . ' line=' . a:line
   call padre#debugger#Log(4, l:msg)
 
@@ -297,6 +342,10 @@ function! padre#debugger#JumpToPosition(file, line)
 
   execute 'normal ' . a:line . 'G'
 
+  if a:0 >= 1 && type(a:1) == v:t_number
+    call cursor(a:line, a:1)
+  endif
+
   if l:current_window != l:source_window
     execute l:current_window . ' wincmd w'
   endif
@@ -308,6 +357,53 @@ function! padre#debugger#ProcessExited(exit_code, pid)
   call padre#debugger#Log(4, 'Process ' . a:pid . ' finished with exit code=' . a:exit_code)
 endfunction
 
+function! padre#debugger#ProgramOutput(stream, text)
+  for l:line in split(a:text, "\n")
+    call padre#debugger#Log(4, '(' . a:stream . ') ' . l:line)
+  endfor
+endfunction
+
+function! padre#debugger#ProtocolTrace(kind, detail)
+  call padre#debugger#Log(5, '[' . a:kind . '] ' . a:detail)
+endfunction
+
+function! padre#debugger#DebuggerReady()
+  call padre#debugger#Log(4, 'Debugger ready')
+endfunction
+
+function! padre#debugger#DebuggerInfo(version)
+  call padre#debugger#Log(4, 'Debugger version: ' . a:version)
+endfunction
+
+" Sent by PADRE when idle to check we're still here, nothing to do
+function! padre#debugger#Ping()
+endfunction
+
+function! padre#debugger#StepProgress(done, total)
+  call padre#debugger#Log(4, 'Stepped ' . a:done . ' of ' . a:total)
+endfunction
+
+" Sent by PADRE every HeartbeatInterval seconds when set, when they stop coming PADRE
+" isn't responding
+function! padre#debugger#Heartbeat(status)
+  let s:last_heartbeat = localtime()
+  let s:program_status = a:status
+endfunction
+
+function! padre#debugger#Logpoint(message)
+  call padre#debugger#Log(4, 'Logpoint: ' . a:message)
+endfunction
+
+function! padre#debugger#WatchpointHit(id, old_value, new_value)
+  call padre#debugger#Log(4, 'Watchpoint ' . a:id . ' changed from ' . string(a:old_value) . ' to ' . a:new_value)
+endfunction
+
+function! padre#debugger#WatchesUpdated(watches)
+  for l:watch in a:watches
+    call padre#debugger#Log(4, 'Watch ' . l:watch['expression'] . '=' . json_encode(l:watch['value']))
+  endfor
+endfunction
+
 function! padre#debugger#Log(level, text)
   let l:log_level_set = get(g:, 'PadreLogLevel', 4)
   let l:level = ''
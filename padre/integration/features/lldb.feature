@@ -72,7 +72,7 @@ Feature: LLDB
         When I send a request to PADRE '{"cmd":"print","variable":"a"}'
         Then I receive a response '{"status":"OK","variable":"a","value":"1","type":"int"}'
         When I send a request to PADRE '{"cmd":"continue"}'
-        Then I receive both a response '{"status":"OK"}' and I expect to be called with
+        Then I receive both a response '{"status":"OK","terminated":true}' and I expect to be called with
             | function                     | args       |
             | padre#debugger#ProcessExited | [0,"\\d+"] |
         When I terminate padre
@@ -121,7 +121,7 @@ Feature: LLDB
             | function                      | args                   |
             | padre#debugger#JumpToPosition | [".*test_prog.c$", 10] |
         When I send a request to PADRE '{"cmd":"continue"}'
-        Then I receive both a response '{"status":"OK"}' and I expect to be called with
+        Then I receive both a response '{"status":"OK","terminated":true}' and I expect to be called with
             | function                     | args       |
             | padre#debugger#ProcessExited | [0,"\\d+"] |
         When I terminate padre
@@ -230,7 +230,7 @@ Feature: LLDB
         When I send a request to PADRE '{"cmd":"setConfig","key":"ProcessSpawnTimeout","value":1}'
         Then I receive a response '{"status":"OK"}'
         When I send a request to PADRE '{"cmd":"run"}'
-        Then I receive both a response '{"status":"ERROR"}' and I expect to be called with
+        Then I receive both a response '{"status":"ERROR","code":"timeout"}' and I expect to be called with
             | function                     | args                             |
             | padre#debugger#Log           | [4,"Launching process"]          |
             | padre#debugger#BreakpointSet | ["test.c",25]                    |
@@ -244,7 +244,7 @@ Feature: LLDB
         When I send a request to PADRE '{"cmd":"setConfig","key":"BreakpointTimeout","value":1}'
         Then I receive a response '{"status":"OK"}'
         When I send a request to PADRE '{"cmd":"breakpoint","file":"test.c","line":17}'
-        Then I receive both a response '{"status":"ERROR"}' and I expect to be called with
+        Then I receive both a response '{"status":"ERROR","code":"timeout"}' and I expect to be called with
             | function           | args                                                      |
             | padre#debugger#Log | [4,"Setting breakpoint in file test.c at line number 17"] |
             | padre#debugger#Log | [2,"Timed out setting breakpoint"]                        |
@@ -263,6 +263,6 @@ Feature: LLDB
             | padre#debugger#JumpToPosition | [".*test_prog.c$",22]   |
             | padre#debugger#Log            | [4,"Launching process"] |
         When I send a request to PADRE '{"cmd":"print","variable":"a"}'
-        Then I receive both a response '{"status":"ERROR"}' and I expect to be called with
+        Then I receive both a response '{"status":"ERROR","code":"timeout"}' and I expect to be called with
             | function           | args                              |
             | padre#debugger#Log | [2,"Timed out printing variable"] |
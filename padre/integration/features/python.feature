@@ -70,7 +70,7 @@ Feature: Python
         When I send a request to PADRE '{"cmd":"print","variable":"b"}'
         Then I receive a response '{"status":"OK","variable":"b","value":"123"}'
         When I send a request to PADRE '{"cmd":"continue"}'
-        Then I receive both a response '{"status":"OK"}' and I expect to be called with
+        Then I receive both a response '{"status":"OK","terminated":true}' and I expect to be called with
             | function                      | args                   |
             | padre#debugger#ProcessExited  | [0,"\\d+"]             |
             | padre#debugger#JumpToPosition | [".*test_prog.py$", 3] |
@@ -15,8 +15,61 @@
 //!    Only used in LLDB.
 //!  - PrintVariableTimeout: Timeout for setting a breakpoint. Defaults to 2 second.
 //!    Only used in LLDB.
+//!  - RawCommandTimeout: Timeout for a raw command sent to the debugger to return
+//!    to the prompt. Defaults to 5 seconds.
+//!  - StepTimeout: Timeout for a step in, step over or continue to be acknowledged.
+//!    Defaults to 2 seconds.
+//!  - IncludeSourceLine: Set to 1 to include the source code of the line as a fourth
+//!    argument, after the column, when jumping to a position, only when the debugger
+//!    reports it (currently Python). Defaults to 0.
+//!  - CommandTimeout: Timeout for any debugger command that doesn't have its own
+//!    timeout config item. Defaults to 30 seconds.
+//!  - SourceMaps: Set to 1 to use the source maps of scripts so that breakpoints and
+//!    positions are in the original source files (e.g. TypeScript). Read when the
+//!    program is run, only used in Node. Defaults to 0.
+//!  - StopAtMain: Set to 0 to stop setting a breakpoint on `main` when the program is
+//!    run, e.g. for programs without a `main` function. Only used in LLDB, setting
+//!    StopOnEntry to 0 does the same. Defaults to 1.
+//!  - StopOnEntry: Set to 0 to run straight to the first breakpoint rather than stopping
+//!    when the program starts, at `main` in LLDB and the first line in Node and Python.
+//!    Stopping there is reported with the reason `entry`. Defaults to 1.
+//!  - TypedValues: Set to 1 to return values printed by LLDB or Python that are plain
+//!    numbers as JSON numbers rather than strings. Node always returns JSON values.
+//!    Defaults to 0.
+//!  - StepProgressInterval: Notify the progress of a step in or step over with a count
+//!    every time this many steps have been made, only used in LLDB and Python. 0 turns
+//!    this off. Defaults to 100.
+//!  - HeartbeatInterval: Seconds between heartbeat notifications saying what the
+//!    program is doing, so that a client can tell PADRE is still responding. 0 turns
+//!    this off. Defaults to 0.
+//!  - JumpCoalesceMs: Milliseconds to hold back notifications of the position the
+//!    program is at, only the latest position in that time is sent so that a burst of
+//!    stops while stepping doesn't flood the connection. The position is always sent
+//!    once the time's up, it can arrive after the response to the step. 0 turns this
+//!    off. Defaults to 0.
+//!  - DebugProtocol: Set to 1 to be sent a `ProtocolTrace` notification every time PADRE
+//!    starts or finishes a command, the Python analyser changes status and a command is
+//!    sent to the debugger, for debugging PADRE itself. Defaults to 0.
+//!  - IdleTimeout: Seconds without a request on a connection before it's sent a ping to
+//!    check it's still there, the connection is closed if the ping can't be sent. 0
+//!    turns this off. Defaults to 0.
+//!  - MaxValueBytes: The most bytes of a printed value, or the value of a child from
+//!    `getChildren` in Node, to return. Anything longer is cut short and ends with
+//!    `… (truncated)` so that printing a huge container doesn't flood the editor. 0 turns
+//!    this off. Defaults to 65536.
+//!  - Demangle: Set to 0 to report the function of each frame in `stackWithLocals` by its
+//!    mangled symbol name, e.g. `_ZN3foo3barEv` rather than `foo::bar()`, for C++ and Rust
+//!    programs. Only used in LLDB. Defaults to 1.
+//!
+//! Values outside the range allowed for an item are rejected. Timeouts must be between
+//! 1 and 3600 seconds, apart from IdleTimeout and HeartbeatInterval which can also be 0,
+//! BackPressure between 0 and 1000, UnknownPosition between 0 and 2,
+//! StepProgressInterval between 0 and 1000000, JumpCoalesceMs between 0 and 10000,
+//! MaxValueBytes between 0 and 100000000 and the remaining items, which are flags,
+//! either 0 or 1.
 
 use std::collections::HashMap;
+use std::io;
 
 /// Configuration
 ///
@@ -25,19 +78,48 @@ use std::collections::HashMap;
 ///
 /// Only config items that are meaningful and have defaults can be set and
 /// retreived.
+#[derive(Debug)]
 pub struct Config<'a> {
     config: HashMap<&'a str, i64>,
+    ranges: HashMap<&'a str, (i64, i64)>,
 }
 
+/// The longest any timeout can be set to, in seconds
+const MAX_TIMEOUT: i64 = 3600;
+
+/// Every config item with its default, minimum and maximum values
+const CONFIG_ITEMS: &[(&str, i64, i64, i64)] = &[
+    ("BackPressure", 20, 0, 1000),
+    ("UnknownPosition", 0, 0, 2),
+    ("ProcessSpawnTimeout", 10, 1, MAX_TIMEOUT),
+    ("BreakpointTimeout", 2, 1, MAX_TIMEOUT),
+    ("PrintVariableTimeout", 2, 1, MAX_TIMEOUT),
+    ("RawCommandTimeout", 5, 1, MAX_TIMEOUT),
+    ("StepTimeout", 2, 1, MAX_TIMEOUT),
+    ("CommandTimeout", 30, 1, MAX_TIMEOUT),
+    ("IncludeSourceLine", 0, 0, 1),
+    ("SourceMaps", 0, 0, 1),
+    ("StopAtMain", 1, 0, 1),
+    ("StopOnEntry", 1, 0, 1),
+    ("DebugProtocol", 0, 0, 1),
+    ("IdleTimeout", 0, 0, MAX_TIMEOUT),
+    ("HeartbeatInterval", 0, 0, MAX_TIMEOUT),
+    ("TypedValues", 0, 0, 1),
+    ("Demangle", 1, 0, 1),
+    ("StepProgressInterval", 100, 0, 1_000_000),
+    ("JumpCoalesceMs", 0, 0, 10_000),
+    ("MaxValueBytes", 65536, 0, 100_000_000),
+];
+
 impl<'a> Config<'a> {
     pub fn new() -> Self {
         let mut config = HashMap::new();
-        config.insert("BackPressure", 20);
-        config.insert("UnknownPosition", 0);
-        config.insert("ProcessSpawnTimeout", 10);
-        config.insert("BreakpointTimeout", 2);
-        config.insert("PrintVariableTimeout", 2);
-        Config { config }
+        let mut ranges = HashMap::new();
+        for &(key, default, min, max) in CONFIG_ITEMS {
+            config.insert(key, default);
+            ranges.insert(key, (min, max));
+        }
+        Config { config, ranges }
     }
 
     /// Get a config items value, either true or false
@@ -49,15 +131,49 @@ impl<'a> Config<'a> {
     }
 
     /// Set a config items value to an integer
-    pub fn set_config(&mut self, key: &str, value: i64) -> bool {
+    ///
+    /// Fails with `NotFound` if there's no such config item and `InvalidInput` if the
+    /// value is outside the range allowed for it.
+    pub fn set_config(&mut self, key: &str, value: i64) -> Result<(), io::Error> {
+        if let Some(&(min, max)) = self.ranges.get(key) {
+            if value < min || value > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Value {} for config key '{}' must be between {} and {}",
+                        value, key, min, max
+                    ),
+                ));
+            }
+        }
+
         match self.config.get_mut(key) {
             Some(s) => {
                 *s = value;
-                true
+                Ok(())
             }
-            None => false,
+            None => Err(self.unknown_key(key)),
         }
     }
+
+    /// All the config items that can be got and set, sorted
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.config.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Error for a config item that doesn't exist, listing the ones that do
+    pub fn unknown_key(&self, key: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "Unknown config key '{}', valid keys are {}",
+                key,
+                self.keys().join(", ")
+            ),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -66,7 +182,7 @@ mod tests {
     fn check_set_and_get_config_item() {
         let mut config = super::Config::new();
         assert_eq!(config.get_config("BackPressure"), Some(20));
-        assert_eq!(config.set_config("BackPressure", 0), true);
+        config.set_config("BackPressure", 0).unwrap();
         assert_eq!(config.get_config("BackPressure"), Some(0));
     }
 
@@ -79,6 +195,36 @@ mod tests {
     #[test]
     fn check_set_non_existent_config_item() {
         let mut config = super::Config::new();
-        assert_eq!(config.set_config("NotExists", 2), false);
+        let err = config.set_config("NotExists", 2).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert!(err
+            .to_string()
+            .starts_with("Unknown config key 'NotExists', valid keys are BackPressure, "));
+    }
+
+    #[test]
+    fn check_set_config_item_out_of_range() {
+        let mut config = super::Config::new();
+
+        let err = config.set_config("StepTimeout", 0).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "Value 0 for config key 'StepTimeout' must be between 1 and 3600"
+        );
+
+        let err = config.set_config("StepTimeout", 3601).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        let err = config.set_config("UnknownPosition", -1).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        assert_eq!(config.get_config("StepTimeout"), Some(2));
+        assert_eq!(config.get_config("UnknownPosition"), Some(0));
+
+        config.set_config("StepTimeout", 3600).unwrap();
+        assert_eq!(config.get_config("StepTimeout"), Some(3600));
+        config.set_config("UnknownPosition", 2).unwrap();
+        assert_eq!(config.get_config("UnknownPosition"), Some(2));
     }
 }
@@ -4,13 +4,26 @@
 //! and creates the main debugger objects.
 
 use std::fmt::Debug;
+use std::fs;
 use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 use crate::config::Config;
-use crate::util::{file_is_binary_executable, file_is_text};
+use crate::notifier::{
+    debugger_info, is_debugger_ready, log_msg, protocol_trace, watches_updated, LogLevel,
+};
+use crate::server::{error_response, ErrorCode};
+use crate::util::{
+    self, canonicalize_file_path, file_exists, file_is_binary_executable, file_is_text, typed_value,
+};
 
+use regex::Regex;
 use tokio::prelude::*;
+use tokio::sync::mpsc;
 
 mod lldb;
 mod node;
@@ -25,7 +38,7 @@ enum DebuggerType {
 }
 
 /// File location
-#[derive(Clone, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Hash)]
 pub struct FileLocation {
     name: String,
     line_num: u64,
@@ -35,10 +48,114 @@ impl FileLocation {
     pub fn new(name: String, line_num: u64) -> Self {
         FileLocation { name, line_num }
     }
+
+    /// Check whether two locations refer to the same line of the same file
+    ///
+    /// Debuggers don't always report the full path of a file so a file name matches a
+    /// path that ends in that file name.
+    pub fn matches(&self, other: &FileLocation) -> bool {
+        if self.line_num != other.line_num {
+            return false;
+        }
+
+        self.name == other.name
+            || self.name.ends_with(&format!("/{}", other.name))
+            || other.name.ends_with(&format!("/{}", self.name))
+    }
+}
+
+/// When a breakpoint with a hit condition should stop given the number of times it's been
+/// hit, hits are counted from 1
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+pub enum HitCondition {
+    AtLeast(u64),
+    Above(u64),
+    Equal(u64),
+    Multiple(u64),
+}
+
+impl HitCondition {
+    /// Parse a hit condition as sent in a request, one of `>=N`, `>N`, `==N` or `%N`
+    pub fn parse(s: &str) -> Option<Self> {
+        lazy_static! {
+            static ref RE_HIT_CONDITION: Regex = Regex::new("^(>=|>|==|%) *(\\d+)$").unwrap();
+        }
+
+        let cap = RE_HIT_CONDITION.captures(s.trim())?;
+        let n = cap[2].parse::<u64>().ok()?;
+
+        match &cap[1] {
+            ">=" => Some(HitCondition::AtLeast(n)),
+            ">" => Some(HitCondition::Above(n)),
+            "==" => Some(HitCondition::Equal(n)),
+            "%" if n > 0 => Some(HitCondition::Multiple(n)),
+            _ => None,
+        }
+    }
+
+    /// Whether a breakpoint should stop on the hit given
+    pub fn is_satisfied(&self, hits: u64) -> bool {
+        match *self {
+            HitCondition::AtLeast(n) => hits >= n,
+            HitCondition::Above(n) => hits > n,
+            HitCondition::Equal(n) => hits == n,
+            HitCondition::Multiple(n) => hits.is_multiple_of(n),
+        }
+    }
+}
+
+/// Printed by the debugger when a logpoint is hit, followed by the message
+pub const LOGPOINT_PREFIX: &str = "PADRE logpoint: ";
+
+/// A piece of a logpoint's message, either text or an expression in braces that's
+/// evaluated in the frame the logpoint's hit in
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogMessagePart {
+    Text(String),
+    Expression(String),
+}
+
+/// Split a logpoint's message into text and `{expr}` expressions, `{{` and `}}` are a
+/// brace in the text and a brace that isn't closed is just text
+pub fn parse_log_message(message: &str) -> Vec<LogMessagePart> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = message.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                text.push(c);
+            }
+            '{' => {
+                let rest: String = chars.clone().collect();
+                match rest.find('}') {
+                    Some(end) => {
+                        if !text.is_empty() {
+                            parts.push(LogMessagePart::Text(text.split_off(0)));
+                        }
+                        parts.push(LogMessagePart::Expression(rest[..end].to_string()));
+                        for _ in rest[..=end].chars() {
+                            chars.next();
+                        }
+                    }
+                    None => text.push(c),
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(LogMessagePart::Text(text));
+    }
+
+    parts
 }
 
 /// Variable name
-#[derive(Clone, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Hash)]
 pub struct Variable {
     name: String,
 }
@@ -49,35 +166,461 @@ impl Variable {
     }
 }
 
+/// A child of a compound value, such as a field of a struct or an element of an array
+///
+/// The reference is only given when the child is compound itself and can be used to get
+/// its children in turn.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VariableChild {
+    name: String,
+    #[serde(rename = "type")]
+    type_: String,
+    value: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+}
+
+impl VariableChild {
+    pub fn new(
+        name: String,
+        type_: String,
+        value: serde_json::Value,
+        reference: Option<String>,
+    ) -> Self {
+        VariableChild {
+            name,
+            type_,
+            value,
+            reference,
+        }
+    }
+}
+
+/// Where the program last stopped and why, if the debugger says
+///
+/// The column is left out when the debugger doesn't report it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StopLocation {
+    file: String,
+    line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<u64>,
+    reason: Option<String>,
+}
+
+impl StopLocation {
+    pub fn new(file: String, line: u64, reason: Option<String>) -> Self {
+        StopLocation {
+            file,
+            line,
+            column: None,
+            reason,
+        }
+    }
+
+    pub fn with_column(mut self, column: Option<u64>) -> Self {
+        self.column = column;
+        self
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+}
+
+/// When a watchpoint should trigger
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+pub enum WatchpointMode {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// A format to show a printed integer in as well as its usual value
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+pub enum ValueFormat {
+    Hex,
+    Dec,
+    Bin,
+    Char,
+}
+
+impl ValueFormat {
+    /// Parse a format as sent in a request, one of `hex`, `dec`, `bin` or `char`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hex" => Some(ValueFormat::Hex),
+            "dec" => Some(ValueFormat::Dec),
+            "bin" => Some(ValueFormat::Bin),
+            "char" => Some(ValueFormat::Char),
+            _ => None,
+        }
+    }
+
+    /// Format a value as printed by the debugger, None if it isn't an integer
+    ///
+    /// The value can be a JSON number or a string of a decimal or `0x` hex number, or a
+    /// character in single quotes as LLDB prints `char`s, e.g. `'a'`, which is taken as
+    /// its code.
+    pub fn format(&self, value: &serde_json::Value) -> Option<String> {
+        let n: i128 = match value {
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(n) => n as i128,
+                None => n.as_u64()? as i128,
+            },
+            serde_json::Value::String(s) => parse_integer(s)?,
+            _ => return None,
+        };
+
+        let sign = if n < 0 { "-" } else { "" };
+
+        match self {
+            ValueFormat::Hex => Some(format!("{}{:#x}", sign, n.abs())),
+            ValueFormat::Dec => Some(n.to_string()),
+            ValueFormat::Bin => Some(format!("{}{:#b}", sign, n.abs())),
+            ValueFormat::Char if (0..=u32::MAX as i128).contains(&n) => {
+                std::char::from_u32(n as u32).map(|c| c.to_string())
+            }
+            ValueFormat::Char => None,
+        }
+    }
+}
+
+/// Parse an integer printed by a debugger for `ValueFormat`
+fn parse_integer(s: &str) -> Option<i128> {
+    let s = s.trim();
+
+    let mut chars = s.chars();
+    if let (Some('\''), Some(c), Some('\''), None) =
+        (chars.next(), chars.next(), chars.next(), chars.next())
+    {
+        return Some(c as i128);
+    }
+
+    let (sign, digits) = match s.starts_with('-') {
+        true => (-1, &s[1..]),
+        false => (1, s),
+    };
+
+    let n = match digits.starts_with("0x") || digits.starts_with("0X") {
+        true => i128::from_str_radix(&digits[2..], 16).ok()?,
+        false => digits.parse::<i128>().ok()?,
+    };
+
+    Some(sign * n)
+}
+
+/// What the program being debugged is doing as reported in heartbeats
+///
+/// `Processing` means PADRE is in the middle of a debugger command.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgramStatus {
+    NotRunning,
+    Running,
+    Stopped,
+    Processing,
+}
+
+/// What a debugger supports
+///
+/// `commands` are the names of the commands supported as sent in requests.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Capabilities {
+    debugger: &'static str,
+    commands: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn new(debugger: &'static str, commands: Vec<&'static str>) -> Self {
+        Capabilities { debugger, commands }
+    }
+}
+
 /// All debugger commands
-#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub enum DebuggerCmd {
     V1(DebuggerCmdV1),
 }
 
 /// All V1 debugger commands
-#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub enum DebuggerCmdV1 {
     Run,
     Breakpoint(FileLocation),
-    StepIn,
-    StepOver,
+    HitConditionBreakpoint(FileLocation, HitCondition),
+    Logpoint(FileLocation, String),
+    DisableBreakpoint(FileLocation),
+    EnableBreakpoint(FileLocation),
+    ClearAllBreakpoints,
+    StepIn(u64, bool),
+    StepOver(u64, bool),
     Continue,
-    Print(Variable),
+    Interrupt,
+    Detach,
+    Print(Variable, Option<u64>, Option<ValueFormat>),
+    Assert(String),
+    GetFrameSource { before: u64, after: u64 },
+    StackWithLocals { max_frames: u64 },
+    Raw(String),
+    ListThreads,
+    SelectThread(u64),
+    AddWatch(String),
+    RemoveWatch(String),
+    ValidateBreakpoint(FileLocation),
+    Watchpoint(Variable, WatchpointMode),
+    CurrentLocation,
+    Finish,
+    ExceptionBreakpoint { caught: bool, uncaught: bool },
+    GetChildren { reference: String },
+    ReverseContinue,
+    ReverseStepOver,
+    SetCwd(String),
+    ListSources,
+    ListModules,
+    ReadSymbol(String),
+    TypeOf(String),
+    SetNextStatement(FileLocation),
+}
+
+/// Queue of commands for a debugger
+///
+/// Every command is given a sequence number when it's queued and isn't started until the
+/// command queued before it has finished. This means commands are run, and so answered,
+/// in the order they were submitted even when they come from different connections, for
+/// example a breakpoint set from one connection before a run from another will always be
+/// set before the program is run.
+///
+/// The command currently running can be cancelled, it's then answered with an
+/// `Interrupted` error straight away. Once the queue's shut down every command that hasn't
+/// started yet is answered with an `Interrupted` error too rather than being run.
+#[derive(Debug)]
+struct CommandQueue {
+    next_seq: u64,
+    last: Option<mpsc::Receiver<()>>,
+    in_flight: Arc<Mutex<Option<mpsc::Sender<()>>>>,
+    shut_down: Arc<AtomicBool>,
+}
+
+impl CommandQueue {
+    fn new() -> CommandQueue {
+        CommandQueue {
+            next_seq: 0,
+            last: None,
+            in_flight: Arc::new(Mutex::new(None)),
+            shut_down: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether a command is running
+    fn is_processing(&self) -> bool {
+        self.in_flight.lock().unwrap().is_some()
+    }
+
+    /// Cancel the command currently running, returns false if there isn't one
+    fn cancel(&mut self) -> bool {
+        match self.in_flight.lock().unwrap().take() {
+            Some(mut cancel_tx) => cancel_tx.try_send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Cancel the command currently running and stop running any more
+    fn shut_down(&mut self) {
+        self.shut_down.store(true, Ordering::SeqCst);
+        self.cancel();
+    }
+
+    /// Queue a command, `f` is called with the command's sequence number to start it once
+    /// every command before it has finished
+    fn queue<F>(
+        &mut self,
+        f: F,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>
+    where
+        F: FnOnce(u64) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>
+            + Send
+            + 'static,
+    {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        // Nothing is ever sent, the command before is finished when its sender is dropped
+        let (tx, rx) = mpsc::channel(1);
+
+        let previous: Box<dyn Future<Item = (), Error = ()> + Send> = match self.last.replace(rx) {
+            Some(rx) => Box::new(rx.into_future().then(|_| Ok(()))),
+            None => Box::new(future::ok(())),
+        };
+
+        let in_flight = self.in_flight.clone();
+        let shut_down = self.shut_down.clone();
+
+        let f = previous
+            .then(
+                move |_| -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+                    if shut_down.load(Ordering::SeqCst) {
+                        return Box::new(future::err(shutting_down_error()));
+                    }
+
+                    util::write_log(&format!("Starting command {}", seq));
+                    protocol_trace("status", &format!("Processing command {}", seq));
+
+                    let (cancel_tx, cancel_rx) = mpsc::channel(1);
+                    *in_flight.lock().unwrap() = Some(cancel_tx);
+
+                    // Checked first so a cancelled command isn't answered with whatever
+                    // interrupting it did to the debugger
+                    let cancelled = cancel_rx.take(1).into_future().then(|event| match event {
+                        Ok((Some(_), _)) => Box::new(future::err(cancelled_error()))
+                            as Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>,
+                        _ => Box::new(future::empty()),
+                    });
+
+                    Box::new(
+                        cancelled
+                            .select(f(seq))
+                            .map(|(resp, _)| resp)
+                            .map_err(|(e, _)| e)
+                            .then(move |resp| {
+                                in_flight.lock().unwrap().take();
+                                resp
+                            }),
+                    )
+                },
+            )
+            .then(move |resp| {
+                util::write_log(&format!("Finished command {}", seq));
+                protocol_trace("status", &format!("Listening after command {}", seq));
+                drop(tx);
+                resp
+            });
+
+        Box::new(f)
+    }
 }
 
 #[derive(Debug)]
 pub struct Debugger {
     debugger: Box<dyn DebuggerV1 + Send>,
+    setup_error: Option<String>,
+    // The version the debugger reports, if it's been found yet
+    debugger_version: Arc<Mutex<Option<String>>>,
+    watches: Vec<String>,
+    queue: CommandQueue,
 }
 
 impl Debugger {
     pub fn new(debugger: Box<dyn DebuggerV1 + Send>) -> Debugger {
-        Debugger { debugger }
+        Debugger {
+            debugger,
+            setup_error: None,
+            debugger_version: Arc::new(Mutex::new(None)),
+            watches: vec![],
+            queue: CommandQueue::new(),
+        }
+    }
+
+    /// Stop the debugger and exit PADRE with the exit code given
+    pub fn stop(&mut self, exit_code: i32) {
+        self.debugger.teardown(exit_code);
+    }
+
+    /// The exit code the program last exited with, if it has
+    pub fn exit_code(&self) -> Option<i64> {
+        self.debugger.exit_code()
+    }
+
+    /// Report the process id of the program being debugged, null if it isn't running
+    pub fn pid(&self) -> serde_json::Value {
+        serde_json::json!({"status":"OK","pid":self.debugger.pid()})
+    }
+
+    /// What the program is doing, `Processing` if it isn't running and a command is
+    pub fn status(&self) -> ProgramStatus {
+        match self.debugger.program_status() {
+            ProgramStatus::Running => ProgramStatus::Running,
+            _ if self.queue.is_processing() => ProgramStatus::Processing,
+            status => status,
+        }
+    }
+
+    /// Report what the program is doing for a status line, only from what's already known
+    /// so it doesn't wait on any command
+    pub fn state(&self) -> serde_json::Value {
+        state_response(
+            is_debugger_ready(),
+            self.debugger.program_status(),
+            self.debugger.pid(),
+            self.debugger.exit_code(),
+            self.debugger.location(),
+        )
+    }
+
+    /// Report the debugger type, the commands it supports, its version and the PADRE
+    /// version
+    pub fn capabilities(&self) -> serde_json::Value {
+        let mut capabilities = self.debugger.capabilities();
+
+        // Handled here for every debugger
+        capabilities
+            .commands
+            .extend(&["validateBreakpoint", "addWatch", "removeWatch"]);
+
+        serde_json::json!({
+            "status": "OK",
+            "debugger": capabilities.debugger,
+            "commands": capabilities.commands,
+            "debuggerVersion": *self.debugger_version.lock().unwrap(),
+            "version": env!("CARGO_PKG_VERSION"),
+        })
+    }
+
+    /// Cancel the command currently running by interrupting the program
+    ///
+    /// The cancelled command is answered with an error, the response says whether there
+    /// was a command to cancel.
+    pub fn cancel(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if !self.queue.cancel() {
+            return Box::new(future::lazy(|| {
+                Ok(serde_json::json!({"status":"OK","cancelled":false}))
+            }));
+        }
+
+        log_msg(LogLevel::INFO, "Cancelling command");
+
+        let f = self.debugger.interrupt(config).then(|resp| {
+            if let Err(e) = resp {
+                log_msg(
+                    LogLevel::WARN,
+                    &format!("Failed interrupting cancelled command: {}", e),
+                );
+            }
+            Ok(serde_json::json!({"status":"OK","cancelled":true}))
+        });
+
+        Box::new(f)
     }
 
-    pub fn stop(&mut self) {
-        self.debugger.teardown();
+    /// Stop running debugger commands ready for PADRE to exit
+    ///
+    /// The command running and any waiting to run are answered with an error straight
+    /// away.
+    pub fn shut_down(&mut self) {
+        log_msg(LogLevel::INFO, "Shutting down");
+        self.queue.shut_down();
     }
 
     pub fn handle_v1_cmd(
@@ -85,21 +628,226 @@ impl Debugger {
         cmd: &DebuggerCmdV1,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        // Validating a breakpoint never needs the debugger
+        if let DebuggerCmdV1::ValidateBreakpoint(fl) = cmd {
+            let resp = validate_breakpoint(fl);
+            return Box::new(future::lazy(move || Ok(resp)));
+        }
+
+        // If the debugger couldn't be setup report that for every command rather than
+        // trying to talk to a debugger that doesn't exist.
+        if let Some(msg) = &self.setup_error {
+            let msg = msg.clone();
+            return Box::new(future::lazy(move || {
+                Err(io::Error::new(io::ErrorKind::NotFound, msg))
+            }));
+        }
+
         match cmd {
             DebuggerCmdV1::Run => self.debugger.run(config),
             DebuggerCmdV1::Breakpoint(fl) => self.debugger.breakpoint(fl, config),
-            DebuggerCmdV1::StepIn => self.debugger.step_in(),
-            DebuggerCmdV1::StepOver => self.debugger.step_over(),
-            DebuggerCmdV1::Continue => self.debugger.continue_(),
-            DebuggerCmdV1::Print(v) => self.debugger.print(v, config),
+            DebuggerCmdV1::HitConditionBreakpoint(fl, hit_condition) => self
+                .debugger
+                .hit_condition_breakpoint(fl, *hit_condition, config),
+            DebuggerCmdV1::Logpoint(fl, message) => self.debugger.logpoint(fl, message, config),
+            DebuggerCmdV1::DisableBreakpoint(fl) => self.debugger.disable_breakpoint(fl, config),
+            DebuggerCmdV1::EnableBreakpoint(fl) => self.debugger.enable_breakpoint(fl, config),
+            DebuggerCmdV1::StepIn(count, report_each) => {
+                self.debugger.step_in(*count, *report_each, config)
+            }
+            DebuggerCmdV1::StepOver(count, report_each) => {
+                self.debugger.step_over(*count, *report_each, config)
+            }
+            DebuggerCmdV1::Continue => self.debugger.continue_(config),
+            DebuggerCmdV1::Finish => self.debugger.finish(config),
+            DebuggerCmdV1::ReverseContinue => self.debugger.reverse_continue(config),
+            DebuggerCmdV1::ReverseStepOver => self.debugger.reverse_step_over(config),
+            DebuggerCmdV1::Interrupt => self.debugger.interrupt(config),
+            DebuggerCmdV1::Detach => self.debugger.detach(config),
+            DebuggerCmdV1::Print(v, frame, None) => self.debugger.print(v, *frame, config),
+            DebuggerCmdV1::Print(v, frame, Some(format)) => {
+                let format = *format;
+                let f = self
+                    .debugger
+                    .print(v, *frame, config)
+                    .map(move |resp| formatted_print_response(resp, format));
+                Box::new(f)
+            }
+            DebuggerCmdV1::Assert(e) => self.debugger.assert(e, config),
+            DebuggerCmdV1::GetFrameSource { before, after } => {
+                self.debugger.frame_source(*before, *after, config)
+            }
+            DebuggerCmdV1::StackWithLocals { max_frames } => {
+                self.debugger.stack_with_locals(*max_frames, config)
+            }
+            DebuggerCmdV1::Raw(c) => self.debugger.raw(c, config),
+            DebuggerCmdV1::ListThreads => self.debugger.list_threads(config),
+            DebuggerCmdV1::SelectThread(t) => self.debugger.select_thread(*t, config),
+            DebuggerCmdV1::Watchpoint(v, mode) => self.debugger.watchpoint(v, *mode, config),
+            DebuggerCmdV1::CurrentLocation => self.debugger.current_location(config),
+            DebuggerCmdV1::ClearAllBreakpoints => self.debugger.clear_all_breakpoints(config),
+            DebuggerCmdV1::ExceptionBreakpoint { caught, uncaught } => self
+                .debugger
+                .exception_breakpoint(*caught, *uncaught, config),
+            DebuggerCmdV1::GetChildren { reference } => {
+                self.debugger.get_children(reference, config)
+            }
+            DebuggerCmdV1::ListSources => self.debugger.list_sources(config),
+            DebuggerCmdV1::ListModules => self.debugger.list_modules(config),
+            DebuggerCmdV1::ReadSymbol(name) => self.debugger.read_symbol(name, config),
+            DebuggerCmdV1::TypeOf(e) => self.debugger.type_of(e, config),
+            DebuggerCmdV1::SetNextStatement(fl) => self.debugger.set_next_statement(fl, config),
+            DebuggerCmdV1::SetCwd(dir) => {
+                if !Path::new(dir).is_dir() {
+                    let msg = format!("Directory {} doesn't exist", dir);
+                    return Box::new(future::lazy(move || {
+                        Err(io::Error::new(io::ErrorKind::NotFound, msg))
+                    }));
+                }
+                self.debugger.set_cwd(&canonicalize_file_path(dir), config)
+            }
+            DebuggerCmdV1::AddWatch(e) => {
+                if !self.watches.contains(e) {
+                    self.watches.push(e.clone());
+                }
+                Box::new(future::lazy(|| Ok(serde_json::json!({"status":"OK"}))))
+            }
+            DebuggerCmdV1::ValidateBreakpoint(_) => unreachable!(),
+            DebuggerCmdV1::RemoveWatch(e) => {
+                let resp = match self.watches.iter().position(|w| w == e) {
+                    Some(i) => {
+                        self.watches.remove(i);
+                        serde_json::json!({"status":"OK"})
+                    }
+                    None => {
+                        log_msg(LogLevel::WARN, &format!("No watch for {}", e));
+                        serde_json::json!({"status":"ERROR"})
+                    }
+                };
+                Box::new(future::lazy(move || Ok(resp)))
+            }
         }
     }
 }
 
+/// Check a breakpoint could be set without asking the debugger
+///
+/// The file must exist and the line must be within the file, the reason is given when
+/// it's not valid.
+fn validate_breakpoint(file_location: &FileLocation) -> serde_json::Value {
+    if !file_exists(&file_location.name) {
+        return serde_json::json!({
+            "status": "OK",
+            "valid": false,
+            "reason": format!("File {} doesn't exist", file_location.name),
+        });
+    }
+
+    let line_count = match fs::read(&file_location.name) {
+        Ok(contents) => String::from_utf8_lossy(&contents).lines().count() as u64,
+        Err(e) => {
+            return serde_json::json!({
+                "status": "OK",
+                "valid": false,
+                "reason": format!("Can't read file {}: {}", file_location.name, e),
+            });
+        }
+    };
+
+    if file_location.line_num == 0 || file_location.line_num > line_count {
+        return serde_json::json!({
+            "status": "OK",
+            "valid": false,
+            "reason": format!(
+                "Line {} is outside of file {} with {} lines",
+                file_location.line_num, file_location.name, line_count
+            ),
+        });
+    }
+
+    serde_json::json!({"status":"OK","valid":true})
+}
+
+/// Run something against the debugger once every command submitted before it has finished
+///
+/// All debugger commands should be run through this so they're run in the order they were
+/// submitted, see `CommandQueue`.
+pub fn run_in_order<F>(
+    debugger: Arc<Mutex<Debugger>>,
+    f: F,
+) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>
+where
+    F: FnOnce(&mut Debugger) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>
+        + Send
+        + 'static,
+{
+    let debugger_inner = debugger.clone();
+    let mut debugger = debugger.lock().unwrap();
+    debugger
+        .queue
+        .queue(move |_| f(&mut debugger_inner.lock().unwrap()))
+}
+
+/// Evaluate every watch expression and notify with the results
+///
+/// The expressions are printed one after the other the same way as the `print` command,
+/// an expression that can't be evaluated is reported as `<error>` without affecting
+/// the others.
+pub fn update_watches(
+    debugger: Arc<Mutex<Debugger>>,
+    config: Arc<Mutex<Config<'static>>>,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let watches = debugger.lock().unwrap().watches.clone();
+
+    if watches.is_empty() {
+        return Box::new(future::ok(()));
+    }
+
+    let f = stream::iter_ok(watches)
+        .and_then(move |expression| {
+            let variable = Variable::new(expression.clone());
+            let config = config.clone();
+            run_in_order(debugger.clone(), move |d| {
+                d.debugger.print(&variable, None, config)
+            })
+            .then(move |resp| {
+                let value = match resp {
+                    Ok(ref v) if v["status"] == "OK" => v["value"].clone(),
+                    _ => serde_json::json!("<error>"),
+                };
+                Ok((expression, value))
+            })
+        })
+        .collect()
+        .map(|values| watches_updated(&values));
+
+    Box::new(f)
+}
+
 /// Debugger trait that implements the basics
 pub trait DebuggerV1: Debug {
-    fn setup(&mut self);
-    fn teardown(&mut self);
+    fn setup(&mut self) -> Result<(), io::Error>;
+    /// Stop the debugger and exit PADRE with the exit code given
+    fn teardown(&mut self, exit_code: i32);
+    fn capabilities(&self) -> Capabilities;
+    /// The process id of the program being debugged if it's running
+    fn pid(&self) -> Option<u64>;
+    /// The exit code the program last exited with, None if it hasn't exited
+    fn exit_code(&self) -> Option<i64> {
+        None
+    }
+    /// Whether the program is running or stopped, should be quick as it's checked
+    /// for every heartbeat
+    fn program_status(&self) -> ProgramStatus {
+        match self.pid() {
+            Some(_) => ProgramStatus::Running,
+            None => ProgramStatus::NotRunning,
+        }
+    }
+    /// Where the program is stopped, if it is and the position is known
+    fn location(&self) -> Option<StopLocation> {
+        None
+    }
     fn run(
         &mut self,
         config: Arc<Mutex<Config>>,
@@ -109,14 +857,388 @@ pub trait DebuggerV1: Debug {
         file_location: &FileLocation,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
-    fn step_in(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
-    fn step_over(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
-    fn continue_(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    fn disable_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    fn enable_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    /// Step in `count` times, only the last stop is reported unless `report_each` is set
+    fn step_in(
+        &mut self,
+        count: u64,
+        report_each: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    /// Step over `count` times, only the last stop is reported unless `report_each` is set
+    fn step_over(
+        &mut self,
+        count: u64,
+        report_each: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    fn continue_(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    fn interrupt(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    /// Print a variable in the frame given, counting up from the current frame at 0, or
+    /// the current frame if none is given
     fn print(
         &mut self,
         variable: &Variable,
+        frame: Option<u64>,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    /// Evaluate an expression in the current frame, responding with whether it's true the
+    /// way the language would treat it in a condition as `passed` along with its `value`
+    fn assert(
+        &mut self,
+        _expression: &str,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Assertions")
+    }
+    /// Get the source around the line the program's stopped at from the debugger, for
+    /// when it can't be read locally, as `startLine` and `lines`
+    fn frame_source(
+        &mut self,
+        _before: u64,
+        _after: u64,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Fetching the source")
+    }
+    /// Get the backtrace of the current thread, up to `max_frames` frames, with the local
+    /// variables of every frame
+    fn stack_with_locals(
+        &mut self,
+        _max_frames: u64,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Getting the stack with locals")
+    }
+    fn current_location(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    fn raw(
+        &mut self,
+        command: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>;
+    fn list_threads(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Listing threads")
+    }
+    fn select_thread(
+        &mut self,
+        _thread: u64,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Selecting threads")
+    }
+    fn watchpoint(
+        &mut self,
+        _variable: &Variable,
+        _mode: WatchpointMode,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Watchpoints")
+    }
+    /// Set a breakpoint that only stops when its hit condition is satisfied
+    fn hit_condition_breakpoint(
+        &mut self,
+        _file_location: &FileLocation,
+        _hit_condition: HitCondition,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Breakpoint hit conditions")
+    }
+    /// Set a breakpoint that doesn't stop but notifies its message with the expressions
+    /// in it evaluated when it's hit
+    fn logpoint(
+        &mut self,
+        _file_location: &FileLocation,
+        _message: &str,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Logpoints")
+    }
+    /// Stop debugging the program but leave it running
+    fn detach(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Detaching")
+    }
+    /// Run to the end of the current function, responding with the value it returns as
+    /// `returnValue` if the debugger reports it
+    fn finish(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Finishing functions")
+    }
+    /// Run backwards to the previous breakpoint, only possible when the program is being
+    /// recorded
+    fn reverse_continue(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Reverse execution")
+    }
+    /// Step backwards over the previous line, only possible when the program is being
+    /// recorded
+    fn reverse_step_over(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Reverse execution")
+    }
+    fn exception_breakpoint(
+        &mut self,
+        _caught: bool,
+        _uncaught: bool,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Exception breakpoints")
+    }
+    /// Remove every breakpoint, logging how many there were
+    fn clear_all_breakpoints(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Clearing all breakpoints")
+    }
+    /// Set the directory the program is run in, only possible before it's run
+    fn set_cwd(
+        &mut self,
+        _dir: &str,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Setting the working directory")
+    }
+    /// Get the children of a compound value given the `reference` a print or an earlier
+    /// call to this returned for it
+    fn get_children(
+        &mut self,
+        _reference: &str,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Getting the children of variables")
+    }
+    /// List the source files the debugger knows about, e.g. to choose one to set a
+    /// breakpoint in
+    fn list_sources(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Listing source files")
+    }
+    /// List the modules loaded into the program, e.g. shared libraries, with the address
+    /// each is loaded at
+    fn list_modules(
+        &mut self,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Listing modules")
+    }
+    /// Get the type and value of a global or static variable, which can be done without
+    /// the program running
+    fn read_symbol(
+        &mut self,
+        _name: &str,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Reading symbols")
+    }
+    /// Get the type of an expression without its value, e.g. for hovering over it
+    fn type_of(
+        &mut self,
+        _expression: &str,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Getting the type of expressions")
+    }
+    /// Move where the program carries on from to another line without running anything
+    /// in between, responding with the new location
+    fn set_next_statement(
+        &mut self,
+        _file_location: &FileLocation,
+        _config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        not_supported("Setting the next statement")
+    }
+}
+
+/// Respond with an error for a command that a debugger doesn't support
+pub fn not_supported(
+    description: &str,
+) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+    log_msg(
+        LogLevel::WARN,
+        &format!("{} not supported by this debugger", description),
+    );
+
+    Box::new(future::lazy(move || {
+        Ok(error_response(ErrorCode::Unsupported))
+    }))
+}
+
+/// Respond to a command that needs the program to be running when it isn't
+pub fn not_running() -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+    log_msg(LogLevel::WARN, "No process running");
+
+    Box::new(future::lazy(move || {
+        Ok(error_response(ErrorCode::NotRunning))
+    }))
+}
+
+/// Error a command is answered with when it's cancelled
+pub fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Command cancelled")
+}
+
+/// Error for a command that can't be run because PADRE is shutting down
+fn shutting_down_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "PADRE is shutting down")
+}
+
+/// Read a listing of source lines from the debugger into the response to `frameSource`
+///
+/// Lines are numbered by the debugger with the current line and breakpoints marked, e.g.
+/// `-> 12   \tline` by LLDB and `  12 B->\tline` by pdb, anything else in the output is
+/// ignored as are lines outside of `start` to `end`.
+pub fn source_listing_response(output: &str, start: u64, end: u64) -> serde_json::Value {
+    lazy_static! {
+        static ref RE_SOURCE_LINE: Regex =
+            Regex::new("^(?:->)? *(\\d+) *B?(?:->)? *\t(.*)$").unwrap();
+    }
+
+    let lines: Vec<(u64, String)> = util::split_lines(output)
+        .iter()
+        .filter_map(|line| RE_SOURCE_LINE.captures(line))
+        .map(|cap| (cap[1].parse::<u64>().unwrap(), cap[2].to_string()))
+        .filter(|(line_num, _)| *line_num >= start && *line_num <= end)
+        .collect();
+
+    serde_json::json!({
+        "status": "OK",
+        "startLine": lines.first().map(|(line_num, _)| *line_num),
+        "lines": lines.into_iter().map(|(_, line)| line).collect::<Vec<String>>(),
+    })
+}
+
+/// Read the output of a debugger asked to evaluate an expression for `assert` into the
+/// response
+///
+/// The output should be `True` or `False` then a space and the value, anything else is
+/// taken to be the debugger's error evaluating the expression.
+pub fn assert_response(
+    expression: &str,
+    output: &str,
+    typed_values: bool,
+) -> Result<serde_json::Value, io::Error> {
+    let output = output.trim();
+    let mut parts = output.splitn(2, ' ');
+
+    let passed = match parts.next() {
+        Some("True") => true,
+        Some("False") => false,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't evaluate '{}': {}", expression, output),
+            ))
+        }
+    };
+
+    let value = parts.next().unwrap_or("");
+
+    Ok(serde_json::json!({
+        "status": "OK",
+        "passed": passed,
+        "value": match typed_values {
+            true => typed_value(value),
+            false => serde_json::json!(value),
+        },
+    }))
+}
+
+/// Add the value printed in the format asked for to the response to `print`
+///
+/// A value that can't be formatted, e.g. a struct, is logged and given as null.
+fn formatted_print_response(mut resp: serde_json::Value, format: ValueFormat) -> serde_json::Value {
+    if resp["status"] != "OK" {
+        return resp;
+    }
+
+    let formatted = format.format(&resp["value"]);
+    if formatted.is_none() {
+        log_msg(
+            LogLevel::WARN,
+            &format!(
+                "Can't format {} as {}",
+                resp["value"],
+                format!("{:?}", format).to_lowercase()
+            ),
+        );
+    }
+    resp["formatted"] = serde_json::json!(formatted);
+
+    resp
+}
+
+/// The response to `status`
+///
+/// The state is `starting` until the debugger's ready, then `not_running` until the
+/// program's run, `running` or `stopped` along with its pid, and `exited` with its exit
+/// code once it's exited. The location is given when stopped if it's known.
+fn state_response(
+    ready: bool,
+    status: ProgramStatus,
+    pid: Option<u64>,
+    exit_code: Option<i64>,
+    location: Option<StopLocation>,
+) -> serde_json::Value {
+    let state = match status {
+        ProgramStatus::Running | ProgramStatus::Processing => "running",
+        ProgramStatus::Stopped => "stopped",
+        ProgramStatus::NotRunning if exit_code.is_some() => "exited",
+        ProgramStatus::NotRunning if !ready => "starting",
+        ProgramStatus::NotRunning => "not_running",
+    };
+
+    let mut resp = serde_json::json!({"status":"OK","state":state});
+
+    match state {
+        "running" => resp["pid"] = serde_json::json!(pid),
+        "stopped" => {
+            resp["pid"] = serde_json::json!(pid);
+            if let Some(location) = location {
+                resp["location"] = serde_json::json!(location);
+            }
+        }
+        "exited" => resp["exitCode"] = serde_json::json!(exit_code),
+        _ => {}
+    }
+
+    resp
+}
+
+/// Error for trying to set the working directory once the program has been run
+pub fn cwd_after_run_error() -> io::Error {
+    io::Error::other("Can't set the working directory once the program has been run")
 }
 
 /// Get the debugger implementation
@@ -127,6 +1249,9 @@ pub fn get_debugger(
     debugger_cmd: Option<&str>,
     debugger_type: Option<&str>,
     run_cmd: Vec<String>,
+    prompt: Option<&str>,
+    init_commands: Vec<String>,
+    debugger_env: Vec<(String, String)>,
 ) -> Debugger {
     let debugger_type = match debugger_type {
         Some(s) => match s.to_ascii_lowercase().as_str() {
@@ -161,15 +1286,102 @@ pub fn get_debugger(
         },
     };
 
+    let prompt = prompt.map(|p| p.to_string());
+    let version_cmd = debugger_cmd.clone();
+
     let mut debugger: Box<dyn DebuggerV1 + Send> = match debugger_type {
-        DebuggerType::LLDB => Box::new(lldb::ImplDebugger::new(debugger_cmd, run_cmd)),
-        DebuggerType::Node => Box::new(node::ImplDebugger::new(debugger_cmd, run_cmd)),
-        DebuggerType::Python => Box::new(python::ImplDebugger::new(debugger_cmd, run_cmd)),
+        DebuggerType::LLDB => Box::new(lldb::ImplDebugger::new(
+            debugger_cmd,
+            run_cmd,
+            prompt,
+            init_commands,
+            debugger_env,
+        )),
+        DebuggerType::Node => {
+            if prompt.is_some() {
+                eprintln!("Node doesn't have a prompt, ignoring --prompt");
+            }
+            if !init_commands.is_empty() {
+                eprintln!("Node doesn't take debugger commands, ignoring --init-commands");
+            }
+            Box::new(node::ImplDebugger::new(debugger_cmd, run_cmd, debugger_env))
+        }
+        DebuggerType::Python => Box::new(python::ImplDebugger::new(
+            debugger_cmd,
+            run_cmd,
+            prompt,
+            init_commands,
+            debugger_env,
+        )),
     };
 
-    debugger.setup();
+    let setup = debugger.setup();
+
+    let mut debugger = Debugger::new(debugger);
+
+    find_debugger_version(&version_cmd, debugger.debugger_version.clone());
+
+    if let Err(e) = setup {
+        let msg = format!("{}", e);
+        eprintln!("{}", msg);
+        log_msg(LogLevel::CRITICAL, &msg);
+        debugger.setup_error = Some(msg);
+    }
 
-    Debugger::new(debugger)
+    debugger
+}
+
+/// Find out the version of the debugger and notify it
+///
+/// The debugger is run with `--version` on a separate thread so as not to hold anything
+/// up, if that fails the version is just left unknown.
+fn find_debugger_version(debugger_cmd: &str, debugger_version: Arc<Mutex<Option<String>>>) {
+    let (tx, rx) = mpsc::channel(1);
+    let debugger_cmd = debugger_cmd.to_string();
+
+    thread::spawn(move || {
+        let _ = tx.send(get_debugger_version(&debugger_cmd)).wait();
+    });
+
+    tokio::spawn(rx.take(1).map_err(|_| {}).for_each(move |version| {
+        match version {
+            Some(version) => {
+                debugger_info(&version);
+                *debugger_version.lock().unwrap() = Some(version);
+            }
+            None => log_msg(LogLevel::INFO, "Can't find the version of the debugger"),
+        }
+        Ok(())
+    }));
+}
+
+/// The first line the debugger prints when run with `--version`, some versions of Python
+/// print it to stderr
+fn get_debugger_version(debugger_cmd: &str) -> Option<String> {
+    let output = Command::new(debugger_cmd)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = match output.stdout.is_empty() {
+        true => output.stderr,
+        false => output.stdout,
+    };
+
+    let output = String::from_utf8(output).ok()?;
+
+    output
+        .lines()
+        .map(|l| l.trim())
+        .find(|l| !l.is_empty())
+        .map(|l| l.to_string())
 }
 
 /// Guesses the debugger type
@@ -219,3 +1431,342 @@ fn is_python(cmd: &str) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::{
+        assert_response, source_listing_response, state_response, CommandQueue, FileLocation,
+        HitCondition, LogMessagePart, ProgramStatus, StopLocation, ValueFormat,
+    };
+
+    use tokio::prelude::*;
+    use tokio::timer::Delay;
+
+    #[test]
+    fn check_hit_conditions() {
+        assert_eq!(HitCondition::parse(">=5"), Some(HitCondition::AtLeast(5)));
+        assert_eq!(HitCondition::parse("> 2"), Some(HitCondition::Above(2)));
+        assert_eq!(HitCondition::parse("==3"), Some(HitCondition::Equal(3)));
+        assert_eq!(HitCondition::parse("%3"), Some(HitCondition::Multiple(3)));
+        assert_eq!(HitCondition::parse("%0"), None);
+        assert_eq!(HitCondition::parse("<5"), None);
+        assert_eq!(HitCondition::parse(">=five"), None);
+
+        let hits_stopped = |hit_condition: HitCondition| {
+            (1..10)
+                .filter(|hits| hit_condition.is_satisfied(*hits))
+                .collect::<Vec<u64>>()
+        };
+
+        assert_eq!(hits_stopped(HitCondition::AtLeast(7)), vec![7, 8, 9]);
+        assert_eq!(hits_stopped(HitCondition::Above(7)), vec![8, 9]);
+        assert_eq!(hits_stopped(HitCondition::Equal(7)), vec![7]);
+        assert_eq!(hits_stopped(HitCondition::Multiple(3)), vec![3, 6, 9]);
+    }
+
+    #[test]
+    fn check_assert_response() {
+        assert_eq!(
+            assert_response("a == 1", "True 1\n", false).unwrap(),
+            serde_json::json!({"status":"OK","passed":true,"value":"1"})
+        );
+        assert_eq!(
+            assert_response("s", "False ", false).unwrap(),
+            serde_json::json!({"status":"OK","passed":false,"value":""})
+        );
+        assert_eq!(
+            assert_response("a + 1", "True 2", true).unwrap(),
+            serde_json::json!({"status":"OK","passed":true,"value":2})
+        );
+
+        let err =
+            assert_response("b", "*** NameError: name 'b' is not defined", false).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "Can't evaluate 'b': *** NameError: name 'b' is not defined"
+        );
+    }
+
+    #[test]
+    fn check_source_listing_response() {
+        let lldb_output = "   1   \tint main() {\n\
+                           -> 2   \t    int a = 1;\n\
+                           \x20      \t        ^\n\
+                           \x20  3   \t\n\
+                           \x20  4   \t    return a;\n";
+        assert_eq!(
+            source_listing_response(lldb_output, 1, 3),
+            serde_json::json!({
+                "status": "OK",
+                "startLine": 1,
+                "lines": ["int main() {", "    int a = 1;", ""],
+            })
+        );
+
+        let pdb_output = "  1  \tdef g(y):\n  2 B->\t    z = y + 1\n  3  \t    return z\n";
+        assert_eq!(
+            source_listing_response(pdb_output, 2, 7),
+            serde_json::json!({
+                "status": "OK",
+                "startLine": 2,
+                "lines": ["    z = y + 1", "    return z"],
+            })
+        );
+
+        assert_eq!(
+            source_listing_response("*** Not stopped\n", 1, 3),
+            serde_json::json!({"status":"OK","startLine":null,"lines":[]})
+        );
+    }
+
+    #[test]
+    fn check_value_format() {
+        assert_eq!(ValueFormat::parse("hex"), Some(ValueFormat::Hex));
+        assert_eq!(ValueFormat::parse("oct"), None);
+
+        let n = serde_json::json!(42);
+        assert_eq!(ValueFormat::Hex.format(&n), Some("0x2a".to_string()));
+        assert_eq!(ValueFormat::Dec.format(&n), Some("42".to_string()));
+        assert_eq!(ValueFormat::Bin.format(&n), Some("0b101010".to_string()));
+        assert_eq!(ValueFormat::Char.format(&n), Some("*".to_string()));
+
+        assert_eq!(
+            ValueFormat::Hex.format(&serde_json::json!("-255")),
+            Some("-0xff".to_string())
+        );
+        assert_eq!(
+            ValueFormat::Dec.format(&serde_json::json!("0x00007ffeefbff5c8")),
+            Some("140732920755656".to_string())
+        );
+        assert_eq!(
+            ValueFormat::Hex.format(&serde_json::json!("'a'")),
+            Some("0x61".to_string())
+        );
+        assert_eq!(
+            ValueFormat::Dec.format(&serde_json::json!(u64::MAX)),
+            Some("18446744073709551615".to_string())
+        );
+
+        assert_eq!(ValueFormat::Hex.format(&serde_json::json!("1.5")), None);
+        assert_eq!(ValueFormat::Hex.format(&serde_json::json!({"x": 1})), None);
+        assert_eq!(ValueFormat::Char.format(&serde_json::json!(-1)), None);
+    }
+
+    #[test]
+    fn check_state_response() {
+        assert_eq!(
+            state_response(false, ProgramStatus::NotRunning, None, None, None),
+            serde_json::json!({"status":"OK","state":"starting"})
+        );
+        assert_eq!(
+            state_response(true, ProgramStatus::NotRunning, None, None, None),
+            serde_json::json!({"status":"OK","state":"not_running"})
+        );
+        assert_eq!(
+            state_response(true, ProgramStatus::Processing, Some(123), None, None),
+            serde_json::json!({"status":"OK","state":"running","pid":123})
+        );
+
+        let location = StopLocation::new("test.c".to_string(), 12, Some("breakpoint".to_string()));
+        assert_eq!(
+            state_response(
+                true,
+                ProgramStatus::Stopped,
+                Some(123),
+                Some(0),
+                Some(location)
+            ),
+            serde_json::json!({
+                "status": "OK",
+                "state": "stopped",
+                "pid": 123,
+                "location": {"file": "test.c", "line": 12, "reason": "breakpoint"},
+            })
+        );
+
+        assert_eq!(
+            state_response(true, ProgramStatus::NotRunning, None, Some(2), None),
+            serde_json::json!({"status":"OK","state":"exited","exitCode":2})
+        );
+    }
+
+    #[test]
+    fn check_parse_log_message() {
+        assert_eq!(
+            super::parse_log_message("x is {x}, {{y}} is {y + 1}"),
+            vec![
+                LogMessagePart::Text("x is ".to_string()),
+                LogMessagePart::Expression("x".to_string()),
+                LogMessagePart::Text(", {y} is ".to_string()),
+                LogMessagePart::Expression("y + 1".to_string()),
+            ]
+        );
+        assert_eq!(
+            super::parse_log_message("unclosed {x"),
+            vec![LogMessagePart::Text("unclosed {x".to_string())]
+        );
+        assert_eq!(super::parse_log_message(""), vec![]);
+    }
+
+    #[test]
+    fn check_file_location_matches() {
+        let fl = FileLocation::new("/home/me/test.c".to_string(), 12);
+        assert!(fl.matches(&FileLocation::new("/home/me/test.c".to_string(), 12)));
+        assert!(fl.matches(&FileLocation::new("test.c".to_string(), 12)));
+        assert!(FileLocation::new("test.c".to_string(), 12).matches(&fl));
+    }
+
+    #[test]
+    fn check_file_location_doesnt_match() {
+        let fl = FileLocation::new("/home/me/test.c".to_string(), 12);
+        assert!(!fl.matches(&FileLocation::new("/home/me/test.c".to_string(), 13)));
+        assert!(!fl.matches(&FileLocation::new("est.c".to_string(), 12)));
+        assert!(!fl.matches(&FileLocation::new("/home/me/other.c".to_string(), 12)));
+    }
+
+    #[test]
+    fn check_validate_breakpoint() {
+        let path = std::env::temp_dir().join(format!("padre_test_{}.c", std::process::id()));
+        std::fs::write(&path, "int main() {\n  return 0;\n}\n").unwrap();
+        let name = path.to_string_lossy().to_string();
+
+        let resp = super::validate_breakpoint(&FileLocation::new(name.clone(), 3));
+        assert_eq!(resp, serde_json::json!({"status":"OK","valid":true}));
+
+        let resp = super::validate_breakpoint(&FileLocation::new(name.clone(), 4));
+        assert_eq!(resp["valid"], false);
+        assert!(resp["reason"]
+            .as_str()
+            .unwrap()
+            .starts_with("Line 4 is outside"));
+
+        std::fs::remove_file(&path).unwrap();
+
+        let resp = super::validate_breakpoint(&FileLocation::new(name, 1));
+        assert_eq!(resp["valid"], false);
+        assert!(resp["reason"].as_str().unwrap().ends_with("doesn't exist"));
+    }
+
+    #[test]
+    fn check_commands_run_in_submission_order() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut queue = CommandQueue::new();
+        let mut futures = vec![];
+
+        // Earlier commands take longer so would finish last if they weren't queued
+        for i in 0..50u64 {
+            let events = events.clone();
+            let events_inner = events.clone();
+            let f = queue.queue(move |seq| {
+                events.lock().unwrap().push(("start", seq));
+                let delay = Delay::new(Instant::now() + Duration::from_millis(50 - i));
+                Box::new(delay.map_err(std::io::Error::other).map(move |_| {
+                    events_inner.lock().unwrap().push(("end", seq));
+                    serde_json::json!({ "seq": seq })
+                }))
+            });
+            futures.push(f);
+        }
+
+        let responses = Arc::new(Mutex::new(vec![]));
+        let responses_inner = responses.clone();
+
+        // Poll them all at once in reverse to interleave them as much as possible
+        futures.reverse();
+        tokio::run(
+            future::join_all(futures)
+                .map(move |resps| *responses_inner.lock().unwrap() = resps)
+                .map_err(|e| panic!("Command failed: {}", e)),
+        );
+
+        let mut expected = vec![];
+        for seq in 0..50u64 {
+            expected.push(("start", seq));
+            expected.push(("end", seq));
+        }
+        assert_eq!(*events.lock().unwrap(), expected);
+
+        let responses = responses.lock().unwrap();
+        for (i, resp) in responses.iter().rev().enumerate() {
+            assert_eq!(resp["seq"], i as u64);
+        }
+    }
+
+    #[test]
+    fn check_cancel_running_command() {
+        let queue = Arc::new(Mutex::new(CommandQueue::new()));
+        assert!(!queue.lock().unwrap().cancel());
+
+        let stuck = queue.lock().unwrap().queue(|_| Box::new(future::empty()));
+        let next = queue
+            .lock()
+            .unwrap()
+            .queue(|seq| Box::new(future::ok(serde_json::json!({ "seq": seq }))));
+
+        let queue_inner = queue.clone();
+        let cancel = Delay::new(Instant::now() + Duration::from_millis(20))
+            .map(move |_| assert!(queue_inner.lock().unwrap().cancel()))
+            .map_err(|e| panic!("Timer failed: {}", e));
+
+        let results = Arc::new(Mutex::new(vec![]));
+        let results_inner = results.clone();
+
+        tokio::run(
+            stuck
+                .then(move |resp| {
+                    results_inner.lock().unwrap().push(resp.unwrap_err().kind());
+                    next
+                })
+                .map(|resp| assert_eq!(resp["seq"], 1))
+                .map_err(|e| panic!("Command failed: {}", e))
+                .join(cancel)
+                .map(|_| {}),
+        );
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![std::io::ErrorKind::Interrupted]
+        );
+        assert!(!queue.lock().unwrap().cancel());
+    }
+
+    #[test]
+    fn check_commands_fail_once_shut_down() {
+        let queue = Arc::new(Mutex::new(CommandQueue::new()));
+
+        let stuck = queue.lock().unwrap().queue(|_| Box::new(future::empty()));
+        let next = queue
+            .lock()
+            .unwrap()
+            .queue(|seq| Box::new(future::ok(serde_json::json!({ "seq": seq }))));
+
+        let queue_inner = queue.clone();
+        let shut_down = Delay::new(Instant::now() + Duration::from_millis(20))
+            .map(move |_| queue_inner.lock().unwrap().shut_down())
+            .map_err(|e| panic!("Timer failed: {}", e));
+
+        let results = Arc::new(Mutex::new(vec![]));
+        let results_inner = results.clone();
+
+        tokio::run(
+            stuck
+                .then(|resp| next.then(move |next_resp| Ok((resp, next_resp))))
+                .map(move |(resp, next_resp)| {
+                    let mut results = results_inner.lock().unwrap();
+                    results.push(resp.unwrap_err().to_string());
+                    results.push(next_resp.unwrap_err().to_string());
+                })
+                .join(shut_down)
+                .map(|_| {}),
+        );
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec!["Command cancelled", "PADRE is shutting down"]
+        );
+    }
+}
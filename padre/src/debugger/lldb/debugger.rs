@@ -8,24 +8,51 @@ use std::process::exit;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use super::process::{Event, LLDBProcess, Listener};
+use super::process::{
+    parse_breakpoints_deleted, parse_modules, parse_sources, parse_stack, parse_thread_list,
+    parse_variable_children, quote_arg, Event, LLDBProcess, Listener,
+};
 use crate::config::Config;
-use crate::debugger::{DebuggerV1, FileLocation, Variable};
-use crate::notifier::{log_msg, LogLevel};
+use crate::debugger::{
+    assert_response, cwd_after_run_error, not_running, parse_log_message, source_listing_response,
+    Capabilities, DebuggerV1, FileLocation, HitCondition, LogMessagePart, ProgramStatus,
+    StopLocation, Variable, WatchpointMode, LOGPOINT_PREFIX,
+};
+use crate::notifier::{breakpoint_pending, debugger_ready, log_msg, step_progress, LogLevel};
+use crate::server::{error_response, ErrorCode};
+use crate::util::{truncate_value, typed_value};
 
 use bytes::Bytes;
+use regex::Regex;
 use tokio::prelude::*;
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
 pub struct ImplDebugger {
     process: Arc<Mutex<LLDBProcess>>,
+    exception_breakpoint: Arc<Mutex<Option<u64>>>,
+    cwd: Option<String>,
+    init_commands: Vec<String>,
 }
 
 impl ImplDebugger {
-    pub fn new(debugger_cmd: String, run_cmd: Vec<String>) -> ImplDebugger {
+    pub fn new(
+        debugger_cmd: String,
+        run_cmd: Vec<String>,
+        prompt: Option<String>,
+        init_commands: Vec<String>,
+        debugger_env: Vec<(String, String)>,
+    ) -> ImplDebugger {
+        let process = LLDBProcess::new(debugger_cmd, run_cmd, debugger_env);
+        if let Some(prompt) = prompt {
+            process.set_prompt(prompt);
+        }
+
         ImplDebugger {
-            process: Arc::new(Mutex::new(LLDBProcess::new(debugger_cmd, run_cmd))),
+            process: Arc::new(Mutex::new(process)),
+            exception_breakpoint: Arc::new(Mutex::new(None)),
+            cwd: None,
+            init_commands,
         }
     }
 }
@@ -34,7 +61,7 @@ impl DebuggerV1 for ImplDebugger {
     /// Perform any initial setup including starting LLDB and setting up the stdio analyser stuff
     /// - startup lldb and setup the stdio analyser
     /// - perform initial setup so we can analyse LLDB properly
-    fn setup(&mut self) {
+    fn setup(&mut self) -> Result<(), io::Error> {
         let (tx, rx) = mpsc::channel(1);
 
         self.process
@@ -43,15 +70,30 @@ impl DebuggerV1 for ImplDebugger {
             .add_listener(Listener::LLDBLaunched, tx);
 
         let process = self.process.clone();
+        let init_commands = self.init_commands.clone();
 
         tokio::spawn(
             rx.take(1)
                 .for_each(move |event| {
                     match event {
                         Event::LLDBLaunched => {
-                            process.lock().unwrap().write_stdin(Bytes::from(&b"settings set stop-line-count-after 0\n"[..]));
-                            process.lock().unwrap().write_stdin(Bytes::from(&b"settings set stop-line-count-before 0\n"[..]));
-                            process.lock().unwrap().write_stdin(Bytes::from(&b"settings set frame-format frame #${frame.index}{ at ${line.file.fullpath}:${line.number}}\\n\n"[..]));
+                            let settings = vec![
+                                "settings set stop-line-count-after 0".to_string(),
+                                "settings set stop-line-count-before 0".to_string(),
+                                "settings set frame-format frame #${frame.index}{ at ${line.file.fullpath}:${line.number}{:${line.column}}}\\n".to_string(),
+                                "settings set thread-format thread #${thread.index}: tid = ${thread.id}{, name = '${thread.name}'}{, stop reason = ${thread.stop-reason}}{ at ${line.file.fullpath}:${line.number}}\\n".to_string(),
+                            ];
+                            let process = process.clone();
+                            tokio::spawn(
+                                run_init_commands(process.clone(), settings, init_commands.clone()).map(
+                                    move |_| {
+                                        // Anything sent before now, e.g. breakpoints set
+                                        // straight away, goes after the setup
+                                        process.lock().unwrap().started(vec![]);
+                                        debugger_ready();
+                                    },
+                                ),
+                            );
                         }
                         _ => unreachable!()
                     }
@@ -62,12 +104,71 @@ impl DebuggerV1 for ImplDebugger {
                 })
         );
 
-        self.process.lock().unwrap().setup();
+        self.process.lock().unwrap().setup()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new(
+            "lldb",
+            vec![
+                "run",
+                "breakpoint",
+                "disableBreakpoint",
+                "enableBreakpoint",
+                "clearAllBreakpoints",
+                "stepIn",
+                "stepOver",
+                "continue",
+                "interrupt",
+                "detach",
+                "finish",
+                "reverseContinue",
+                "reverseStepOver",
+                "print",
+                "assert",
+                "getChildren",
+                "listSources",
+                "listModules",
+                "readSymbol",
+                "typeOf",
+                "frameSource",
+                "stackWithLocals",
+                "raw",
+                "currentLocation",
+                "setNextStatement",
+                "threads",
+                "selectThread",
+                "watch",
+                "exceptionBreakpoint",
+                "setCwd",
+            ],
+        )
+    }
+
+    fn pid(&self) -> Option<u64> {
+        self.process.lock().unwrap().get_pid()
+    }
+
+    fn program_status(&self) -> ProgramStatus {
+        let process = self.process.lock().unwrap();
+        match process.get_pid() {
+            None => ProgramStatus::NotRunning,
+            Some(_) if process.is_stopped() => ProgramStatus::Stopped,
+            Some(_) => ProgramStatus::Running,
+        }
+    }
+
+    fn location(&self) -> Option<StopLocation> {
+        self.process.lock().unwrap().get_location()
     }
 
-    fn teardown(&mut self) {
+    fn teardown(&mut self, exit_code: i32) {
         self.process.lock().unwrap().teardown();
-        exit(0);
+        exit(exit_code);
+    }
+
+    fn exit_code(&self) -> Option<i64> {
+        self.process.lock().unwrap().get_exit_code()
     }
 
     fn run(
@@ -76,24 +177,47 @@ impl DebuggerV1 for ImplDebugger {
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
         log_msg(LogLevel::INFO, "Launching process");
 
+        let stop_at_main = {
+            let config = config.lock().unwrap();
+            config.get_config("StopAtMain") == Some(1)
+                && config.get_config("StopOnEntry") == Some(1)
+        };
+
         let (tx, rx) = mpsc::channel(1);
 
-        self.process
-            .lock()
-            .unwrap()
-            .add_listener(Listener::Breakpoint, tx);
+        // When not stopping at main the sender is dropped so we go straight on to launching
+        if stop_at_main {
+            self.process
+                .lock()
+                .unwrap()
+                .add_listener(Listener::Breakpoint, tx);
+        }
 
         let process = self.process.clone();
+        let process_2 = self.process.clone();
+
+        let launch_stmt = match &self.cwd {
+            Some(dir) => format!("process launch --working-dir {}\n", quote_arg(dir)),
+            None => "process launch\n".to_string(),
+        };
 
         let f = rx
             .take(1)
             .into_future()
             .and_then(move |lldb_output| {
-                let lldb_output = lldb_output.0.unwrap();
-
-                match lldb_output {
-                    Event::BreakpointSet(_) | Event::BreakpointMultiple => {}
-                    _ => {
+                // If LLDB has terminated launching the process will fail below
+                match lldb_output.0 {
+                    Some(Event::BreakpointSet(_)) | Some(Event::BreakpointMultiple) => {
+                        process_2.lock().unwrap().expect_entry_stop();
+                    }
+                    None => {}
+                    Some(Event::BreakpointPending) => {
+                        log_msg(
+                            LogLevel::INFO,
+                            "No main function found, the program won't stop when it starts",
+                        );
+                    }
+                    Some(lldb_output) => {
                         panic!("Don't understand output {:?}", lldb_output);
                     }
                 };
@@ -111,7 +235,7 @@ impl DebuggerV1 for ImplDebugger {
                 process
                     .lock()
                     .unwrap()
-                    .write_stdin(Bytes::from("process launch\n"));
+                    .write_stdin(Bytes::from(launch_stmt));
 
                 rx.take(1).into_future()
             })
@@ -123,122 +247,305 @@ impl DebuggerV1 for ImplDebugger {
                     .unwrap() as u64,
                 0,
             ))
-            .map(move |event| match event.0.unwrap() {
-                Event::ProcessLaunched(pid) => {
-                    serde_json::json!({"status":"OK","pid":pid.to_string()})
+            .then(move |event| match event {
+                Ok((Some(Event::ProcessLaunched(pid)), _)) => {
+                    Ok(serde_json::json!({"status":"OK","pid":pid}))
+                }
+                Ok((None, _)) => Err(lldb_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out spawning process",
+                    ))
                 }
-                _ => unreachable!(),
-            })
-            .map_err(|e| {
-                eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out spawning process")
             });
 
-        let stmt = "breakpoint set --name main\n";
+        if stop_at_main {
+            let stmt = "breakpoint set --name main\n";
 
-        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+            self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+        }
 
         Box::new(f)
     }
 
+    fn set_cwd(
+        &mut self,
+        dir: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let resp = if self.process.lock().unwrap().is_process_running() {
+            Err(cwd_after_run_error())
+        } else {
+            self.cwd = Some(dir.to_string());
+            Ok(serde_json::json!({"status":"OK"}))
+        };
+        Box::new(future::lazy(move || resp))
+    }
+
+    /// A breakpoint set without a hit condition replaces one with a hit condition
     fn breakpoint(
         &mut self,
         file_location: &FileLocation,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        log_msg(
-            LogLevel::INFO,
-            &format!(
-                "Setting breakpoint in file {} at line number {}",
-                file_location.name, file_location.line_num
-            ),
+        self.process
+            .lock()
+            .unwrap()
+            .remove_hit_condition(file_location);
+
+        self.set_breakpoint(file_location, "", config)
+    }
+
+    /// The breakpoint continues automatically after running a script that prints the
+    /// message, which is spotted in LLDB's output and notified
+    fn logpoint(
+        &mut self,
+        file_location: &FileLocation,
+        message: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let args = format!(
+            " --auto-continue true --command {}",
+            quote_arg(&log_cmd(message))
         );
 
-        let (tx, rx) = mpsc::channel(1);
+        self.process
+            .lock()
+            .unwrap()
+            .remove_hit_condition(file_location);
+
+        self.set_breakpoint(file_location, &args, config)
+    }
 
+    /// LLDB's own conditions can't see how many times a breakpoint has been hit so hits are
+    /// counted as LLDB stops and it's continued straight away until the condition is
+    /// satisfied
+    fn hit_condition_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        hit_condition: HitCondition,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
         self.process
             .lock()
             .unwrap()
-            .add_listener(Listener::Breakpoint, tx);
+            .add_hit_condition(file_location.clone(), hit_condition);
 
-        let f = rx
-            .take(1)
-            .into_future()
-            .timeout(Duration::new(
-                config
-                    .lock()
-                    .unwrap()
-                    .get_config("BreakpointTimeout")
-                    .unwrap() as u64,
-                0,
-            ))
-            .map(move |event| match event.0.unwrap() {
-                Event::BreakpointSet(_) => serde_json::json!({"status":"OK"}),
-                Event::BreakpointPending => serde_json::json!({"status":"PENDING"}),
-                Event::BreakpointMultiple => serde_json::json!({"status":"OK"}),
-                _ => unreachable!(),
-            })
-            .map_err(|e| {
-                eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out setting breakpoint")
-            });
+        self.set_breakpoint(file_location, "", config)
+    }
 
-        let stmt = format!(
-            "breakpoint set --file {} --line {}\n",
-            file_location.name, file_location.line_num
-        );
+    fn disable_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.toggle_breakpoint(file_location, "disable")
+    }
 
-        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+    fn enable_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.toggle_breakpoint(file_location, "enable")
+    }
+
+    /// Deleting every breakpoint includes the exception breakpoint
+    fn clear_all_breakpoints(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("BreakpointTimeout")
+            .unwrap() as u64;
+        let process = self.process.clone();
+        let exception_breakpoint = self.exception_breakpoint.clone();
+
+        let f = self
+            .raw_command("breakpoint delete --force", timeout)
+            .map(move |output| match parse_breakpoints_deleted(&output) {
+                Some(n) => {
+                    process.lock().unwrap().clear_breakpoints();
+                    *exception_breakpoint.lock().unwrap() = None;
+                    log_msg(LogLevel::INFO, &format!("Removed {} breakpoints", n));
+                    serde_json::json!({"status":"OK"})
+                }
+                None => {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't remove breakpoints: {}", output.trim()),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                }
+            });
 
         Box::new(f)
     }
 
-    fn step_in(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        self.step("step-in")
+    fn step_in(
+        &mut self,
+        count: u64,
+        report_each: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("thread step-in", count, report_each, config)
+    }
+
+    fn step_over(
+        &mut self,
+        count: u64,
+        report_each: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("thread step-over", count, report_each, config)
+    }
+
+    fn continue_(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("thread continue", 1, true, config)
+    }
+
+    fn interrupt(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("process interrupt", 1, true, config)
+    }
+
+    fn finish(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let process = self.process.clone();
+
+        let f = self
+            .step("thread step-out", 1, true, config)
+            .map(move |mut resp| {
+                if resp["terminated"].is_null() {
+                    resp["returnValue"] =
+                        serde_json::json!(process.lock().unwrap().get_return_value());
+                }
+                resp
+            });
+
+        Box::new(f)
     }
 
-    fn step_over(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        self.step("step-over")
+    fn reverse_continue(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.reverse_step("process continue --reverse", config)
     }
 
-    fn continue_(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        self.step("continue")
+    fn reverse_step_over(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.reverse_step("thread step-over --reverse", config)
     }
 
+    /// Before the program's running only global variables can be printed, they're read
+    /// from the program's file
     fn print(
         &mut self,
         variable: &Variable,
+        frame: Option<u64>,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        match self.check_process() {
-            Some(f) => return f,
-            _ => {}
+        let running = self.process.lock().unwrap().is_process_running();
+        if !running && frame.is_some() {
+            return not_running();
         }
 
-        let (tx, rx) = mpsc::channel(1);
+        let typed_values = config.lock().unwrap().get_config("TypedValues") == Some(1);
+        let max_bytes = config.lock().unwrap().get_config("MaxValueBytes").unwrap() as usize;
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
 
-        self.process
+        let stmt = match frame {
+            Some(frame) => frame_variable_cmd(&variable.name, frame),
+            None if !running => format!("target variable {}\n", variable.name),
+            None => format!("frame variable {}\n", variable.name),
+        };
+
+        let f = self
+            .variable_command(stmt, timeout)
+            .map(move |event| match event {
+                Event::PrintVariable(variable, value) => {
+                    let printed = truncate_value(value.value(), max_bytes);
+                    let mut resp = serde_json::json!({
+                        "status": "OK",
+                        "variable": variable.name,
+                        "value": match typed_values {
+                            true => typed_value(&printed),
+                            false => serde_json::json!(printed),
+                        },
+                        "type": value.type_()
+                    });
+                    // Structs and arrays can be expanded with `getChildren`
+                    if value.value().starts_with("{") {
+                        resp["reference"] = serde_json::json!(variable.name);
+                    }
+                    resp
+                }
+                Event::VariableNotFound(variable) if !running => {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!(
+                            "No global variable '{}', only globals can be printed before the program's running",
+                            variable.name
+                        ),
+                    );
+                    error_response(ErrorCode::NotRunning)
+                }
+                Event::VariableNotFound(variable) => {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("variable '{}' doesn't exist here", variable.name),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                }
+                _ => unreachable!(),
+            });
+
+        Box::new(f)
+    }
+
+    /// Uses `frame variable` so that the type's parsed the same as when printing, which
+    /// means the expression has to be a variable path such as `a.b[1]` or `*p`
+    fn type_of(
+        &mut self,
+        expression: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config
             .lock()
             .unwrap()
-            .add_listener(Listener::PrintVariable, tx);
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
 
-        let f = rx
-            .take(1)
-            .into_future()
-            .timeout(Duration::new(
-                config
-                    .lock()
-                    .unwrap()
-                    .get_config("PrintVariableTimeout")
-                    .unwrap() as u64,
-                0,
-            ))
-            .map(move |event| match event.0.unwrap() {
+        let f = self
+            .variable_command(format!("frame variable {}\n", expression), timeout)
+            .map(move |event| match event {
                 Event::PrintVariable(variable, value) => serde_json::json!({
                     "status": "OK",
-                    "variable": variable.name,
-                    "value": value.value(),
-                    "type": value.type_()
+                    "expression": variable.name,
+                    "type": value.type_(),
                 }),
                 Event::VariableNotFound(variable) => {
                     log_msg(
@@ -248,56 +555,973 @@ impl DebuggerV1 for ImplDebugger {
                     serde_json::json!({"status":"ERROR"})
                 }
                 _ => unreachable!(),
-            })
-            .map_err(|e| {
-                eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out printing variable")
             });
 
-        let stmt = format!("frame variable {}\n", variable.name);
+        Box::new(f)
+    }
+
+    /// Uses `target variable` so globals and statics can be read without the program
+    /// running, unlike `print` it never looks at the variables of a frame
+    fn read_symbol(
+        &mut self,
+        name: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let typed_values = config.lock().unwrap().get_config("TypedValues") == Some(1);
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
 
-        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+        let f = self
+            .variable_command(format!("target variable {}\n", name), timeout)
+            .map(move |event| match event {
+                Event::PrintVariable(variable, value) => serde_json::json!({
+                    "status": "OK",
+                    "name": variable.name,
+                    "type": value.type_(),
+                    "value": match typed_values {
+                        true => typed_value(value.value()),
+                        false => serde_json::json!(value.value()),
+                    },
+                }),
+                Event::VariableNotFound(variable) => {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("No global or static variable '{}'", variable.name),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                }
+                _ => unreachable!(),
+            });
 
         Box::new(f)
     }
-}
 
-impl ImplDebugger {
-    fn step(
+    fn raw(
         &mut self,
-        kind: &str,
+        command: &str,
+        config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        match self.check_process() {
-            Some(f) => return f,
-            _ => {}
-        }
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("RawCommandTimeout")
+            .unwrap() as u64;
 
-        let stmt = format!("thread {}\n", kind);
+        let f = self.raw_command(command, timeout).map(|output| {
+            serde_json::json!({
+                "status": "OK",
+                "output": output,
+            })
+        });
 
-        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+        Box::new(f)
+    }
 
-        let f = future::lazy(move || {
-            let resp = serde_json::json!({"status":"OK"});
-            Ok(resp)
-        });
+    /// The reference is the path to the variable, e.g. `a.b[1]`
+    fn get_children(
+        &mut self,
+        reference: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
+        let reference = reference.to_string();
+
+        let f = self
+            .raw_command(&format!("frame variable -T -D 1 {}", reference), timeout)
+            .map(move |output| {
+                if output.contains("error: ") {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't get children of '{}': {}", reference, output.trim()),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                } else {
+                    serde_json::json!({
+                        "status": "OK",
+                        "children": parse_variable_children(&reference, &output),
+                    })
+                }
+            });
 
         Box::new(f)
     }
 
-    fn check_process(
+    /// The source files of every compile unit in the target, found with LLDB's Python
+    /// scripting as there's no LLDB command for this
+    fn list_sources(
         &mut self,
-    ) -> Option<Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>> {
-        match self.process.lock().unwrap().is_process_running() {
-            false => {
-                log_msg(LogLevel::WARN, "No process running");
-                let f = future::lazy(move || {
-                    let resp = serde_json::json!({"status":"ERROR"});
-                    Ok(resp)
-                });
-
-                Some(Box::new(f))
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+
+        let f = self.raw_command(LIST_SOURCES_CMD, timeout).map(|output| {
+            if output.contains("error: ") {
+                log_msg(
+                    LogLevel::WARN,
+                    &format!("Can't list source files: {}", output.trim()),
+                );
+                serde_json::json!({"status":"ERROR"})
+            } else {
+                serde_json::json!({
+                    "status": "OK",
+                    "sources": parse_sources(&output),
+                })
             }
-            true => None,
-        }
+        });
+
+        Box::new(f)
     }
+
+    /// The load address is where the module's loaded in the process, before the program's
+    /// run it's the address in the file
+    fn list_modules(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+
+        let f = self
+            .raw_command("image list -u -h -f", timeout)
+            .map(|output| {
+                if output.contains("error: ") {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't list modules: {}", output.trim()),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                } else {
+                    serde_json::json!({
+                        "status": "OK",
+                        "modules": parse_modules(&output),
+                    })
+                }
+            });
+
+        Box::new(f)
+    }
+
+    /// The expression is true when it's nonzero, as C would treat it in an `if`
+    fn assert(
+        &mut self,
+        expression: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
+        let typed_values = config.lock().unwrap().get_config("TypedValues") == Some(1);
+
+        let expression = expression.to_string();
+
+        let f = self
+            .raw_command(&assert_cmd(&expression), timeout)
+            .and_then(move |output| assert_response(&expression, &output, typed_values));
+
+        Box::new(f)
+    }
+
+    /// LLDB says when it's detached from the process, anything else is an error
+    fn detach(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+
+        let f = self.raw_command("process detach", timeout).map(|output| {
+            if output.lines().any(|l| l.ends_with(" detached")) {
+                serde_json::json!({"status":"OK"})
+            } else {
+                log_msg(
+                    LogLevel::WARN,
+                    &format!("Can't detach from the process: {}", output.trim()),
+                );
+                serde_json::json!({"status":"ERROR"})
+            }
+        });
+
+        Box::new(f)
+    }
+
+    fn frame_source(
+        &mut self,
+        before: u64,
+        after: u64,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let location = match self.process.lock().unwrap().get_location() {
+            Some(location) => location,
+            None => return stopped_nowhere(),
+        };
+
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+
+        let start = location.line().saturating_sub(before).max(1);
+        let end = location.line() + after;
+
+        let cmd = format!(
+            "source list --file {} --line {} --count {}",
+            quote_arg(location.file()),
+            start,
+            end - start + 1
+        );
+
+        let f = self
+            .raw_command(&cmd, timeout)
+            .map(move |output| source_listing_response(&output, start, end));
+
+        Box::new(f)
+    }
+
+    /// The whole stack is found with LLDB's Python scripting in one go rather than
+    /// selecting each frame in turn
+    fn stack_with_locals(
+        &mut self,
+        max_frames: u64,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let (timeout, demangle) = {
+            let config = config.lock().unwrap();
+            (
+                config.get_config("CommandTimeout").unwrap() as u64,
+                config.get_config("Demangle") == Some(1),
+            )
+        };
+
+        let f = self
+            .raw_command(&stack_cmd(max_frames, demangle), timeout)
+            .map(|output| match parse_stack(&output) {
+                Some(frames) => serde_json::json!({"status":"OK","frames":frames}),
+                None => {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't get the stack: {}", output.trim()),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                }
+            });
+
+        Box::new(f)
+    }
+
+    fn list_threads(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+
+        let f = self.raw_command("thread list", timeout).map(|output| {
+            serde_json::json!({
+                "status": "OK",
+                "threads": parse_thread_list(&output),
+            })
+        });
+
+        Box::new(f)
+    }
+
+    fn select_thread(
+        &mut self,
+        thread: u64,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+
+        // The location of the thread is reported through the usual jump to position
+        // notification when LLDB prints the selected frame.
+        let f = self
+            .raw_command(&format!("thread select {}", thread), timeout)
+            .map(move |output| {
+                if output.contains("error: ") {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't select thread {}: {}", thread, output),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                } else {
+                    serde_json::json!({"status":"OK"})
+                }
+            });
+
+        Box::new(f)
+    }
+
+    fn current_location(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let location = self.process.lock().unwrap().get_location();
+
+        if location.is_some() || !self.process.lock().unwrap().is_process_running() {
+            return Box::new(future::lazy(move || {
+                Ok(serde_json::json!({"status":"OK","location":location}))
+            }));
+        }
+
+        // Printing the frame info reports the position as a jump to position and
+        // records it as we would when stopping
+        let timeout = config.lock().unwrap().get_config("CommandTimeout").unwrap() as u64;
+        let process = self.process.clone();
+
+        let f = self.raw_command("frame info", timeout).map(move |_| {
+            let location = process.lock().unwrap().get_location();
+            serde_json::json!({"status":"OK","location":location})
+        });
+
+        Box::new(f)
+    }
+
+    /// Uses `thread jump`, LLDB doesn't say where it's jumped to so the frame info is printed
+    /// afterwards to report the new position
+    fn set_next_statement(
+        &mut self,
+        file_location: &FileLocation,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config.lock().unwrap().get_config("StepTimeout").unwrap() as u64;
+        let process = self.process.clone();
+        let file_location = file_location.clone();
+
+        let f = self
+            .raw_command(
+                &format!(
+                    "thread jump --file {} --line {}",
+                    quote_arg(&file_location.name),
+                    file_location.line_num
+                ),
+                timeout,
+            )
+            .and_then(
+                move |output| -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+                    if let Some(error) = output.lines().find(|l| l.starts_with("error: ")) {
+                        log_msg(
+                            LogLevel::WARN,
+                            &format!(
+                                "Can't jump to {}:{}: {}",
+                                file_location.name,
+                                file_location.line_num,
+                                &error["error: ".len()..]
+                            ),
+                        );
+                        return Box::new(future::ok(serde_json::json!({"status":"ERROR"})));
+                    }
+
+                    let f = raw_output(&process, timeout);
+                    process
+                        .lock()
+                        .unwrap()
+                        .write_stdin(Bytes::from("frame info\n"));
+
+                    Box::new(f.map(move |_| {
+                        let location = process.lock().unwrap().get_location();
+                        serde_json::json!({"status":"OK","location":location})
+                    }))
+                },
+            );
+
+        Box::new(f)
+    }
+
+    /// LLDB can only stop when a C++ exception is thrown, not when it's caught, so either
+    /// setting stops on every exception thrown
+    fn exception_breakpoint(
+        &mut self,
+        caught: bool,
+        uncaught: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("BreakpointTimeout")
+            .unwrap() as u64;
+        let enable = caught || uncaught;
+        let existing = *self.exception_breakpoint.lock().unwrap();
+
+        let command = match (enable, existing) {
+            (true, None) => "breakpoint set -E c++".to_string(),
+            (false, Some(id)) => format!("breakpoint delete {}", id),
+            _ => {
+                return Box::new(future::lazy(move || {
+                    Ok(serde_json::json!({"status":"OK","caught":enable,"uncaught":enable}))
+                }));
+            }
+        };
+
+        let exception_breakpoint = self.exception_breakpoint.clone();
+
+        let f = self.raw_command(&command, timeout).map(move |output| {
+            lazy_static! {
+                static ref RE_BREAKPOINT_ID: Regex = Regex::new("^Breakpoint (\\d+): ").unwrap();
+            }
+
+            match enable {
+                true => match RE_BREAKPOINT_ID.captures(output.trim_start()) {
+                    Some(cap) => {
+                        let id = cap[1].parse::<u64>().unwrap();
+                        *exception_breakpoint.lock().unwrap() = Some(id);
+                    }
+                    None => {
+                        log_msg(
+                            LogLevel::ERROR,
+                            &format!("Can't set exception breakpoint: {}", output.trim()),
+                        );
+                        return serde_json::json!({"status":"ERROR"});
+                    }
+                },
+                false => *exception_breakpoint.lock().unwrap() = None,
+            }
+
+            serde_json::json!({"status":"OK","caught":enable,"uncaught":enable})
+        });
+
+        Box::new(f)
+    }
+
+    fn watchpoint(
+        &mut self,
+        variable: &Variable,
+        mode: WatchpointMode,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let (tx, rx) = mpsc::channel(1);
+
+        self.process
+            .lock()
+            .unwrap()
+            .add_listener(Listener::Watchpoint, tx);
+
+        let f = rx
+            .take(1)
+            .into_future()
+            .timeout(Duration::new(
+                config
+                    .lock()
+                    .unwrap()
+                    .get_config("BreakpointTimeout")
+                    .unwrap() as u64,
+                0,
+            ))
+            .then(move |event| match event {
+                Ok((Some(Event::WatchpointSet(id)), _)) => {
+                    Ok(serde_json::json!({"status":"OK","id":id}))
+                }
+                Ok((Some(Event::WatchpointFailed(msg)), _)) => {
+                    log_msg(LogLevel::WARN, &msg);
+                    Ok(serde_json::json!({"status":"ERROR"}))
+                }
+                Ok((None, _)) => Err(lldb_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out setting watchpoint",
+                    ))
+                }
+            });
+
+        let mode = match mode {
+            WatchpointMode::Read => "read",
+            WatchpointMode::Write => "write",
+            WatchpointMode::ReadWrite => "read_write",
+        };
+
+        let stmt = format!("watchpoint set variable -w {} {}\n", mode, variable.name);
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        Box::new(f)
+    }
+}
+
+impl ImplDebugger {
+    /// Set a breakpoint with any extra arguments to `breakpoint set` and wait for LLDB to
+    /// say whether it's been set
+    fn set_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        extra_args: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        log_msg(
+            LogLevel::INFO,
+            &format!(
+                "Setting breakpoint in file {} at line number {}",
+                file_location.name, file_location.line_num
+            ),
+        );
+
+        let (tx, rx) = mpsc::channel(1);
+
+        self.process
+            .lock()
+            .unwrap()
+            .add_listener(Listener::Breakpoint, tx);
+
+        let requested = file_location.clone();
+
+        let f = rx
+            .take(1)
+            .into_future()
+            .timeout(Duration::new(
+                config
+                    .lock()
+                    .unwrap()
+                    .get_config("BreakpointTimeout")
+                    .unwrap() as u64,
+                0,
+            ))
+            .then(move |event| match event {
+                Ok((Some(Event::BreakpointSet(_)), _)) => Ok(serde_json::json!({"status":"OK"})),
+                Ok((Some(Event::BreakpointPending), _)) => {
+                    breakpoint_pending(&requested.name, requested.line_num);
+                    Ok(serde_json::json!({"status":"PENDING"}))
+                }
+                Ok((Some(Event::BreakpointMultiple), _)) => Ok(serde_json::json!({"status":"OK"})),
+                Ok((None, _)) => Err(lldb_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out setting breakpoint",
+                    ))
+                }
+            });
+
+        let stmt = format!(
+            "breakpoint set --file {} --line {}{}\n",
+            quote_arg(&file_location.name),
+            file_location.line_num,
+            extra_args
+        );
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        Box::new(f)
+    }
+
+    /// Send a command to LLDB and return everything it outputs before the next prompt
+    fn raw_command(
+        &mut self,
+        command: &str,
+        timeout: u64,
+    ) -> Box<dyn Future<Item = String, Error = io::Error> + Send> {
+        let f = raw_output(&self.process, timeout);
+
+        let stmt = format!("{}\n", command);
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        Box::new(f)
+    }
+
+    /// Send a command to LLDB that prints a variable and return the variable's value, or
+    /// that it wasn't found
+    fn variable_command(
+        &mut self,
+        stmt: String,
+        timeout: u64,
+    ) -> Box<dyn Future<Item = Event, Error = io::Error> + Send> {
+        let (tx, rx) = mpsc::channel(1);
+
+        self.process
+            .lock()
+            .unwrap()
+            .add_listener(Listener::PrintVariable, tx);
+
+        let f = rx
+            .take(1)
+            .into_future()
+            .timeout(Duration::new(timeout, 0))
+            .then(move |event| match event {
+                Ok((Some(event), _)) => Ok(event),
+                Ok((None, _)) => Err(lldb_terminated()),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out printing variable",
+                    ))
+                }
+            });
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        Box::new(f)
+    }
+
+    fn toggle_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        kind: &str,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let ids = self
+            .process
+            .lock()
+            .unwrap()
+            .get_breakpoints_set_at(file_location);
+
+        if ids.is_empty() {
+            log_msg(
+                LogLevel::WARN,
+                &format!(
+                    "No breakpoint set in file {} at line number {}",
+                    file_location.name, file_location.line_num
+                ),
+            );
+            return Box::new(future::lazy(move || {
+                let resp = serde_json::json!({"status":"ERROR"});
+                Ok(resp)
+            }));
+        }
+
+        let ids = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let stmt = format!("breakpoint {} {}\n", kind, ids);
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        let f = future::lazy(move || {
+            let resp = serde_json::json!({"status":"OK"});
+            Ok(resp)
+        });
+
+        Box::new(f)
+    }
+
+    /// Run a stepping command (or continue or interrupt) `count` times, waiting each time
+    /// for the process to stop
+    ///
+    /// Stops early if a breakpoint is hit or the process exits, in which case the
+    /// response has `terminated` set. If the process doesn't stop within the
+    /// StepTimeout it's still running and we just report OK.
+    fn step(
+        &mut self,
+        command: &str,
+        count: u64,
+        report_each: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        match self.check_process() {
+            Some(f) => return f,
+            _ => {}
+        }
+
+        let stmt = format!("{}\n", command);
+        let timeout = config.lock().unwrap().get_config("StepTimeout").unwrap() as u64;
+        let progress_interval = config
+            .lock()
+            .unwrap()
+            .get_config("StepProgressInterval")
+            .unwrap() as u64;
+        let process = self.process.clone();
+        let process_finished = self.process.clone();
+
+        let f = future::loop_fn(count, move |remaining| {
+            let (tx, rx) = mpsc::channel(1);
+
+            let mut process = process.lock().unwrap();
+            process.report_stops(report_each || remaining == 1);
+            process.add_listener(Listener::Stopped, tx);
+            process.write_stdin(Bytes::from(stmt.clone()));
+
+            rx.take(1)
+                .into_future()
+                .timeout(Duration::new(timeout, 0))
+                .then(move |event| match event {
+                    Ok((Some(Event::Stopped(Some(ref reason), Some(ref file_location))), _))
+                        if reason.starts_with("breakpoint") && remaining > 1 =>
+                    {
+                        // LLDB would otherwise happily step straight past it
+                        Ok(future::Loop::Break(serde_json::json!({
+                            "status": "OK",
+                            "breakpoint": {
+                                "file": file_location.name,
+                                "line": file_location.line_num,
+                            }
+                        })))
+                    }
+                    Ok((Some(Event::Stopped(_, _)), _)) if remaining > 1 => {
+                        let done = count - remaining + 1;
+                        if progress_interval > 0 && done.is_multiple_of(progress_interval) {
+                            step_progress(done, count);
+                        }
+                        Ok(future::Loop::Continue(remaining - 1))
+                    }
+                    Ok((None, _)) => Err(lldb_terminated()),
+                    Ok((Some(Event::ProcessExited(_, _)), _)) => Ok(future::Loop::Break(
+                        serde_json::json!({"status":"OK","terminated":true}),
+                    )),
+                    Ok(_) => Ok(future::Loop::Break(serde_json::json!({"status":"OK"}))),
+                    Err(ref e) if e.is_elapsed() => {
+                        Ok(future::Loop::Break(serde_json::json!({"status":"OK"})))
+                    }
+                    Err(e) => {
+                        eprintln!("Reading stdin error {:?}", e);
+                        Err(io::Error::other("Failed stepping"))
+                    }
+                })
+        })
+        .then(move |resp| {
+            // Where it stopped if it stopped early without it being reported
+            process_finished.lock().unwrap().report_last_stop();
+            resp
+        });
+
+        Box::new(f)
+    }
+
+    /// Run a command that steps backwards, responding with the location it stops at
+    ///
+    /// LLDB refuses to unless the process is being recorded, so the command's output is
+    /// collected as well and LLDB's error is reported if it gives one rather than waiting
+    /// out the StepTimeout for a stop that won't happen.
+    fn reverse_step(
+        &mut self,
+        command: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process() {
+            return f;
+        }
+
+        let timeout = config.lock().unwrap().get_config("StepTimeout").unwrap() as u64;
+        let output = raw_output(&self.process, timeout);
+        let step = self.step(command, 1, true, config);
+        let process = self.process.clone();
+        let command = command.to_string();
+
+        let f = output.then(move |output| {
+            let error = match output {
+                Ok(ref output) => output
+                    .lines()
+                    .find(|l| l.starts_with("error: "))
+                    .map(|l| l["error: ".len()..].to_string()),
+                // Still going, the step works out whether it's stopped
+                Err(_) => {
+                    process.lock().unwrap().remove_listener(Listener::Raw);
+                    None
+                }
+            };
+
+            match error {
+                Some(error) => {
+                    process.lock().unwrap().remove_listener(Listener::Stopped);
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't run '{}': {}", command, error),
+                    );
+                    Box::new(future::ok(error_response(ErrorCode::DebuggerError)))
+                        as Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>
+                }
+                None => Box::new(step.map(move |mut resp| {
+                    if resp["terminated"].is_null() {
+                        resp["location"] =
+                            serde_json::json!(process.lock().unwrap().get_location());
+                    }
+                    resp
+                })),
+            }
+        });
+
+        Box::new(f)
+    }
+
+    fn check_process(
+        &mut self,
+    ) -> Option<Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>> {
+        match self.process.lock().unwrap().is_process_running() {
+            false => Some(not_running()),
+            true => None,
+        }
+    }
+}
+
+/// Print the full path of the source file of every compile unit, one per line
+const LIST_SOURCES_CMD: &str = "script for p in set(cu.file.fullpath for m in lldb.target.module_iter() for cu in m.compile_unit_iter() if cu.file.fullpath): print(p)";
+
+/// Print a variable in another frame of the current thread without selecting it
+///
+/// The variable is printed in the same format as `frame variable` and when it can't be
+/// found so is the error `frame variable` would give, though it's printed to stdout.
+fn frame_variable_cmd(name: &str, frame: u64) -> String {
+    let name = serde_json::to_string(name).unwrap();
+    format!(
+        "script print((lambda p, v: v if v.IsValid() else \"error: no variable named '\" + p + \"' found in this frame\")({}, lldb.thread.GetFrameAtIndex({}).GetValueForVariablePath({})))\n",
+        name, frame, name
+    )
+}
+
+/// Print the first `max_frames` frames of the current thread as JSON, each with its
+/// arguments and local variables
+///
+/// Variables with children in the selected frame are given their name as a reference for
+/// `getChildren`, which only looks in that frame, so those in other frames have none. When
+/// not demangling the function is given by its symbol's mangled name, falling back to the
+/// usual name for symbols that aren't mangled, e.g. C functions.
+fn stack_cmd(max_frames: u64, demangle: bool) -> String {
+    let function = match demangle {
+        true => "f.GetFunctionName()",
+        false => "(f.GetSymbol().GetMangledName() or f.GetFunctionName())",
+    };
+    format!(
+        "script import json; print(json.dumps([{{\"index\": f.GetFrameID(), \"file\": f.GetLineEntry().GetFileSpec().fullpath, \"line\": f.GetLineEntry().GetLine() or None, \"function\": {}, \"locals\": [{{\"name\": v.GetName(), \"type\": v.GetTypeName(), \"value\": v.GetValue() if v.GetValue() is not None else v.GetSummary(), \"reference\": v.GetName() if v.MightHaveChildren() and f.GetFrameID() == lldb.thread.GetSelectedFrame().GetFrameID() else None}} for v in f.GetVariables(True, True, False, True)]}} for f in lldb.thread.frames[:{}]]))",
+        function, max_frames
+    )
+}
+
+/// Print whether an expression is nonzero and its value, or the error evaluating it
+///
+/// The expression is only evaluated once in case it has side effects, whether it's nonzero
+/// is read from the value it gives so that pointers and floats are handled too. Structs
+/// have no value of their own so can't be and give an error.
+fn assert_cmd(expression: &str) -> String {
+    format!(
+        "script (lambda v: print(\"error: \" + str(v.GetError().GetCString()).strip() if v.GetError().Fail() else \"error: '\" + str(v.GetTypeName()) + \"' can't be used as a condition\" if v.GetValue() is None else str(float(v.GetValue()) != 0 if v.GetType().GetCanonicalType().GetBasicType() in (lldb.eBasicTypeFloat, lldb.eBasicTypeDouble, lldb.eBasicTypeLongDouble) else v.GetValueAsUnsigned() != 0) + \" \" + str(v.GetValue())))(lldb.frame.EvaluateExpression({}))",
+        serde_json::to_string(expression).unwrap()
+    )
+}
+
+/// The script a logpoint runs, printing the message with each expression evaluated in the
+/// frame it's hit in
+fn log_cmd(message: &str) -> String {
+    let mut parts = vec![serde_json::to_string(LOGPOINT_PREFIX).unwrap()];
+    for part in parse_log_message(message) {
+        parts.push(match part {
+            LogMessagePart::Text(text) => serde_json::to_string(&text).unwrap(),
+            LogMessagePart::Expression(expr) => format!(
+                "str(lldb.frame.EvaluateExpression({}).GetValue())",
+                serde_json::to_string(&expr).unwrap()
+            ),
+        });
+    }
+    format!("script print({})", parts.join(" + "))
+}
+
+/// Wait for everything LLDB outputs before the next prompt after a raw command is sent
+fn raw_output(
+    process: &Arc<Mutex<LLDBProcess>>,
+    timeout: u64,
+) -> Box<dyn Future<Item = String, Error = io::Error> + Send> {
+    let (tx, rx) = mpsc::channel(1);
+
+    process.lock().unwrap().add_listener(Listener::Raw, tx);
+
+    let f = rx
+        .take(1)
+        .into_future()
+        .timeout(Duration::new(timeout, 0))
+        .then(move |event| match event {
+            Ok((Some(Event::RawOutput(output)), _)) => Ok(output),
+            Ok((None, _)) => Err(lldb_terminated()),
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                eprintln!("Reading stdin error {:?}", e);
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Timed out running raw command",
+                ))
+            }
+        });
+
+    Box::new(f)
+}
+
+/// Send the settings PADRE needs to LLDB followed by the commands given with
+/// `--init-commands`
+///
+/// Each command is waited on before sending the next so they're run in order and each
+/// one's output is its own, one that fails is logged and the rest are still sent.
+fn run_init_commands(
+    process: Arc<Mutex<LLDBProcess>>,
+    settings: Vec<String>,
+    commands: Vec<String>,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let timeout = Config::new().get_config("RawCommandTimeout").unwrap() as u64;
+
+    let settings = stream::iter_ok(settings).map(|command| ("Setting", command));
+    let commands = stream::iter_ok(commands).map(|command| ("Init command", command));
+
+    let f = settings.chain(commands).for_each(move |(kind, command)| {
+        let f = raw_output(&process, timeout);
+
+        process
+            .lock()
+            .unwrap()
+            .send_setup_cmds(vec![Bytes::from(format!("{}\n", command))]);
+
+        f.then(move |output| {
+            match output {
+                Ok(ref output) if !output.contains("error: ") => {}
+                Ok(output) => log_msg(
+                    LogLevel::WARN,
+                    &format!("{} '{}' failed: {}", kind, command, output.trim()),
+                ),
+                Err(e) => log_msg(
+                    LogLevel::WARN,
+                    &format!("{} '{}' failed: {}", kind, command, e),
+                ),
+            }
+            Ok(())
+        })
+    });
+
+    Box::new(f)
+}
+
+/// The response to a command that needs the program to be stopped somewhere
+fn stopped_nowhere() -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+    log_msg(LogLevel::WARN, "The program isn't stopped anywhere");
+    Box::new(future::lazy(|| Ok(serde_json::json!({"status":"ERROR"}))))
+}
+
+/// The error reported for any command when LLDB itself has gone away
+fn lldb_terminated() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "LLDB has terminated")
 }
@@ -4,12 +4,17 @@
 //! analyse the output of the text and work out what is happening then.
 
 use std::collections::HashMap;
-use std::io::BufReader;
+use std::io::{self, BufReader};
 use std::sync::{Arc, Mutex};
 
-use crate::debugger::{FileLocation, Variable};
-use crate::notifier::{breakpoint_set, jump_to_position, log_msg, signal_exited, LogLevel};
-use crate::util::{check_and_spawn_process, read_output, setup_stdin};
+use crate::debugger::{
+    FileLocation, HitCondition, StopLocation, Variable, VariableChild, LOGPOINT_PREFIX,
+};
+use crate::notifier::{
+    breakpoint_set, jump_to_position, log_msg, logpoint_hit, protocol_trace, signal_exited,
+    watchpoint_hit, LogLevel,
+};
+use crate::util::{check_and_spawn_process, read_output, setup_stdin, split_lines};
 
 use bytes::Bytes;
 use regex::Regex;
@@ -23,6 +28,9 @@ use tokio_process::{Child, ChildStderr, ChildStdout};
 /// - ProcessExited: The process spawned by LLDB has exited
 /// - Breakpoint: A breakpoint event has happened
 /// - PrintVariable: A variable has been requested to print and this is the response
+/// - Raw: A raw command has been sent to LLDB and this is the output up to the next prompt
+/// - Stopped: The process has stopped, e.g. after a step, or has exited
+/// - Watchpoint: A watchpoint has been requested and this is the response
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum Listener {
     LLDBLaunched,
@@ -30,6 +38,9 @@ pub enum Listener {
     ProcessExited,
     Breakpoint,
     PrintVariable,
+    Raw,
+    Stopped,
+    Watchpoint,
 }
 
 /// An LLDB event is something that can be registered for being listened to and can be triggered
@@ -46,6 +57,12 @@ pub enum Event {
     BreakpointPending,
     PrintVariable(Variable, VariableValue),
     VariableNotFound(Variable),
+    RawOutput(String),
+    // (Stop reason, Position stopped at)
+    Stopped(Option<String>, Option<FileLocation>),
+    // (Watchpoint id)
+    WatchpointSet(u64),
+    WatchpointFailed(String),
 }
 
 /// The value of a variable
@@ -69,23 +86,208 @@ impl VariableValue {
     }
 }
 
+/// A thread as reported by LLDB's `thread list`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThreadInfo {
+    index: u64,
+    tid: u64,
+    name: Option<String>,
+    file: Option<String>,
+    line: Option<u64>,
+    #[serde(rename = "stopReason")]
+    stop_reason: Option<String>,
+    selected: bool,
+}
+
+lazy_static! {
+    static ref RE_THREAD: Regex = Regex::new(
+        "^(\\*)? *thread #(\\d+): tid = (\\d+)(?:, name = '([^']*)')?(?:, stop reason = (.+?))?(?: at (.+):(\\d+))?$"
+    )
+    .unwrap();
+}
+
+/// Parse the output of `thread list` into the threads listed
+///
+/// Relies on the `thread-format` setting set up when LLDB is launched. Only threads that
+/// have stopped for a reason, e.g. the one that hit a breakpoint, have a stop reason.
+pub fn parse_thread_list(output: &str) -> Vec<ThreadInfo> {
+    let mut threads = vec![];
+
+    for line in split_lines(output) {
+        for cap in RE_THREAD.captures_iter(line) {
+            threads.push(ThreadInfo {
+                index: cap[2].parse::<u64>().unwrap(),
+                tid: cap[3].parse::<u64>().unwrap(),
+                name: cap.get(4).map(|m| m.as_str().to_string()),
+                file: cap.get(6).map(|m| m.as_str().to_string()),
+                line: cap.get(7).map(|m| m.as_str().parse::<u64>().unwrap()),
+                stop_reason: cap.get(5).map(|m| m.as_str().to_string()),
+                selected: cap.get(1).is_some(),
+            });
+        }
+    }
+
+    threads
+}
+
+/// A module loaded into the program as reported by LLDB's `image list`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModuleInfo {
+    name: String,
+    path: String,
+    #[serde(rename = "loadAddress")]
+    load_address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uuid: Option<String>,
+}
+
+/// Parse the output of `image list -u -h -f` into the modules listed
+///
+/// Modules without a UUID, which can happen for ELF files without a build ID, are listed
+/// without one. The lines LLDB prints with the path of a module's separate debug symbols
+/// are ignored.
+pub fn parse_modules(output: &str) -> Vec<ModuleInfo> {
+    lazy_static! {
+        static ref RE_MODULE: Regex =
+            Regex::new("^\\[ *\\d+\\] +(?:(\\S+) +)?(0x[0-9a-fA-F]+) +(.+?) *$").unwrap();
+    }
+
+    let mut modules = vec![];
+
+    for line in split_lines(output) {
+        for cap in RE_MODULE.captures_iter(line) {
+            let path = cap[3].to_string();
+            modules.push(ModuleInfo {
+                name: path.rsplit('/').next().unwrap().to_string(),
+                path,
+                load_address: cap[2].to_string(),
+                uuid: cap.get(1).map(|m| m.as_str().to_string()),
+            });
+        }
+    }
+
+    modules
+}
+
+/// Parse the source files printed one per line by `LIST_SOURCES_CMD`, anything else
+/// LLDB printed is ignored
+pub fn parse_sources(output: &str) -> Vec<String> {
+    let mut sources: Vec<String> = split_lines(output)
+        .iter()
+        .map(|l| l.trim())
+        .filter(|l| l.starts_with("/"))
+        .map(|l| l.to_string())
+        .collect();
+
+    sources.sort();
+    sources.dedup();
+
+    sources
+}
+
+/// Parse the frames printed as JSON by the script that gets the stack with locals, `None`
+/// if it didn't print any
+pub fn parse_stack(output: &str) -> Option<serde_json::Value> {
+    split_lines(output)
+        .iter()
+        .map(|l| l.trim())
+        .filter(|l| l.starts_with("["))
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .next()
+}
+
+/// The number of breakpoints `breakpoint delete` says it removed, `None` if it didn't
+/// remove them
+pub fn parse_breakpoints_deleted(output: &str) -> Option<u64> {
+    lazy_static! {
+        static ref RE_DELETED: Regex =
+            Regex::new("^All breakpoints removed\\. \\((\\d+) breakpoints?\\)").unwrap();
+    }
+
+    for line in split_lines(output) {
+        if line.starts_with("No breakpoints exist to be deleted") {
+            return Some(0);
+        }
+        if let Some(cap) = RE_DELETED.captures(line) {
+            return Some(cap[1].parse::<u64>().unwrap());
+        }
+    }
+
+    None
+}
+
+/// Quote an argument to an LLDB command so it can contain spaces and quotes
+pub fn quote_arg(arg: &str) -> String {
+    format!("\"{}\"", arg.replace("\\", "\\\\").replace("\"", "\\\""))
+}
+
+/// Parse the output of `frame variable -T -D 1` for the variable at `path` into its
+/// children
+///
+/// Children that are compound themselves are shown by LLDB as `{...}` at this depth and are
+/// given the path to them as a reference. Pointers aren't followed, `*p` can be used to
+/// get the children of what `p` points to.
+pub fn parse_variable_children(path: &str, output: &str) -> Vec<VariableChild> {
+    lazy_static! {
+        static ref RE_CHILD: Regex = Regex::new("^  \\((.*?)\\) (\\S+) = (.*)$").unwrap();
+    }
+
+    let mut children = vec![];
+
+    for line in split_lines(output) {
+        for cap in RE_CHILD.captures_iter(line) {
+            let name = cap[2].to_string();
+            let value = cap[3].to_string();
+            let reference = match value.starts_with("{") {
+                true if name.starts_with("[") => Some(format!("{}{}", path, name)),
+                true => Some(format!("{}.{}", path, name)),
+                false => None,
+            };
+            children.push(VariableChild::new(
+                name,
+                cap[1].to_string(),
+                serde_json::json!(value),
+                reference,
+            ));
+        }
+    }
+
+    children
+}
+
+/// LLDB reports a column of 0 when it doesn't know the column
+fn parse_column(column: &str) -> Option<u64> {
+    match column.parse::<u64>() {
+        Ok(0) | Err(_) => None,
+        Ok(c) => Some(c),
+    }
+}
+
 #[derive(Debug)]
 pub struct LLDBProcess {
     debugger_cmd: Option<String>,
     run_cmd: Option<Vec<String>>,
+    env: Vec<(String, String)>,
     lldb_process: Option<Child>,
     lldb_stdin_tx: Option<Sender<Bytes>>,
+    // Whether LLDB has launched and been set up, anything written to stdin before then is
+    // held in `held_stdin` so it doesn't get mixed up with the setup
+    started: bool,
+    held_stdin: Vec<Bytes>,
     analyser: Arc<Mutex<Analyser>>,
 }
 
 impl LLDBProcess {
     /// Create a new LLDBProcess
-    pub fn new(debugger_cmd: String, run_cmd: Vec<String>) -> Self {
+    pub fn new(debugger_cmd: String, run_cmd: Vec<String>, env: Vec<(String, String)>) -> Self {
         LLDBProcess {
             debugger_cmd: Some(debugger_cmd),
             run_cmd: Some(run_cmd),
+            env,
             lldb_process: None,
             lldb_stdin_tx: None,
+            started: false,
+            held_stdin: vec![],
             analyser: Arc::new(Mutex::new(Analyser::new())),
         }
     }
@@ -95,12 +297,14 @@ impl LLDBProcess {
     /// Includes spawning the LLDB process and all the relevant stdio handlers. In particular:
     /// - Sets up a `ReadOutput` from `util.rs` in order to read stdout and stderr;
     /// - Sets up a thread to read stdin and forward it onto LLDB stdin;
-    /// - Checks that LLDB and the program to be ran both exist, otherwise panics.
-    pub fn setup(&mut self) {
+    /// - Checks that LLDB and the program to be ran both exist, otherwise returns an error.
+    pub fn setup(&mut self) -> Result<(), io::Error> {
         let mut lldb_process = check_and_spawn_process(
             vec![self.debugger_cmd.take().unwrap()],
             self.run_cmd.take().unwrap(),
-        );
+            None,
+            &self.env,
+        )?;
 
         self.setup_stdout(
             lldb_process
@@ -122,8 +326,11 @@ impl LLDBProcess {
             false,
         );
 
+        self.analyser.lock().unwrap().set_stdin(stdin_tx.clone());
         self.lldb_stdin_tx = Some(stdin_tx);
         self.lldb_process = Some(lldb_process);
+
+        Ok(())
     }
 
     pub fn teardown(&mut self) {
@@ -131,12 +338,40 @@ impl LLDBProcess {
     }
 
     /// Send a message to write to stdin
+    ///
+    /// Held on to until LLDB has started if it hasn't yet.
     pub fn write_stdin(&mut self, bytes: Bytes) {
+        if !self.started {
+            self.held_stdin.push(bytes);
+            return;
+        }
+        self.send_stdin(vec![bytes]);
+    }
+
+    /// Send commands to set LLDB up straight away, ahead of anything held on to until it's
+    /// started
+    pub fn send_setup_cmds(&mut self, setup_cmds: Vec<Bytes>) {
+        self.send_stdin(setup_cmds);
+    }
+
+    /// LLDB has launched, send it the `setup_cmds` followed by anything written to stdin
+    /// before now
+    pub fn started(&mut self, setup_cmds: Vec<Bytes>) {
+        self.started = true;
+        let mut cmds = setup_cmds;
+        cmds.append(&mut self.held_stdin);
+        self.send_stdin(cmds);
+    }
+
+    /// Write to stdin in the order given
+    fn send_stdin(&mut self, cmds: Vec<Bytes>) {
+        for bytes in cmds.iter() {
+            protocol_trace("command", &String::from_utf8_lossy(bytes));
+        }
         let tx = self.lldb_stdin_tx.clone();
         tokio::spawn(
-            tx.clone()
-                .unwrap()
-                .send(bytes)
+            tx.unwrap()
+                .send_all(stream::iter_ok(cmds))
                 .map(move |_| {})
                 .map_err(|e| eprintln!("Error sending to LLDB: {}", e)),
         );
@@ -146,13 +381,89 @@ impl LLDBProcess {
         self.analyser.lock().unwrap().add_listener(kind, sender);
     }
 
+    /// Stop listening for an event that's no longer going to happen
+    pub fn remove_listener(&mut self, kind: Listener) {
+        self.analyser.lock().unwrap().remove_listener(kind);
+    }
+
     pub fn is_process_running(&self) -> bool {
         self.analyser.lock().unwrap().is_process_running()
     }
 
+    pub fn get_pid(&self) -> Option<u64> {
+        self.analyser.lock().unwrap().process_pid
+    }
+
+    /// Use a different prompt to the usual one to tell when the debugger's finished
+    /// a command
+    pub fn set_prompt(&self, prompt: String) {
+        self.analyser.lock().unwrap().prompt = prompt;
+    }
+
+    pub fn get_location(&self) -> Option<StopLocation> {
+        self.analyser.lock().unwrap().get_location()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.analyser.lock().unwrap().is_stopped()
+    }
+
+    pub fn get_exit_code(&self) -> Option<i64> {
+        self.analyser.lock().unwrap().exit_code
+    }
+
+    /// Set whether the stops of the next step are notified, any stop before that wasn't
+    /// is forgotten
+    pub fn report_stops(&self, report: bool) {
+        self.analyser.lock().unwrap().report_stops(report);
+    }
+
+    /// The next stop is taken to be stopping at `main` when the program starts
+    pub fn expect_entry_stop(&self) {
+        let mut analyser = self.analyser.lock().unwrap();
+        analyser.entry_stop = analyser.last_breakpoint;
+    }
+
+    /// Notify of stops again, including the last one if it wasn't
+    pub fn report_last_stop(&self) {
+        self.analyser.lock().unwrap().report_last_stop();
+    }
+
+    pub fn get_return_value(&self) -> Option<String> {
+        self.analyser.lock().unwrap().get_return_value()
+    }
+
+    pub fn get_breakpoints_set_at(&self, file_location: &FileLocation) -> Vec<u64> {
+        self.analyser
+            .lock()
+            .unwrap()
+            .get_breakpoints_set_at(file_location)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.analyser.lock().unwrap().clear_breakpoints();
+    }
+
+    pub fn add_hit_condition(&mut self, file_location: FileLocation, hit_condition: HitCondition) {
+        self.analyser
+            .lock()
+            .unwrap()
+            .add_hit_condition(file_location, hit_condition);
+    }
+
+    pub fn remove_hit_condition(&mut self, file_location: &FileLocation) {
+        self.analyser
+            .lock()
+            .unwrap()
+            .remove_hit_condition(file_location);
+    }
+
     /// Perform setup of reading LLDB stdout, analysing it and writing it back to stdout.
+    ///
+    /// When LLDB's stdout closes LLDB has gone away so the analyser is told about it.
     fn setup_stdout(&mut self, stdout: ChildStdout) {
         let analyser = self.analyser.clone();
+        let analyser_eof = self.analyser.clone();
         tokio::spawn(
             read_output(BufReader::new(stdout))
                 .for_each(move |text| {
@@ -160,7 +471,11 @@ impl LLDBProcess {
                     analyser.lock().unwrap().analyse_stdout(&text);
                     Ok(())
                 })
-                .map_err(|e| eprintln!("Err reading LLDB stdout: {}", e)),
+                .map_err(|e| eprintln!("Err reading LLDB stdout: {}", e))
+                .then(move |_| {
+                    analyser_eof.lock().unwrap().debugger_terminated();
+                    Ok(())
+                }),
         );
     }
 
@@ -179,12 +494,43 @@ impl LLDBProcess {
     }
 }
 
+/// The prompt LLDB shows unless told otherwise with `--prompt`
+const DEFAULT_PROMPT: &str = "(lldb) ";
+
 #[derive(Debug)]
 pub struct Analyser {
     stdout: String,
     stderr: String,
     process_pid: Option<u64>,
+    // The exit code of the last process LLDB ran to completion
+    exit_code: Option<i64>,
+    launched: bool,
+    terminated: bool,
+    raw_output: Option<String>,
+    stop_reason: Option<String>,
+    location: Option<StopLocation>,
+    // The breakpoint at `main` that stops the program when it starts, until the next stop
+    entry_stop: Option<u64>,
+    // Only the last stop of a counted step is notified unless every one is asked for, the
+    // position of one that wasn't is kept in case the step ends there
+    reporting_location: bool,
+    unreported_location: Option<(String, u64, Option<u64>)>,
+    // The value returned from the function LLDB last stepped out of
+    return_value: Option<String>,
+    // The id, old value and new value of the last watchpoint hit as they're read
+    watchpoint_hit: Option<(u64, Option<String>, Option<String>)>,
+    // All the breakpoints LLDB has told us it's set, by LLDB's breakpoint id
+    breakpoints: HashMap<u64, (FileLocation, Option<u64>)>,
+    // The id of the breakpoint LLDB last said it's set, wherever it's set
+    last_breakpoint: Option<u64>,
+    // Breakpoints that only stop when their hit condition is satisfied and the number of
+    // times each has been hit in this run of the process
+    hit_conditions: Vec<(FileLocation, HitCondition, u64)>,
     listeners: HashMap<Listener, Sender<Event>>,
+    // Shown by LLDB when it's ready for the next command
+    prompt: String,
+    // For continuing straight away after a hit that doesn't satisfy its hit condition
+    stdin_tx: Option<Sender<Bytes>>,
 }
 
 impl Analyser {
@@ -193,14 +539,45 @@ impl Analyser {
             stdout: "".to_string(),
             stderr: "".to_string(),
             process_pid: None,
+            exit_code: None,
+            launched: false,
+            terminated: false,
+            raw_output: None,
+            stop_reason: None,
+            location: None,
+            entry_stop: None,
+            reporting_location: true,
+            unreported_location: None,
+            return_value: None,
+            watchpoint_hit: None,
+            breakpoints: HashMap::new(),
+            last_breakpoint: None,
+            hit_conditions: vec![],
             listeners: HashMap::new(),
+            prompt: DEFAULT_PROMPT.to_string(),
+            stdin_tx: None,
         }
     }
 
+    /// Register for an event, listeners are dropped straight away if LLDB has
+    /// terminated so that they're notified of that immediately.
     pub fn add_listener(&mut self, kind: Listener, sender: Sender<Event>) {
+        if self.terminated {
+            return;
+        }
+        if kind == Listener::Raw {
+            self.raw_output = Some("".to_string());
+        }
         self.listeners.insert(kind, sender);
     }
 
+    pub fn remove_listener(&mut self, kind: Listener) {
+        if kind == Listener::Raw {
+            self.raw_output = None;
+        }
+        self.listeners.remove(&kind);
+    }
+
     pub fn analyse_stdout(&mut self, s: &str) {
         self.stdout.push_str(s);
 
@@ -212,8 +589,9 @@ impl Analyser {
             static ref RE_PROCESS_EXITED: Regex =
                 Regex::new("^Process (\\d+) exited with status = (\\d+) \\(0x[0-9a-f]*\\) *$")
                     .unwrap();
+            static ref RE_PROCESS_DETACHED: Regex = Regex::new("^Process (\\d+) detached$").unwrap();
             static ref RE_BREAKPOINT: Regex = Regex::new(
-                "Breakpoint (\\d+): where = .* at (.*):(\\d+):\\d+, address = 0x[0-9a-f]*$"
+                "Breakpoint (\\d+): where = .* at (.*):(\\d+):(\\d+), address = 0x[0-9a-f]*$"
             )
             .unwrap();
             static ref RE_BREAKPOINT_2: Regex =
@@ -223,18 +601,34 @@ impl Analyser {
                 Regex::new("Breakpoint (\\d+): (\\d+) locations\\.$").unwrap();
             static ref RE_BREAKPOINT_PENDING: Regex =
                 Regex::new("Breakpoint (\\d+): no locations \\(pending\\)\\.$").unwrap();
+            static ref RE_STOP_REASON: Regex = Regex::new("stop reason = (.*)$").unwrap();
+            static ref RE_RETURN_VALUE: Regex = Regex::new("^Return value: (.*)$").unwrap();
+            static ref RE_WATCHPOINT_CREATED: Regex = Regex::new(
+                "^Watchpoint created: Watchpoint (\\d+): addr = 0x[0-9a-f]+ size = \\d+ state = \\w+ type = \\w+$"
+            )
+            .unwrap();
+            static ref RE_WATCHPOINT_HIT: Regex = Regex::new("^Watchpoint (\\d+) hit:$").unwrap();
+            static ref RE_WATCHPOINT_OLD_VALUE: Regex = Regex::new("^old value: (.*)$").unwrap();
+            static ref RE_WATCHPOINT_NEW_VALUE: Regex = Regex::new("^new value: (.*)$").unwrap();
             static ref RE_STOPPED_AT_POSITION: Regex = Regex::new(" *frame #\\d.*$").unwrap();
             static ref RE_JUMP_TO_POSITION: Regex =
-                Regex::new("^ *frame #\\d at (\\S+):(\\d+)$").unwrap();
+                Regex::new("^ *frame #\\d at (.+?):(\\d+)(?::(\\d+))?$").unwrap();
             static ref RE_PRINTED_VARIABLE: Regex =
                 Regex::new("^\\((.*)\\) ([\\S+]*) = .*$").unwrap();
             static ref RE_PROCESS_NOT_RUNNING: Regex =
                 Regex::new("error: invalid process$").unwrap();
+            // As printed when printing a variable in another frame
+            static ref RE_VARIABLE_NOT_FOUND: Regex =
+                Regex::new("^error: no variable named '([^']*)' found in this frame$").unwrap();
         }
 
         let s = self.stdout.clone();
 
-        for line in s.split("\n") {
+        for line in split_lines(&s) {
+            if let Some(message) = line.strip_prefix(LOGPOINT_PREFIX) {
+                logpoint_hit(message);
+            }
+
             for _ in RE_LLDB_STARTED.captures_iter(line) {
                 self.lldb_started();
             }
@@ -250,28 +644,37 @@ impl Analyser {
                 self.process_exited(pid, exit_code);
             }
 
+            for cap in RE_PROCESS_DETACHED.captures_iter(line) {
+                let pid = cap[1].parse::<u64>().unwrap();
+                self.process_detached(pid);
+            }
+
             let mut found_breakpoint = false;
 
             for cap in RE_BREAKPOINT.captures_iter(line) {
                 found_breakpoint = true;
+                let id = cap[1].parse::<u64>().unwrap();
                 let file = cap[2].to_string();
                 let line = cap[3].parse::<u64>().unwrap();
-                self.found_breakpoint(file, line);
+                let column = parse_column(&cap[4]);
+                self.found_breakpoint(id, file, line, column);
             }
 
             if !found_breakpoint {
                 for cap in RE_BREAKPOINT_2.captures_iter(line) {
                     found_breakpoint = true;
+                    let id = cap[1].parse::<u64>().unwrap();
                     let file = cap[2].to_string();
                     let line = cap[3].parse::<u64>().unwrap();
-                    self.found_breakpoint(file, line);
+                    self.found_breakpoint(id, file, line, None);
                 }
             }
 
             if !found_breakpoint {
-                for _ in RE_BREAKPOINT_MULTIPLE.captures_iter(line) {
+                for cap in RE_BREAKPOINT_MULTIPLE.captures_iter(line) {
                     found_breakpoint = true;
-                    self.found_multiple_breakpoints();
+                    let id = cap[1].parse::<u64>().unwrap();
+                    self.found_multiple_breakpoints(id);
                 }
             }
 
@@ -281,13 +684,56 @@ impl Analyser {
                 }
             }
 
+            for cap in RE_STOP_REASON.captures_iter(line) {
+                // Threads listed by `thread list` show their stop reasons too, they're not
+                // a new stop
+                if RE_THREAD.is_match(line) {
+                    continue;
+                }
+                self.stop_reason = Some(cap[1].to_string());
+                self.return_value = None;
+            }
+
+            for cap in RE_RETURN_VALUE.captures_iter(line) {
+                self.return_value = Some(cap[1].to_string());
+            }
+
+            for cap in RE_WATCHPOINT_CREATED.captures_iter(line) {
+                let id = cap[1].parse::<u64>().unwrap();
+                self.watchpoint_set(id);
+            }
+
+            for cap in RE_WATCHPOINT_HIT.captures_iter(line) {
+                let id = cap[1].parse::<u64>().unwrap();
+                self.watchpoint_hit = Some((id, None, None));
+            }
+
+            // LLDB also prints the value when a watchpoint is created so only take notice
+            // of values after a hit
+            for cap in RE_WATCHPOINT_OLD_VALUE.captures_iter(line) {
+                if let Some((_, ref mut old, None)) = self.watchpoint_hit {
+                    *old = Some(cap[1].to_string());
+                }
+            }
+
+            for cap in RE_WATCHPOINT_NEW_VALUE.captures_iter(line) {
+                if let Some((id, ref old, ref mut new @ None)) = self.watchpoint_hit {
+                    *new = Some(cap[1].to_string());
+                    watchpoint_hit(id, old.as_ref().map(|s| &s[..]), &cap[1]);
+                }
+            }
+
             for _ in RE_STOPPED_AT_POSITION.captures_iter(line) {
                 let mut found = false;
                 for cap in RE_JUMP_TO_POSITION.captures_iter(line) {
                     found = true;
                     let file = cap[1].to_string();
                     let line = cap[2].parse::<u64>().unwrap();
-                    self.jump_to_position(file, line);
+                    let column = cap.get(3).and_then(|m| parse_column(m.as_str()));
+                    if self.skip_breakpoint_hit(&file, line) {
+                        continue;
+                    }
+                    self.jump_to_position(file, line, column);
                 }
 
                 if !found {
@@ -304,8 +750,15 @@ impl Analyser {
             for _ in RE_PROCESS_NOT_RUNNING.captures_iter(line) {
                 self.process_not_running();
             }
+
+            for cap in RE_VARIABLE_NOT_FOUND.captures_iter(line) {
+                let variable = cap[1].to_string();
+                self.variable_not_found(variable);
+            }
         }
 
+        self.raw_output(&s);
+
         self.clear_analyser();
     }
 
@@ -315,15 +768,32 @@ impl Analyser {
         lazy_static! {
             static ref RE_VARIABLE_NOT_FOUND: Regex =
                 Regex::new("error: no variable named '([^']*)' found in this frame$").unwrap();
+            static ref RE_GLOBAL_NOT_FOUND: Regex =
+                Regex::new("error: can't find global variable '([^']*)'$").unwrap();
+            static ref RE_WATCHPOINT_FAILED: Regex =
+                Regex::new("^error: (Watchpoint creation failed.*)$").unwrap();
         }
 
         let s = self.stderr.clone();
 
-        for line in s.split("\n") {
+        for line in split_lines(&s) {
             for cap in RE_VARIABLE_NOT_FOUND.captures_iter(line) {
                 let variable = cap[1].to_string();
                 self.variable_not_found(variable);
             }
+
+            for cap in RE_GLOBAL_NOT_FOUND.captures_iter(line) {
+                let variable = cap[1].to_string();
+                self.variable_not_found(variable);
+            }
+
+            for cap in RE_WATCHPOINT_FAILED.captures_iter(line) {
+                self.watchpoint_failed(cap[1].to_string());
+            }
+        }
+
+        if let Some(ref mut output) = self.raw_output {
+            output.push_str(&s);
         }
 
         self.clear_analyser();
@@ -334,6 +804,69 @@ impl Analyser {
         self.stderr = "".to_string();
     }
 
+    /// Get the ids of all the breakpoints LLDB has told us are set at the location
+    pub fn get_breakpoints_set_at(&self, file_location: &FileLocation) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .breakpoints
+            .iter()
+            .filter(|(_, (fl, _))| fl.matches(file_location))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn set_stdin(&mut self, stdin_tx: Sender<Bytes>) {
+        self.stdin_tx = Some(stdin_tx);
+    }
+
+    /// Forget every breakpoint once LLDB's deleted them all
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.hit_conditions.clear();
+    }
+
+    /// Only stop at the breakpoint at the location when the hit condition is satisfied
+    pub fn add_hit_condition(&mut self, file_location: FileLocation, hit_condition: HitCondition) {
+        self.remove_hit_condition(&file_location);
+        self.hit_conditions.push((file_location, hit_condition, 0));
+    }
+
+    /// Always stop at the breakpoint at the location again
+    pub fn remove_hit_condition(&mut self, file_location: &FileLocation) {
+        self.hit_conditions
+            .retain(|(fl, _, _)| !fl.matches(file_location));
+    }
+
+    /// Where the process last stopped, if it's still running
+    pub fn get_location(&self) -> Option<StopLocation> {
+        self.location.clone()
+    }
+
+    /// Whether the program is stopped, it isn't when it's been told to carry on and
+    /// hasn't stopped again yet
+    pub fn is_stopped(&self) -> bool {
+        self.location.is_some() && !self.listeners.contains_key(&Listener::Stopped)
+    }
+
+    /// The value returned by the function last stepped out of, if the last stop was
+    /// stepping out
+    pub fn get_return_value(&self) -> Option<String> {
+        self.return_value.clone()
+    }
+
+    /// LLDB has gone away, drop all listeners so anything waiting on LLDB finds out
+    pub fn debugger_terminated(&mut self) {
+        if self.terminated {
+            return;
+        }
+        self.terminated = true;
+        self.process_pid = None;
+        self.raw_output = None;
+        self.listeners.clear();
+        log_msg(LogLevel::CRITICAL, "LLDB process ended");
+    }
+
     pub fn is_process_running(&self) -> bool {
         match self.process_pid {
             Some(_) => true,
@@ -352,6 +885,18 @@ impl Analyser {
 
     fn process_started(&mut self, pid: u64) {
         self.process_pid = Some(pid);
+
+        for (_, _, hits) in self.hit_conditions.iter_mut() {
+            *hits = 0;
+        }
+
+        // LLDB keeps the breakpoints across a restart so make sure the editor knows
+        // about all of them again
+        if self.launched {
+            self.report_breakpoints();
+        }
+        self.launched = true;
+
         match self.listeners.remove(&Listener::ProcessLaunched) {
             Some(listener) => {
                 listener.send(Event::ProcessLaunched(pid)).wait().unwrap();
@@ -360,8 +905,18 @@ impl Analyser {
         }
     }
 
+    /// The process carries on without LLDB so as far as we're concerned it's gone
+    fn process_detached(&mut self, pid: u64) {
+        self.process_pid = None;
+        self.location = None;
+        log_msg(LogLevel::INFO, &format!("Detached from process {}", pid));
+    }
+
     fn process_exited(&mut self, pid: u64, exit_code: i64) {
         self.process_pid = None;
+        self.exit_code = Some(exit_code);
+        self.location = None;
+        self.entry_stop = None;
         signal_exited(pid, exit_code);
         match self.listeners.remove(&Listener::ProcessExited) {
             Some(listener) => {
@@ -372,11 +927,17 @@ impl Analyser {
             }
             None => {}
         }
+        // The step may have given up waiting if the process ran for a while
+        if let Some(listener) = self.listeners.remove(&Listener::Stopped) {
+            let _ = listener.send(Event::ProcessExited(pid, exit_code)).wait();
+        }
     }
 
-    fn found_breakpoint(&mut self, file: String, line: u64) {
-        breakpoint_set(&file, line);
+    fn found_breakpoint(&mut self, id: u64, file: String, line: u64, column: Option<u64>) {
+        breakpoint_set(&file, line, column);
         let file_location = FileLocation::new(file, line);
+        self.breakpoints.insert(id, (file_location.clone(), column));
+        self.last_breakpoint = Some(id);
         match self.listeners.remove(&Listener::Breakpoint) {
             Some(listener) => {
                 listener
@@ -388,7 +949,29 @@ impl Analyser {
         }
     }
 
-    fn found_multiple_breakpoints(&mut self) {
+    fn report_breakpoints(&self) {
+        let mut ids: Vec<&u64> = self.breakpoints.keys().collect();
+        ids.sort();
+        for id in ids {
+            let (file_location, column) = &self.breakpoints[id];
+            breakpoint_set(&file_location.name, file_location.line_num, *column);
+        }
+    }
+
+    fn watchpoint_set(&mut self, id: u64) {
+        if let Some(listener) = self.listeners.remove(&Listener::Watchpoint) {
+            listener.send(Event::WatchpointSet(id)).wait().unwrap();
+        }
+    }
+
+    fn watchpoint_failed(&mut self, msg: String) {
+        if let Some(listener) = self.listeners.remove(&Listener::Watchpoint) {
+            listener.send(Event::WatchpointFailed(msg)).wait().unwrap();
+        }
+    }
+
+    fn found_multiple_breakpoints(&mut self, id: u64) {
+        self.last_breakpoint = Some(id);
         match self.listeners.remove(&Listener::Breakpoint) {
             Some(listener) => {
                 listener.send(Event::BreakpointMultiple).wait().unwrap();
@@ -406,28 +989,109 @@ impl Analyser {
         }
     }
 
-    fn jump_to_position(&mut self, file: String, line: u64) {
-        jump_to_position(&file, line);
+    fn jump_to_position(&mut self, file: String, line: u64, column: Option<u64>) {
+        if self.reporting_location {
+            jump_to_position(&file, line, column);
+        } else {
+            self.unreported_location = Some((file.clone(), line, column));
+        }
+        self.stopped(Some(FileLocation::new(file, line)), column);
     }
 
-    fn jump_to_unknown_position(&mut self) {
-        log_msg(LogLevel::WARN, "Stopped at unknown position");
+    pub fn report_stops(&mut self, report: bool) {
+        self.reporting_location = report;
+        self.unreported_location = None;
     }
 
-    fn printed_variable(&mut self, variable: String, variable_type: String, data: &str) {
-        let mut start = 1;
+    pub fn report_last_stop(&mut self) {
+        self.reporting_location = true;
+        if let Some((file, line, column)) = self.unreported_location.take() {
+            jump_to_position(&file, line, column);
+        }
+    }
 
-        while &data[start..start + 1] != ")" {
-            start += 1;
+    /// Count a hit of a breakpoint with a hit condition, continuing straight away when the
+    /// condition isn't satisfied so the stop is never reported
+    fn skip_breakpoint_hit(&mut self, file: &str, line: u64) -> bool {
+        match self.stop_reason {
+            Some(ref reason) if reason.starts_with("breakpoint") => {}
+            _ => return false,
         }
-        while &data[start..start + 1] != "=" {
-            start += 1;
+
+        let file_location = FileLocation::new(file.to_string(), line);
+
+        let satisfied = match self
+            .hit_conditions
+            .iter_mut()
+            .find(|(fl, _, _)| fl.matches(&file_location))
+        {
+            Some((_, hit_condition, hits)) => {
+                *hits += 1;
+                hit_condition.is_satisfied(*hits)
+            }
+            None => return false,
+        };
+
+        if satisfied {
+            return false;
+        }
+
+        self.stop_reason = None;
+
+        if let Some(tx) = self.stdin_tx.clone() {
+            protocol_trace("command", "thread continue\n");
+            tokio::spawn(
+                tx.send(Bytes::from(&b"thread continue\n"[..]))
+                    .map(|_| {})
+                    .map_err(|e| eprintln!("Error sending to LLDB: {}", e)),
+            );
+        }
+
+        true
+    }
+
+    fn jump_to_unknown_position(&mut self) {
+        log_msg(LogLevel::WARN, "Stopped at unknown position");
+        self.stopped(None, None);
+    }
+
+    fn stopped(&mut self, file_location: Option<FileLocation>, column: Option<u64>) {
+        let stop_reason = self.stop_reason.take();
+        // Only a stop at the breakpoint on `main` is the program starting
+        let entry_stop = match (self.entry_stop.take(), &stop_reason) {
+            (Some(id), Some(reason)) => reason.starts_with(&format!("breakpoint {}.", id)),
+            _ => false,
+        };
+        self.location = file_location.as_ref().map(|fl| {
+            let location = StopLocation::new(fl.name.clone(), fl.line_num, stop_reason.clone())
+                .with_column(column);
+            match entry_stop {
+                true => location.with_reason("entry"),
+                false => location,
+            }
+        });
+        // The step may have given up waiting if the process ran for a while
+        if let Some(listener) = self.listeners.remove(&Listener::Stopped) {
+            let _ = listener
+                .send(Event::Stopped(stop_reason, file_location))
+                .wait();
         }
-        start += 2;
+    }
 
-        // TODO: Need a better way of doing this to strip of the last \n,
-        // it's possible one day we'll screw the UTF-8 pooch here.
-        let value = data[start..data.len() - 1].to_string();
+    fn printed_variable(&mut self, variable: String, variable_type: String, data: &str) {
+        // The value follows the `= ` after the type and ends with a line ending, the
+        // positions found are of ASCII characters so the value can be sliced safely
+        // whatever characters it contains
+        let start = data
+            .get(1..)
+            .and_then(|d| d.find(')'))
+            .map(|i| i + 1)
+            .and_then(|i| data[i..].find('=').map(|j| i + j + 2));
+        let mut value = start
+            .and_then(|start| data.get(start..))
+            .unwrap_or("")
+            .to_string();
+        value.pop();
 
         match self.listeners.remove(&Listener::PrintVariable) {
             Some(listener) => {
@@ -458,4 +1122,732 @@ impl Analyser {
             None => {}
         }
     }
+
+    /// Collect the output of a raw command until LLDB shows the prompt again
+    fn raw_output(&mut self, s: &str) {
+        let finished = match self.raw_output {
+            Some(ref mut output) => {
+                output.push_str(s);
+                output.ends_with(&self.prompt)
+            }
+            None => false,
+        };
+
+        if !finished {
+            return;
+        }
+
+        let output = self.raw_output.take().unwrap();
+        let output = output[..output.len() - self.prompt.len()]
+            .trim_end()
+            .to_string();
+
+        if let Some(listener) = self.listeners.remove(&Listener::Raw) {
+            listener.send(Event::RawOutput(output)).wait().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::{FileLocation, HitCondition, StopLocation, Variable};
+    use bytes::Bytes;
+    use tokio::prelude::*;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn check_parse_thread_list() {
+        let output = "Process 12345 stopped\n\
+                      * thread #1: tid = 12345, name = 'test' at /home/me/test.c:12\n  \
+                      thread #2: tid = 12346, name = 'worker'\n  \
+                      thread #3: tid = 12347\n";
+
+        let threads = super::parse_thread_list(output);
+
+        assert_eq!(threads.len(), 3);
+        assert_eq!(
+            serde_json::to_value(&threads[0]).unwrap(),
+            serde_json::json!({
+                "index": 1,
+                "tid": 12345,
+                "name": "test",
+                "file": "/home/me/test.c",
+                "line": 12,
+                "stopReason": null,
+                "selected": true,
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&threads[1]).unwrap(),
+            serde_json::json!({
+                "index": 2,
+                "tid": 12346,
+                "name": "worker",
+                "file": null,
+                "line": null,
+                "stopReason": null,
+                "selected": false,
+            })
+        );
+        assert_eq!(threads[2].name, None);
+    }
+
+    #[test]
+    fn check_parse_thread_list_stop_reasons() {
+        let output = "Process 12345 stopped\n  \
+                      thread #1: tid = 12345, name = 'test' at /home/me/test.c:30\n\
+                      * thread #2: tid = 12346, name = 'worker', stop reason = breakpoint 1.1 at /home/me/worker.c:12\n  \
+                      thread #3: tid = 12347, stop reason = signal SIGSTOP\n";
+
+        let threads = super::parse_thread_list(output);
+
+        assert_eq!(threads.len(), 3);
+        assert_eq!(threads[0].stop_reason, None);
+        assert!(!threads[0].selected);
+        assert_eq!(
+            serde_json::to_value(&threads[1]).unwrap(),
+            serde_json::json!({
+                "index": 2,
+                "tid": 12346,
+                "name": "worker",
+                "file": "/home/me/worker.c",
+                "line": 12,
+                "stopReason": "breakpoint 1.1",
+                "selected": true,
+            })
+        );
+        assert_eq!(threads[2].stop_reason, Some("signal SIGSTOP".to_string()));
+        assert_eq!(threads[2].file, None);
+
+        // Listing the threads isn't taken as the program stopping again
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(output);
+        assert_eq!(analyser.stop_reason, None);
+    }
+
+    #[test]
+    fn check_parse_variable_children() {
+        let output = "(Test) t = {\n  \
+                      (int) a = 1\n  \
+                      (Inner) inner = {...}\n  \
+                      (int [2]) arr = {...}\n  \
+                      (int (*)(int)) f = 0x0000000100000f50\n\
+                      }\n";
+
+        let children = super::parse_variable_children("t", output);
+
+        assert_eq!(
+            serde_json::to_value(&children).unwrap(),
+            serde_json::json!([
+                {"name": "a", "type": "int", "value": "1"},
+                {"name": "inner", "type": "Inner", "value": "{...}", "reference": "t.inner"},
+                {"name": "arr", "type": "int [2]", "value": "{...}", "reference": "t.arr"},
+                {"name": "f", "type": "int (*)(int)", "value": "0x0000000100000f50"},
+            ])
+        );
+
+        let output = "(int [2]) arr = {\n  (int) [0] = 1\n  (Inner) [1] = {...}\n}\n";
+
+        let children = super::parse_variable_children("t.arr", output);
+
+        assert_eq!(children.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&children[1]).unwrap(),
+            serde_json::json!(
+                {"name": "[1]", "type": "Inner", "value": "{...}", "reference": "t.arr[1]"}
+            )
+        );
+    }
+
+    #[test]
+    fn check_breakpoints_set_are_recorded() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(
+            "Breakpoint 2: where = test`main + 15 at test.c:12:5, address = 0x0000000100000f2f\n",
+        );
+        analyser.analyse_stdout(
+            "Breakpoint 3: where = test`main + 30 at test.c:14, address = 0x0000000100000f3e\n",
+        );
+
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("/home/me/test.c".to_string(), 12)),
+            vec![2]
+        );
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("test.c".to_string(), 14)),
+            vec![3]
+        );
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("test.c".to_string(), 13)),
+            Vec::<u64>::new()
+        );
+    }
+
+    #[test]
+    fn check_parse_modules() {
+        let output =
+            "[  0] 5A1C3B4D-8E2F-3A6B-9C7D-1E2F3A4B5C6D 0x0000555555554000 /home/me/test \n\
+                      [  1] 9B2D4E6F 0x00007ffff7fc5000 /lib64/ld-linux-x86-64.so.2 \n      \
+                      /usr/lib/debug/.build-id/9b/2d4e6f.debug\n\
+                      [  2] 0x00007ffff7dd0000 /home/me/my libs/libutil.so \n";
+
+        assert_eq!(
+            serde_json::to_value(super::parse_modules(output)).unwrap(),
+            serde_json::json!([
+                {
+                    "name": "test",
+                    "path": "/home/me/test",
+                    "loadAddress": "0x0000555555554000",
+                    "uuid": "5A1C3B4D-8E2F-3A6B-9C7D-1E2F3A4B5C6D",
+                },
+                {
+                    "name": "ld-linux-x86-64.so.2",
+                    "path": "/lib64/ld-linux-x86-64.so.2",
+                    "loadAddress": "0x00007ffff7fc5000",
+                    "uuid": "9B2D4E6F",
+                },
+                {
+                    "name": "libutil.so",
+                    "path": "/home/me/my libs/libutil.so",
+                    "loadAddress": "0x00007ffff7dd0000",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn check_parse_sources() {
+        let output = "script for p in []: print(p)\n\
+                      /home/me/test.c\n\
+                      /home/me/my project/util.c\n\
+                      /home/me/test.c\n";
+
+        assert_eq!(
+            super::parse_sources(output),
+            vec![
+                "/home/me/my project/util.c".to_string(),
+                "/home/me/test.c".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn check_parse_breakpoints_deleted() {
+        assert_eq!(
+            super::parse_breakpoints_deleted("All breakpoints removed. (3 breakpoints)\n"),
+            Some(3)
+        );
+        assert_eq!(
+            super::parse_breakpoints_deleted("All breakpoints removed. (1 breakpoint)\n"),
+            Some(1)
+        );
+        assert_eq!(
+            super::parse_breakpoints_deleted("No breakpoints exist to be deleted.\n"),
+            Some(0)
+        );
+        assert_eq!(super::parse_breakpoints_deleted("error: bad\n"), None);
+    }
+
+    #[test]
+    fn check_parse_stack() {
+        let output = "script import json\n\
+                      [{\"index\": 0, \"file\": \"/home/me/test.c\", \"line\": 12, \"function\": \"add\", \"locals\": [{\"name\": \"a\", \"type\": \"int\", \"value\": \"1\", \"reference\": null}]}]\n";
+
+        assert_eq!(
+            super::parse_stack(output),
+            Some(serde_json::json!([{
+                "index": 0,
+                "file": "/home/me/test.c",
+                "line": 12,
+                "function": "add",
+                "locals": [{"name": "a", "type": "int", "value": "1", "reference": null}],
+            }]))
+        );
+
+        assert_eq!(super::parse_stack("error: invalid thread\n"), None);
+    }
+
+    #[test]
+    fn check_paths_with_spaces() {
+        assert_eq!(
+            super::quote_arg("/tmp/my project/main.c"),
+            "\"/tmp/my project/main.c\""
+        );
+        assert_eq!(super::quote_arg("a\"b\\c"), "\"a\\\"b\\\\c\"");
+
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(
+            "Breakpoint 2: where = test`main + 15 at main.c:12:5, address = 0x0000000100000f2f\n",
+        );
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new(
+                "/tmp/my project/main.c".to_string(),
+                12
+            )),
+            vec![2]
+        );
+
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, stop reason = breakpoint 2.1\n    \
+             frame #0 at /tmp/my project/main.c:12:5\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(
+                StopLocation::new(
+                    "/tmp/my project/main.c".to_string(),
+                    12,
+                    Some("breakpoint 2.1".to_string())
+                )
+                .with_column(Some(5))
+            )
+        );
+
+        let threads =
+            super::parse_thread_list("* thread #1: tid = 1 at /tmp/my project/main.c:12\n");
+        assert_eq!(threads[0].file, Some("/tmp/my project/main.c".to_string()));
+    }
+
+    #[test]
+    fn check_breakpoints_kept_across_restart() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(
+            "Breakpoint 2: where = test`main + 15 at test.c:12:5, address = 0x0000000100000f2f\n",
+        );
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+        analyser.analyse_stdout("Process 12345 exited with status = 0 (0x00000000) \n");
+        assert!(!analyser.is_process_running());
+        analyser.analyse_stdout("Process 12346 launched: '/home/me/test' (x86_64)\n");
+
+        assert!(analyser.is_process_running());
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("test.c".to_string(), 12)),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn check_watchpoint_set() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        analyser.add_listener(super::Listener::Watchpoint, tx);
+        analyser.analyse_stdout(
+            "Watchpoint created: Watchpoint 1: addr = 0x7ffeefbff5bc size = 4 state = enabled type = w\n    \
+             declare @ '/home/me/test.c:5'\n    \
+             watchpoint spec = 'a'\n    \
+             new value: 0\n",
+        );
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(event, Some(super::Event::WatchpointSet(1)));
+        assert_eq!(analyser.watchpoint_hit, None);
+    }
+
+    #[test]
+    fn check_write_watchpoint_hit() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(
+            "\n\
+             Watchpoint 1 hit:\n\
+             old value: 0\n\
+             new value: 1\n\
+             Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = watchpoint 1\n    \
+             frame #0 at /home/me/test.c:7\n",
+        );
+
+        assert_eq!(
+            analyser.watchpoint_hit,
+            Some((1, Some("0".to_string()), Some("1".to_string())))
+        );
+    }
+
+    #[test]
+    fn check_printed_variable_with_multibyte_characters() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.analyse_stdout("(const char *) s = 0x0000555555556004 \"héllo wörld ✓\"\n");
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(
+            event,
+            Some(super::Event::PrintVariable(
+                Variable::new("s".to_string()),
+                super::VariableValue::new(
+                    "const char *".to_string(),
+                    "0x0000555555556004 \"héllo wörld ✓\"".to_string()
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn check_global_variable_not_found() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.analyse_stderr("error: can't find global variable 'abc'\n");
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(
+            event,
+            Some(super::Event::VariableNotFound(Variable::new(
+                "abc".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn check_variable_in_other_frame_not_found() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.analyse_stdout("error: no variable named 'abc' found in this frame\n");
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(
+            event,
+            Some(super::Event::VariableNotFound(Variable::new(
+                "abc".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn check_listeners_dropped_when_lldb_terminates() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.debugger_terminated();
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(event, None);
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        analyser.add_listener(super::Listener::Breakpoint, tx);
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(event, None);
+    }
+
+    #[test]
+    fn check_stop_reports_breakpoint_hit_while_stepping() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        analyser.add_listener(super::Listener::Stopped, tx);
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = step over\n    \
+             frame #0 at /home/me/test.c:13\n",
+        );
+
+        let (event, rx) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(
+            event,
+            Some(super::Event::Stopped(
+                Some("step over".to_string()),
+                Some(FileLocation::new("/home/me/test.c".to_string(), 13))
+            ))
+        );
+        drop(rx);
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        analyser.add_listener(super::Listener::Stopped, tx);
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 2.1\n    \
+             frame #0 at /home/me/test.c:22\n",
+        );
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(
+            event,
+            Some(super::Event::Stopped(
+                Some("breakpoint 2.1".to_string()),
+                Some(FileLocation::new("/home/me/test.c".to_string(), 22))
+            ))
+        );
+    }
+
+    #[test]
+    fn check_location_recorded_until_exit() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+        assert_eq!(analyser.get_location(), None);
+
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 1.1\n    \
+             frame #0 at /home/me/test.c:12\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(StopLocation::new(
+                "/home/me/test.c".to_string(),
+                12,
+                Some("breakpoint 1.1".to_string())
+            ))
+        );
+
+        analyser.analyse_stdout("Process 12345 exited with status = 0 (0x00000000) \n");
+        assert_eq!(analyser.get_location(), None);
+    }
+
+    #[test]
+    fn check_entry_stop_only_at_main_breakpoint() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(
+            "Breakpoint 2: where = test`main + 15 at test.c:8:5, address = 0x0000000100000f4f\n",
+        );
+        analyser.entry_stop = analyser.last_breakpoint;
+        assert_eq!(analyser.entry_stop, Some(2));
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+
+        // A breakpoint hit before `main` isn't the program starting and forgets about it
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 1.1\n    \
+             frame #0 at /home/me/test.c:3:5\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(
+                StopLocation::new(
+                    "/home/me/test.c".to_string(),
+                    3,
+                    Some("breakpoint 1.1".to_string())
+                )
+                .with_column(Some(5))
+            )
+        );
+        assert_eq!(analyser.entry_stop, None);
+
+        analyser.entry_stop = Some(2);
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 2.1\n    \
+             frame #0 at /home/me/test.c:8:5\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(
+                StopLocation::new("/home/me/test.c".to_string(), 8, Some("entry".to_string()))
+                    .with_column(Some(5))
+            )
+        );
+
+        analyser.entry_stop = Some(2);
+        analyser.analyse_stdout("Process 12345 exited with status = 0 (0x00000000) \n");
+        assert_eq!(analyser.entry_stop, None);
+    }
+
+    #[test]
+    fn check_removed_listeners_not_sent_to() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = step over\n    \
+             frame #0 at /home/me/test.c:12\n",
+        );
+
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::Stopped, tx);
+        assert!(!analyser.is_stopped());
+        analyser.remove_listener(super::Listener::Stopped);
+        assert!(analyser.is_stopped());
+        drop(rx);
+
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::Raw, tx);
+        analyser.remove_listener(super::Listener::Raw);
+        drop(rx);
+        analyser.analyse_stderr("error: reverse execution isn't supported\n");
+        analyser.analyse_stdout("(lldb) ");
+    }
+
+    #[test]
+    fn check_stdin_held_until_started() {
+        let (tx, rx) = mpsc::channel(10);
+
+        tokio::run(future::lazy(move || {
+            let mut process = super::LLDBProcess::new("lldb".to_string(), vec![], vec![]);
+            process.lldb_stdin_tx = Some(tx);
+            process.write_stdin(Bytes::from("breakpoint set --file test.c --line 12\n"));
+            process.started(vec![
+                Bytes::from("settings set stop-line-count-after 0\n"),
+                Bytes::from("settings set stop-line-count-before 0\n"),
+            ]);
+            process.write_stdin(Bytes::from("process launch\n"));
+            Ok(())
+        }));
+
+        assert_eq!(
+            rx.collect().wait().unwrap(),
+            vec![
+                Bytes::from("settings set stop-line-count-after 0\n"),
+                Bytes::from("settings set stop-line-count-before 0\n"),
+                Bytes::from("breakpoint set --file test.c --line 12\n"),
+                Bytes::from("process launch\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_setup_cmds_sent_before_held_stdin() {
+        let (tx, rx) = mpsc::channel(10);
+
+        tokio::run(future::lazy(move || {
+            let mut process = super::LLDBProcess::new("lldb".to_string(), vec![], vec![]);
+            process.lldb_stdin_tx = Some(tx);
+            process.write_stdin(Bytes::from("breakpoint set --file test.c --line 12\n"));
+            process.send_setup_cmds(vec![Bytes::from("command alias bfl breakpoint set\n")]);
+            process.started(vec![]);
+            Ok(())
+        }));
+
+        assert_eq!(
+            rx.collect().wait().unwrap(),
+            vec![
+                Bytes::from("command alias bfl breakpoint set\n"),
+                Bytes::from("breakpoint set --file test.c --line 12\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_process_detached() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 1.1\n    \
+             frame #0 at /home/me/test.c:12\n",
+        );
+        assert_eq!(analyser.process_pid, Some(12345));
+
+        analyser.analyse_stdout("Process 12345 detached\n");
+        assert_eq!(analyser.process_pid, None);
+        assert_eq!(analyser.get_location(), None);
+    }
+
+    #[test]
+    fn check_stops_only_when_hit_condition_satisfied() {
+        let mut analyser = super::Analyser::new();
+        analyser.add_hit_condition(
+            FileLocation::new("/home/me/test.c".to_string(), 12),
+            HitCondition::Multiple(2),
+        );
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+
+        let hit = "Process 12345 stopped\n\
+                   * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 1.1\n    \
+                   frame #0 at /home/me/test.c:12\n";
+
+        analyser.analyse_stdout(hit);
+        assert_eq!(analyser.get_location(), None);
+
+        analyser.analyse_stdout(hit);
+        assert_eq!(
+            analyser.get_location(),
+            Some(StopLocation::new(
+                "/home/me/test.c".to_string(),
+                12,
+                Some("breakpoint 1.1".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn check_hit_condition_removed() {
+        let mut analyser = super::Analyser::new();
+        let file_location = FileLocation::new("/home/me/test.c".to_string(), 12);
+        analyser.add_hit_condition(file_location.clone(), HitCondition::Multiple(2));
+        analyser.remove_hit_condition(&file_location);
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = breakpoint 1.1\n    \
+             frame #0 at /home/me/test.c:12\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(StopLocation::new(
+                "/home/me/test.c".to_string(),
+                12,
+                Some("breakpoint 1.1".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn check_return_value_recorded() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = step out\n\
+             Return value: (int) $0 = 3\n\n    \
+             frame #0 at /home/me/test.c:22\n",
+        );
+        assert_eq!(
+            analyser.get_return_value(),
+            Some("(int) $0 = 3".to_string())
+        );
+
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = step over\n    \
+             frame #0 at /home/me/test.c:23\n",
+        );
+        assert_eq!(analyser.get_return_value(), None);
+    }
+
+    #[test]
+    fn check_location_records_column() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Process 12345 launched: '/home/me/test' (x86_64)\n");
+
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = step over\n    \
+             frame #0 at /home/me/test.c:12:5\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(
+                StopLocation::new(
+                    "/home/me/test.c".to_string(),
+                    12,
+                    Some("step over".to_string())
+                )
+                .with_column(Some(5))
+            )
+        );
+
+        // LLDB reports column 0 when it doesn't know
+        analyser.analyse_stdout(
+            "Process 12345 stopped\n\
+             * thread #1, queue = 'com.apple.main-thread', stop reason = step over\n    \
+             frame #0 at /home/me/test.c:13:0\n",
+        );
+        assert_eq!(
+            analyser.get_location(),
+            Some(StopLocation::new(
+                "/home/me/test.c".to_string(),
+                13,
+                Some("step over".to_string())
+            ))
+        );
+    }
 }
@@ -4,9 +4,10 @@
 
 use std::sync::{Arc, Mutex};
 
+use super::sourcemap::SourceMap;
 use super::ws::WSHandler;
-use crate::debugger::FileLocation;
-use crate::notifier::{breakpoint_set, jump_to_position, log_msg, signal_exited, LogLevel};
+use crate::debugger::{FileLocation, StopLocation};
+use crate::notifier::{breakpoint_verified, jump_to_position, log_msg, signal_exited, LogLevel};
 
 use tokio::prelude::*;
 use websocket::OwnedMessage;
@@ -17,6 +18,7 @@ pub struct Script {
     file: String,
     script_id: String,
     is_internal: bool,
+    source_map: Option<Arc<SourceMap>>,
 }
 
 impl Script {
@@ -25,20 +27,30 @@ impl Script {
             file,
             script_id,
             is_internal,
+            source_map: None,
         }
     }
-
-    pub fn get_script_id(&self) -> &str {
-        &self.script_id
-    }
 }
 
 #[derive(Debug)]
 pub struct Analyser {
     scripts: Vec<Script>,
-    pending_breakpoints: Vec<FileLocation>,
+    // Breakpoints in scripts that haven't been parsed yet with any condition for them
+    pending_breakpoints: Vec<(FileLocation, Option<String>)>,
+    // The ids Node gave every breakpoint it's set
+    breakpoint_ids: Arc<Mutex<Vec<String>>>,
     ws_handler: Arc<Mutex<WSHandler>>,
     pid: Option<u64>,
+    // Node doesn't say what the program exited with over the inspector so this is
+    // always 0 once it's exited
+    exit_code: Option<i64>,
+    source_maps: bool,
+    // Whether to stay paused when Node starts the program paused, see `--inspect-brk`
+    stop_on_entry: bool,
+    location: Option<StopLocation>,
+    call_frame_ids: Vec<String>,
+    // The id of the script paused in and the line in it, before any source map
+    paused_script: Option<(String, u64)>,
 }
 
 impl Analyser {
@@ -46,8 +58,15 @@ impl Analyser {
         Analyser {
             scripts: vec![],
             pending_breakpoints: vec![],
+            breakpoint_ids: Arc::new(Mutex::new(vec![])),
             ws_handler,
             pid: None,
+            exit_code: None,
+            source_maps: false,
+            stop_on_entry: true,
+            location: None,
+            call_frame_ids: vec![],
+            paused_script: None,
         }
     }
 
@@ -63,15 +82,17 @@ impl Analyser {
             "Runtime.consoleAPICalled" => {}
             "Runtime.executionContextCreated" => {}
             "Runtime.executionContextDestroyed" => {
-                match self.pid {
-                    Some(pid) => signal_exited(pid, 0),
-                    None => {}
-                };
+                // Node exits once we disconnect, which is when its exit code is known
+                self.location = None;
+                self.call_frame_ids.clear();
                 self.ws_handler.lock().unwrap().close()
             }
             "Runtime.exceptionThrown" => println!("TODO: Code {:?}", msg),
             "Debugger.paused" => self.analyse_debugger_paused(msg),
-            "Debugger.resumed" => {}
+            "Debugger.resumed" => {
+                self.location = None;
+                self.call_frame_ids.clear();
+            }
             "Debugger.scriptFailedToParse" => {
                 log_msg(LogLevel::WARN, &format!("Can't parse script: {:?}", msg))
             }
@@ -89,12 +110,120 @@ impl Analyser {
         None
     }
 
-    pub fn add_pending_breakpoint(&mut self, bkpt: FileLocation) {
-        self.pending_breakpoints.push(bkpt);
+    /// The files of the scripts Node has parsed, and the original sources of any with
+    /// source maps, leaving out Node's internal scripts
+    pub fn get_sources(&self) -> Vec<String> {
+        let mut sources = vec![];
+
+        for script in self.scripts.iter().filter(|s| !s.is_internal) {
+            sources.push(script.file.clone());
+            if let Some(source_map) = &script.source_map {
+                sources.extend(source_map.sources().iter().cloned());
+            }
+        }
+
+        sources.sort();
+        sources.dedup();
+
+        sources
+    }
+
+    /// Find the script and line (numbered from 0) to set a breakpoint on for a line in
+    /// a file, going through any source maps if the file isn't a script itself
+    pub fn get_script_location(&self, filename: &str, line_num: u64) -> Option<(String, u64)> {
+        if let Some(script) = self.get_script_from_filename(filename) {
+            return Some((script.script_id.clone(), line_num));
+        }
+
+        for script in &self.scripts {
+            if let Some(source_map) = &script.source_map {
+                if source_map.has_source(filename) {
+                    return source_map
+                        .generated_line(filename, line_num)
+                        .map(|l| (script.script_id.clone(), l));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Whether to use scripts' source maps, must be set before the scripts are parsed
+    pub fn set_source_maps(&mut self, source_maps: bool) {
+        self.source_maps = source_maps;
+    }
+
+    pub fn set_stop_on_entry(&mut self, stop_on_entry: bool) {
+        self.stop_on_entry = stop_on_entry;
+    }
+
+    pub fn add_pending_breakpoint(&mut self, bkpt: FileLocation, condition: Option<String>) {
+        self.pending_breakpoints.push((bkpt, condition));
     }
 
+    /// Record the id Node gave a breakpoint in its response to setting it
+    pub fn add_breakpoint(&self, response: &serde_json::Value) {
+        if let Some(id) = response["result"]["breakpointId"].as_str() {
+            self.breakpoint_ids.lock().unwrap().push(id.to_string());
+        }
+    }
+
+    /// Forget every breakpoint, returning the ids of the ones Node has set and how many
+    /// were still waiting for their script
+    pub fn take_breakpoints(&mut self) -> (Vec<String>, usize) {
+        let pending = self.pending_breakpoints.len();
+        self.pending_breakpoints.clear();
+        (
+            self.breakpoint_ids.lock().unwrap().drain(..).collect(),
+            pending,
+        )
+    }
+
+    /// The program's been run, anything known about it exiting before is forgotten
     pub fn set_pid(&mut self, pid: u64) {
         self.pid = Some(pid);
+        self.exit_code = None;
+    }
+
+    pub fn get_pid(&self) -> Option<u64> {
+        self.pid
+    }
+
+    /// Forget about the program once we've stopped debugging it
+    pub fn detached(&mut self) {
+        if let Some(pid) = self.pid.take() {
+            log_msg(LogLevel::INFO, &format!("Detached from process {}", pid));
+        }
+        self.location = None;
+        self.call_frame_ids.clear();
+        self.breakpoint_ids.lock().unwrap().clear();
+    }
+
+    /// The program's exited, unless we've detached from it
+    pub fn process_exited(&mut self, exit_code: i64) {
+        if let Some(pid) = self.pid.take() {
+            self.exit_code = Some(exit_code);
+            signal_exited(pid, exit_code);
+        }
+    }
+
+    pub fn get_exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    /// Where the program is paused, if it is
+    pub fn get_location(&self) -> Option<StopLocation> {
+        self.location.clone()
+    }
+
+    /// The id of the script the program's paused in and the line it's paused at in it
+    pub fn get_paused_script(&self) -> Option<(String, u64)> {
+        self.location.as_ref().and(self.paused_script.clone())
+    }
+
+    /// The id Node gave the frame while paused, counting up from the current frame at 0
+    pub fn get_call_frame_id(&self, frame: u64) -> Option<String> {
+        self.call_frame_ids.get(frame as usize).cloned()
     }
 
     fn analyse_script_parsed(&mut self, mut msg: serde_json::Value) {
@@ -121,30 +250,62 @@ impl Analyser {
             }
         };
 
+        let mut script = Script::new(file, script_id, is_internal);
+
+        match msg["params"]["sourceMapURL"].as_str() {
+            Some(url) if self.source_maps && !is_internal && !url.is_empty() => {
+                match SourceMap::load(&script.file, url) {
+                    Ok(source_map) => script.source_map = Some(Arc::new(source_map)),
+                    Err(e) => log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't load source map for {}: {}", script.file, e),
+                    ),
+                }
+            }
+            _ => {}
+        }
+
+        let file = script.file.clone();
+        let script_id = script.script_id.clone();
+
         // TODO: drain_filter if/when it's stable in Rust
         let mut i = 0;
 
         while i != self.pending_breakpoints.len() {
-            if self.pending_breakpoints[i].name == file {
-                let bkpt = self.pending_breakpoints.remove(i);
-
-                let msg = OwnedMessage::Text(format!(
-                    "{{\
-                     \"method\":\"Debugger.setBreakpoint\",\
-                     \"params\":{{\
-                     \"location\":{{\
-                     \"scriptId\":\"{}\",\
-                     \"lineNumber\":{}\
-                     }}\
-                     }}\
-                     }}",
-                    script_id,
-                    bkpt.line_num - 1
-                ));
-
-                let file = file.clone();
+            let source_map = match &script.source_map {
+                Some(sm) if sm.has_source(&self.pending_breakpoints[i].0.name) => Some(sm.clone()),
+                _ => None,
+            };
+
+            if self.pending_breakpoints[i].0.name == file || source_map.is_some() {
+                let (bkpt, condition) = self.pending_breakpoints.remove(i);
+
+                // Node numbers lines from 0
+                let node_line_num = match bkpt.line_num.checked_sub(1) {
+                    Some(l) => match &source_map {
+                        Some(sm) => sm.generated_line(&bkpt.name, l),
+                        None => Some(l),
+                    },
+                    None => None,
+                };
+
+                let node_line_num = match node_line_num {
+                    Some(l) => l,
+                    None => {
+                        log_msg(
+                            LogLevel::ERROR,
+                            &format!("Bad line number for breakpoint {:?}", bkpt),
+                        );
+                        continue;
+                    }
+                };
+
+                let msg = set_breakpoint_message(&script_id, node_line_num, condition.as_ref());
+
+                let file = bkpt.name.clone();
 
                 let ws_handler = self.ws_handler.clone();
+                let breakpoint_ids = self.breakpoint_ids.clone();
 
                 tokio::spawn(
                     ws_handler
@@ -152,22 +313,21 @@ impl Analyser {
                         .unwrap()
                         .send_and_receive_message(msg)
                         .map(move |response| {
-                            if response["error"].is_null() {
-                                breakpoint_set(&file, bkpt.line_num);
-                            } else {
-                                log_msg(
-                                    LogLevel::CRITICAL,
-                                    &format!("Can't set breakpoint {:?}", bkpt),
-                                );
-                                panic!("Can't set breakpoint, panicking");
+                            if let Some(id) = response["result"]["breakpointId"].as_str() {
+                                breakpoint_ids.lock().unwrap().push(id.to_string());
                             }
+                            pending_breakpoint_response(
+                                &file,
+                                &bkpt,
+                                &response,
+                                source_map.as_deref(),
+                            );
                         })
                         .map_err(|e| {
                             log_msg(
-                                LogLevel::CRITICAL,
+                                LogLevel::ERROR,
                                 &format!("Can't set breakpoint, error: {}", e),
                             );
-                            panic!("Can't set breakpoint, panicking");
                         }),
                 );
             } else {
@@ -175,18 +335,28 @@ impl Analyser {
             }
         }
 
-        self.scripts.push(Script::new(file, script_id, is_internal));
+        self.scripts.push(script);
     }
 
-    fn analyse_debugger_paused(&self, mut msg: serde_json::Value) {
+    fn analyse_debugger_paused(&mut self, mut msg: serde_json::Value) {
+        self.call_frame_ids = msg["params"]["callFrames"]
+            .as_array()
+            .map(|frames| {
+                frames
+                    .iter()
+                    .filter_map(|f| f["callFrameId"].as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
         let file: String =
             match serde_json::from_value(msg["params"]["callFrames"][0]["url"].take()) {
                 Ok(s) => {
-                    let mut s: String = s;
-                    if s.len() > 7 && &s[0..7] == "file://" {
-                        s = s[7..].to_string()
+                    let s: String = s;
+                    match s.starts_with("file://") {
+                        true => s["file://".len()..].to_string(),
+                        false => s,
                     }
-                    s
                 }
                 Err(e) => {
                     // TODO: How do we get here? Handle when we see it.
@@ -197,17 +367,156 @@ impl Analyser {
         let line_num: u64 = match serde_json::from_value(
             msg["params"]["callFrames"][0]["location"]["lineNumber"].take(),
         ) {
-            Ok(s) => {
-                let s: u64 = s;
-                s + 1
-            }
+            Ok(s) => s,
             Err(e) => {
                 panic!("Can't understand line_num: {:?}", e);
             }
         };
 
-        jump_to_position(&file, line_num);
+        let column_num = msg["params"]["callFrames"][0]["location"]["columnNumber"].as_u64();
+
+        let script_id = msg["params"]["callFrames"][0]["location"]["scriptId"].take();
+
+        self.paused_script = script_id.as_str().map(|id| (id.to_string(), line_num + 1));
+
+        // Jump to the original source if we have a source map for the script
+        let original_position = self
+            .scripts
+            .iter()
+            .find(|s| script_id == s.script_id.as_str())
+            .and_then(|s| s.source_map.as_ref())
+            .and_then(|sm| sm.original_position(line_num))
+            .map(|(f, l)| (f.to_string(), l));
+
+        // Node pauses when it starts the program as it's run with `--inspect-brk`
+        let reason = match msg["params"]["reason"].as_str() {
+            Some("Break on start") if !self.stop_on_entry => {
+                let msg = OwnedMessage::Text("{\"method\":\"Debugger.resume\"}".to_string());
+                tokio::spawn(
+                    self.ws_handler
+                        .lock()
+                        .unwrap()
+                        .send_and_receive_message(msg)
+                        .map(|_| ())
+                        .map_err(|e| eprintln!("Can't resume Node: {}", e)),
+                );
+                return;
+            }
+            Some("Break on start") => Some("entry".to_string()),
+            reason => reason.map(|s| s.to_string()),
+        };
+
+        // Only lines are mapped by source maps so the column is only known when stopped
+        // in the script itself
+        let column_num = match original_position {
+            Some(_) => None,
+            None => column_num,
+        };
+
+        // Node numbers lines and columns from 0
+        let (file, line_num) = original_position.unwrap_or((file, line_num));
+        let column_num = column_num.map(|c| c + 1);
+        jump_to_position(&file, line_num + 1, column_num);
+        self.location = Some(StopLocation::new(file, line_num + 1, reason).with_column(column_num));
+    }
+}
+
+/// The message to set a breakpoint in a script, Node numbers lines from 0
+///
+/// When there's a condition the breakpoint only stops when it's true.
+pub fn set_breakpoint_message(
+    script_id: &str,
+    line_num: u64,
+    condition: Option<&String>,
+) -> OwnedMessage {
+    let mut msg = serde_json::json!({
+        "method": "Debugger.setBreakpoint",
+        "params": {
+            "location": {
+                "scriptId": script_id,
+                "lineNumber": line_num,
+            },
+        },
+    });
+
+    if let Some(condition) = condition {
+        msg["params"]["condition"] = serde_json::json!(condition);
     }
+
+    OwnedMessage::Text(msg.to_string())
+}
+
+/// Handle Node's response to setting a pending breakpoint, returning the line the
+/// breakpoint was actually set on if it was
+///
+/// If the breakpoint was set through a source map the line is mapped back through it.
+fn pending_breakpoint_response(
+    file: &str,
+    bkpt: &FileLocation,
+    response: &serde_json::Value,
+    source_map: Option<&SourceMap>,
+) -> Option<u64> {
+    if !response["error"].is_null() {
+        log_msg(
+            LogLevel::ERROR,
+            &format!("Can't set breakpoint {:?}: {}", bkpt, response["error"]),
+        );
+        return None;
+    }
+
+    // Node numbers lines from 0
+    let resolved_line = response["result"]["actualLocation"]["lineNumber"]
+        .as_u64()
+        .and_then(|l| match source_map {
+            Some(sm) => sm.original_position(l).map(|(_, l)| l),
+            None => Some(l),
+        })
+        .and_then(|l| l.checked_add(1));
+
+    match resolved_line {
+        Some(l) => breakpoint_verified(file, bkpt.line_num, l),
+        None => log_msg(
+            LogLevel::WARN,
+            &format!("Breakpoint {:?} couldn't be resolved", bkpt),
+        ),
+    };
+
+    resolved_line
+}
+
+/// The response to printing a variable from Node's response to evaluating it by value
+///
+/// Node reporting an error, or the variable throwing an exception when evaluated, is
+/// logged and gives an `ERROR` response. Only the first line of an exception is logged,
+/// not its stack.
+pub fn print_response(variable: &str, response: &serde_json::Value) -> serde_json::Value {
+    let error = match (&response["error"], &response["result"]["exceptionDetails"]) {
+        (serde_json::Value::Null, serde_json::Value::Null) => None,
+        (serde_json::Value::Null, _) => Some(&response["result"]["result"]["description"]),
+        (error, _) => Some(&error["message"]),
+    };
+
+    if let Some(error) = error {
+        log_msg(
+            LogLevel::WARN,
+            &format!(
+                "Can't print '{}': {}",
+                variable,
+                error
+                    .as_str()
+                    .and_then(|e| e.lines().next())
+                    .unwrap_or("unknown error")
+            ),
+        );
+        return serde_json::json!({"status":"ERROR"});
+    }
+
+    serde_json::json!({
+        "status": "OK",
+        "type": response["result"]["result"]["type"],
+        "variable": variable,
+        "value": response["result"]["result"]["value"],
+    })
 }
 
 #[cfg(test)]
@@ -216,6 +525,7 @@ mod tests {
 
     use super::super::ws::WSHandler;
     use super::Analyser;
+    use crate::debugger::{FileLocation, StopLocation};
 
     #[test]
     fn check_internal_script_parsed() {
@@ -313,6 +623,123 @@ mod tests {
         assert_eq!(analyser.scripts[0].is_internal, false);
     }
 
+    #[test]
+    fn check_paused_records_column() {
+        let msg = serde_json::json!(
+            {
+              "method":"Debugger.paused",
+              "params":{
+                "callFrames":[{
+                  "url":"file:///home/me/test.js",
+                  "location":{"scriptId":"52","lineNumber":9,"columnNumber":4}
+                }],
+                "reason":"other"
+              }
+            }
+        );
+
+        let ws = Arc::new(Mutex::new(WSHandler::new()));
+        let mut analyser = Analyser::new(ws);
+
+        analyser.analyse_message(msg);
+
+        assert_eq!(
+            analyser.get_location(),
+            Some(
+                StopLocation::new(
+                    "/home/me/test.js".to_string(),
+                    10,
+                    Some("other".to_string())
+                )
+                .with_column(Some(5))
+            )
+        );
+    }
+
+    #[test]
+    fn check_pending_breakpoint_response() {
+        let bkpt = FileLocation::new("/home/me/test.js".to_string(), 12);
+
+        let response = serde_json::json!({
+            "id": 3,
+            "result": {
+                "breakpointId": "1:11:0:52",
+                "actualLocation": {"scriptId": "52", "lineNumber": 12, "columnNumber": 4}
+            }
+        });
+        assert_eq!(
+            super::pending_breakpoint_response("/home/me/test.js", &bkpt, &response, None),
+            Some(13)
+        );
+
+        let response = serde_json::json!({
+            "id": 4,
+            "error": {"code": -32000, "message": "Breakpoint at specified location already exists."}
+        });
+        assert_eq!(
+            super::pending_breakpoint_response("/home/me/test.js", &bkpt, &response, None),
+            None
+        );
+
+        let response = serde_json::json!({"id": 5, "result": {"breakpointId": "1:11:0:52"}});
+        assert_eq!(
+            super::pending_breakpoint_response("/home/me/test.js", &bkpt, &response, None),
+            None
+        );
+    }
+
+    #[test]
+    fn check_exit_code_recorded_until_run_again() {
+        let ws = Arc::new(Mutex::new(WSHandler::new()));
+        let mut analyser = Analyser::new(ws);
+
+        analyser.set_pid(12345);
+        analyser.process_exited(1);
+        assert_eq!(analyser.get_exit_code(), Some(1));
+        assert_eq!(analyser.get_pid(), None);
+
+        analyser.set_pid(12346);
+        assert_eq!(analyser.get_exit_code(), None);
+    }
+
+    #[test]
+    fn check_print_response() {
+        let response = serde_json::json!({
+            "id": 7,
+            "result": {"result": {"type": "number", "value": 42, "description": "42"}}
+        });
+        assert_eq!(
+            super::print_response("a", &response),
+            serde_json::json!({"status": "OK", "type": "number", "variable": "a", "value": 42})
+        );
+
+        let response = serde_json::json!({
+            "id": 8,
+            "result": {
+                "result": {
+                    "type": "object",
+                    "subtype": "error",
+                    "className": "ReferenceError",
+                    "description": "ReferenceError: b is not defined\n    at eval (eval at <anonymous>)"
+                },
+                "exceptionDetails": {"exceptionId": 1, "text": "Uncaught", "lineNumber": 0}
+            }
+        });
+        assert_eq!(
+            super::print_response("b", &response),
+            serde_json::json!({"status": "ERROR"})
+        );
+
+        let response = serde_json::json!({
+            "id": 9,
+            "error": {"code": -32000, "message": "Could not find call frame with given id"}
+        });
+        assert_eq!(
+            super::print_response("a", &response),
+            serde_json::json!({"status": "ERROR"})
+        );
+    }
+
     #[test]
     fn test_get_existing_script_from_filename() {
         let ws = Arc::new(Mutex::new(WSHandler::new()));
@@ -326,6 +753,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_sources_leaves_out_internal_scripts() {
+        let ws = Arc::new(Mutex::new(WSHandler::new()));
+        let mut analyser = Analyser::new(ws);
+        analyser.scripts.push(super::Script::new(
+            "/home/me/test.js".to_string(),
+            "52".to_string(),
+            false,
+        ));
+        analyser.scripts.push(super::Script::new(
+            "internal/main.js".to_string(),
+            "7".to_string(),
+            true,
+        ));
+        analyser.scripts.push(super::Script::new(
+            "/home/me/lib.js".to_string(),
+            "53".to_string(),
+            false,
+        ));
+
+        assert_eq!(
+            analyser.get_sources(),
+            vec![
+                "/home/me/lib.js".to_string(),
+                "/home/me/test.js".to_string()
+            ]
+        );
+    }
+
     #[test]
     fn test_get_no_script_from_filename() {
         let ws = Arc::new(Mutex::new(WSHandler::new()));
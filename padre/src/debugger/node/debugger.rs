@@ -10,12 +10,16 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use super::analyser::Analyser;
+use super::analyser::{print_response, set_breakpoint_message, Analyser};
 use super::process::Process;
 use super::ws::WSHandler;
 use crate::config::Config;
-use crate::debugger::{DebuggerV1, FileLocation, Variable};
-use crate::notifier::{breakpoint_set, log_msg, LogLevel};
+use crate::debugger::{
+    not_running, not_supported, parse_log_message, Capabilities, DebuggerV1, FileLocation,
+    LogMessagePart, ProgramStatus, StopLocation, Variable, VariableChild, LOGPOINT_PREFIX,
+};
+use crate::notifier::{breakpoint_pending, breakpoint_set, debugger_ready, log_msg, LogLevel};
+use crate::util::truncate_json_value;
 
 use tokio::prelude::*;
 use tokio::sync::mpsc;
@@ -29,8 +33,16 @@ pub struct ImplDebugger {
 }
 
 impl ImplDebugger {
-    pub fn new(debugger_cmd: String, run_cmd: Vec<String>) -> ImplDebugger {
-        let process = Arc::new(Mutex::new(Process::new(debugger_cmd, run_cmd)));
+    pub fn new(
+        debugger_cmd: String,
+        run_cmd: Vec<String>,
+        debugger_env: Vec<(String, String)>,
+    ) -> ImplDebugger {
+        let process = Arc::new(Mutex::new(Process::new(
+            debugger_cmd,
+            run_cmd,
+            debugger_env,
+        )));
         let ws_handler = Arc::new(Mutex::new(WSHandler::new()));
         let analyser = Arc::new(Mutex::new(Analyser::new(ws_handler.clone())));
         ImplDebugger {
@@ -39,13 +51,259 @@ impl ImplDebugger {
             analyser,
         }
     }
+
+    /// Send the stepping method the number of times requested, giving up at the first error
+    fn step(
+        &mut self,
+        method: &str,
+        count: u64,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let ws_handler = self.ws_handler.clone();
+        let method = method.to_string();
+
+        let f = future::loop_fn(count, move |remaining| {
+            let msg = OwnedMessage::Text(format!("{{\"method\":\"{}\"}}", method));
+
+            ws_handler
+                .lock()
+                .unwrap()
+                .send_and_receive_message(msg)
+                .map(move |response| {
+                    if !response["error"].is_null() {
+                        future::Loop::Break(serde_json::json!({"status":"ERROR"}))
+                    } else if remaining > 1 {
+                        future::Loop::Continue(remaining - 1)
+                    } else {
+                        future::Loop::Break(serde_json::json!({"status":"OK"}))
+                    }
+                })
+        });
+
+        Box::new(f)
+    }
+
+    /// Set a breakpoint, with a JavaScript condition deciding whether it stops if given
+    fn set_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        condition: Option<String>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        // Node numbers lines from 0
+        let node_line_num = match file_location.line_num.checked_sub(1) {
+            Some(l) => l,
+            None => {
+                log_msg(
+                    LogLevel::ERROR,
+                    &format!("Bad line number {}", file_location.line_num),
+                );
+                return Box::new(future::lazy(move || {
+                    let resp = serde_json::json!({"status":"ERROR"});
+                    Ok(resp)
+                }));
+            }
+        };
+
+        let full_file_name = Path::new(&file_location.name).canonicalize();
+        let f = match full_file_name {
+            Ok(s) => {
+                let filename = s.to_string_lossy().to_string();
+                let mut analyser = self.analyser.lock().unwrap();
+                match analyser.get_script_location(&filename, node_line_num) {
+                    Some((script_id, node_line_num)) => {
+                        let msg =
+                            set_breakpoint_message(&script_id, node_line_num, condition.as_ref());
+
+                        let line_num = file_location.line_num;
+                        let analyser = self.analyser.clone();
+
+                        self.ws_handler
+                            .lock()
+                            .unwrap()
+                            .send_and_receive_message(msg)
+                            .map(move |response| {
+                                if response["error"].is_null() {
+                                    analyser.lock().unwrap().add_breakpoint(&response);
+
+                                    // Node numbers columns from 0
+                                    let column = response["result"]["actualLocation"]
+                                        ["columnNumber"]
+                                        .as_u64()
+                                        .map(|c| c + 1);
+                                    breakpoint_set(&filename, line_num, column);
+
+                                    serde_json::json!({"status":"OK"})
+                                } else {
+                                    serde_json::json!({"status":"ERROR"})
+                                }
+                            })
+                    }
+                    None => {
+                        breakpoint_pending(&filename, file_location.line_num);
+                        analyser.add_pending_breakpoint(
+                            FileLocation::new(filename, file_location.line_num),
+                            condition,
+                        );
+
+                        return Box::new(future::lazy(move || {
+                            let resp = serde_json::json!({"status":"PENDING"});
+                            Ok(resp)
+                        }));
+                    }
+                }
+            }
+            Err(e) => {
+                log_msg(
+                    LogLevel::ERROR,
+                    &format!("Can't find file {}: {}", file_location.name, e),
+                );
+
+                return Box::new(future::lazy(move || {
+                    let resp = serde_json::json!({"status":"ERROR"});
+                    Ok(resp)
+                }));
+            }
+        };
+
+        Box::new(f)
+    }
+}
+
+/// The condition for a logpoint, logs the message with its expressions evaluated and is
+/// always false so it never stops
+fn log_condition(message: &str) -> String {
+    let mut parts = vec![serde_json::to_string(LOGPOINT_PREFIX).unwrap()];
+    for part in parse_log_message(message) {
+        match part {
+            LogMessagePart::Text(s) => parts.push(serde_json::to_string(&s).unwrap()),
+            LogMessagePart::Expression(e) => parts.push(format!("String(({}))", e)),
+        }
+    }
+
+    format!("console.log({}), false", parts.join(" + "))
+}
+
+/// The id older versions of Node give the top frame, used if Node hasn't told us the id
+const TOP_CALL_FRAME_ID: &str = "{\"ordinal\":0,\"injectedScriptId\":1}";
+
+/// Evaluate an expression in the frame with the id given
+///
+/// When `return_by_value` is false objects are returned as a remote object with an
+/// `objectId` rather than as a JSON value.
+fn evaluate_message(expression: &str, call_frame_id: &str, return_by_value: bool) -> OwnedMessage {
+    OwnedMessage::Text(
+        serde_json::json!({
+            "method": "Debugger.evaluateOnCallFrame",
+            "params": {
+                "callFrameId": call_frame_id,
+                "expression": expression,
+                "returnByValue": return_by_value,
+            },
+        })
+        .to_string(),
+    )
+}
+
+/// Whether a remote object as returned by `Debugger.evaluateOnCallFrame` is truthy in
+/// JavaScript
+fn is_truthy(result: &serde_json::Value) -> bool {
+    match (result["type"].as_str(), result["subtype"].as_str()) {
+        (Some("undefined"), _) | (Some("object"), Some("null")) => false,
+        (Some("boolean"), _) => result["value"] == true,
+        (Some("string"), _) => result["value"] != "",
+        // NaN, -0 and big integers have an `unserializableValue` instead of a value
+        (Some("number"), _) | (Some("bigint"), _) => match result["value"].as_f64() {
+            Some(n) => n != 0.0,
+            None => !matches!(
+                result["unserializableValue"].as_str(),
+                Some("NaN") | Some("-0") | Some("0n")
+            ),
+        },
+        _ => true,
+    }
+}
+
+/// Convert a property as returned by `Runtime.getProperties` to a child, properties
+/// with getters and setters rather than values are left out
+fn property_to_child(property: &serde_json::Value, max_bytes: usize) -> Option<VariableChild> {
+    let name = property["name"].as_str()?.to_string();
+    let value = &property["value"];
+    let type_ = value["type"].as_str()?.to_string();
+
+    let reference = match (&type_[..], value["objectId"].as_str()) {
+        ("object", Some(id)) => Some(id.to_string()),
+        _ => None,
+    };
+
+    let value = match value.get("value") {
+        Some(v) => v.clone(),
+        None => value["description"].clone(),
+    };
+
+    Some(VariableChild::new(
+        name,
+        type_,
+        truncate_json_value(value, max_bytes),
+        reference,
+    ))
 }
 
 impl DebuggerV1 for ImplDebugger {
-    fn setup(&mut self) {}
+    fn setup(&mut self) -> Result<(), io::Error> {
+        // Node isn't started until the program is run
+        debugger_ready();
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new(
+            "node",
+            vec![
+                "run",
+                "breakpoint",
+                "clearAllBreakpoints",
+                "stepIn",
+                "stepOver",
+                "continue",
+                "interrupt",
+                "detach",
+                "print",
+                "assert",
+                "typeOf",
+                "getChildren",
+                "listSources",
+                "frameSource",
+                "raw",
+                "currentLocation",
+                "exceptionBreakpoint",
+                "setCwd",
+            ],
+        )
+    }
+
+    fn pid(&self) -> Option<u64> {
+        self.analyser.lock().unwrap().get_pid()
+    }
+
+    fn program_status(&self) -> ProgramStatus {
+        let analyser = self.analyser.lock().unwrap();
+        match analyser.get_pid() {
+            None => ProgramStatus::NotRunning,
+            Some(_) if analyser.get_location().is_some() => ProgramStatus::Stopped,
+            Some(_) => ProgramStatus::Running,
+        }
+    }
 
-    fn teardown(&mut self) {
-        exit(0);
+    fn location(&self) -> Option<StopLocation> {
+        self.analyser.lock().unwrap().get_location()
+    }
+
+    fn teardown(&mut self, exit_code: i32) {
+        self.process.lock().unwrap().teardown();
+        exit(exit_code);
+    }
+
+    fn exit_code(&self) -> Option<i64> {
+        self.analyser.lock().unwrap().get_exit_code()
     }
 
     fn run(
@@ -54,9 +312,21 @@ impl DebuggerV1 for ImplDebugger {
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
         log_msg(LogLevel::INFO, "Launching process");
 
+        let source_maps = config.lock().unwrap().get_config("SourceMaps") == Some(1);
+        self.analyser.lock().unwrap().set_source_maps(source_maps);
+
+        let stop_on_entry = config.lock().unwrap().get_config("StopOnEntry") == Some(1);
+        self.analyser
+            .lock()
+            .unwrap()
+            .set_stop_on_entry(stop_on_entry);
+
         let (tx, rx) = mpsc::channel(1);
 
-        self.process.lock().unwrap().run(tx);
+        match self.process.lock().unwrap().run(tx) {
+            Ok(_) => {}
+            Err(e) => return Box::new(future::lazy(move || Err(e))),
+        };
 
         let process = self.process.clone();
         let analyser = self.analyser.clone();
@@ -119,12 +389,15 @@ impl DebuggerV1 for ImplDebugger {
                 } else {
                     let pid = process.lock().unwrap().get_pid();
                     analyser2.lock().unwrap().set_pid(pid);
+                    tokio::spawn(process.lock().unwrap().wait_for_exit().map(move |code| {
+                        analyser2.lock().unwrap().process_exited(code);
+                    }));
                     serde_json::json!({"status":"OK","pid":pid})
                 }
             })
             .map_err(|e| {
                 eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out setting breakpoint")
+                io::Error::new(io::ErrorKind::TimedOut, "Timed out setting breakpoint")
             });
 
         Box::new(f)
@@ -135,74 +408,96 @@ impl DebuggerV1 for ImplDebugger {
         file_location: &FileLocation,
         _: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let full_file_name = Path::new(&file_location.name).canonicalize();
-        let f = match full_file_name {
-            Ok(s) => {
-                let filename = s.to_string_lossy().to_string();
-                let mut analyser = self.analyser.lock().unwrap();
-                match analyser.get_script_from_filename(&filename) {
-                    Some(script) => {
-                        let msg = OwnedMessage::Text(format!(
-                            "{{\
-                             \"method\":\"Debugger.setBreakpoint\",\
-                             \"params\":{{\
-                             \"location\":{{\
-                             \"scriptId\":\"{}\",\
-                             \"lineNumber\":{}\
-                             }}\
-                             }}\
-                             }}",
-                            script.get_script_id(),
-                            file_location.line_num - 1
-                        ));
+        self.set_breakpoint(file_location, None)
+    }
 
-                        let line_num = file_location.line_num;
+    fn logpoint(
+        &mut self,
+        file_location: &FileLocation,
+        message: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.set_breakpoint(file_location, Some(log_condition(message)))
+    }
 
-                        self.ws_handler
-                            .lock()
-                            .unwrap()
-                            .send_and_receive_message(msg)
-                            .map(move |response| {
-                                if response["error"].is_null() {
-                                    breakpoint_set(&filename, line_num);
+    fn disable_breakpoint(
+        &mut self,
+        _: &FileLocation,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        // The inspector protocol has no way of disabling a single breakpoint
+        not_supported("Disabling and enabling breakpoints")
+    }
 
-                                    serde_json::json!({"status":"OK"})
-                                } else {
-                                    serde_json::json!({"status":"ERROR"})
-                                }
-                            })
-                    }
-                    None => {
-                        analyser.add_pending_breakpoint(FileLocation::new(
-                            filename,
-                            file_location.line_num,
-                        ));
+    fn enable_breakpoint(
+        &mut self,
+        _: &FileLocation,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        // The inspector protocol has no way of disabling a single breakpoint
+        not_supported("Disabling and enabling breakpoints")
+    }
 
-                        return Box::new(future::lazy(move || {
-                            let resp = serde_json::json!({"status":"PENDING"});
-                            Ok(resp)
-                        }));
-                    }
-                }
-            }
-            Err(e) => {
+    /// Breakpoints waiting for their script to be parsed are just forgotten about
+    fn clear_all_breakpoints(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let (ids, pending) = self.analyser.lock().unwrap().take_breakpoints();
+
+        let removals: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                let msg = serde_json::json!({
+                    "method": "Debugger.removeBreakpoint",
+                    "params": {"breakpointId": id},
+                });
+                self.ws_handler
+                    .lock()
+                    .unwrap()
+                    .send_and_receive_message(OwnedMessage::Text(msg.to_string()))
+            })
+            .collect();
+
+        let f = future::join_all(removals).map(move |responses| {
+            for response in responses.iter().filter(|r| !r["error"].is_null()) {
                 log_msg(
-                    LogLevel::ERROR,
-                    &format!("Can't find file {}: {}", file_location.name, e),
+                    LogLevel::WARN,
+                    &format!("Can't remove breakpoint: {}", response["error"]["message"]),
                 );
-
-                return Box::new(future::lazy(move || {
-                    let resp = serde_json::json!({"status":"ERROR"});
-                    Ok(resp)
-                }));
             }
-        };
+            let removed = responses.iter().filter(|r| r["error"].is_null()).count() + pending;
+            log_msg(LogLevel::INFO, &format!("Removed {} breakpoints", removed));
+            serde_json::json!({"status":"OK"})
+        });
 
         Box::new(f)
     }
 
-    fn step_in(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let msg = OwnedMessage::Text("{\"method\":\"Debugger.stepInto\"}".to_string());
+    /// Node reports every pause so `report_each` makes no difference
+    fn step_in(
+        &mut self,
+        count: u64,
+        _: bool,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("Debugger.stepInto", count)
+    }
+
+    fn step_over(
+        &mut self,
+        count: u64,
+        _: bool,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("Debugger.stepOver", count)
+    }
+
+    fn continue_(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let msg = OwnedMessage::Text("{\"method\":\"Debugger.resume\"}".to_string());
 
         let f = self
             .ws_handler
@@ -220,36 +515,107 @@ impl DebuggerV1 for ImplDebugger {
         Box::new(f)
     }
 
-    fn step_over(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let msg = OwnedMessage::Text("{\"method\":\"Debugger.stepOver\"}".to_string());
+    fn interrupt(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("Debugger.pause", 1)
+    }
+
+    /// Disabling the debugger removes the breakpoints and resumes the program, which then
+    /// carries on without a debugger once the websocket's closed
+    fn detach(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if self.analyser.lock().unwrap().get_pid().is_none() {
+            return not_running();
+        }
+
+        let msg = OwnedMessage::Text("{\"method\":\"Debugger.disable\"}".to_string());
+
+        let ws_handler = self.ws_handler.clone();
+        let analyser = self.analyser.clone();
 
         let f = self
             .ws_handler
             .lock()
             .unwrap()
             .send_and_receive_message(msg)
-            .map(|response| {
-                if response["error"].is_null() {
-                    serde_json::json!({"status":"OK"})
-                } else {
-                    serde_json::json!({"status":"ERROR"})
+            .map(move |response| {
+                if !response["error"].is_null() {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't detach from Node: {}", response["error"]["message"]),
+                    );
+                    return serde_json::json!({"status":"ERROR"});
                 }
+
+                ws_handler.lock().unwrap().close();
+                analyser.lock().unwrap().detached();
+
+                serde_json::json!({"status":"OK"})
             });
 
         Box::new(f)
     }
 
-    fn continue_(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let msg = OwnedMessage::Text("{\"method\":\"Debugger.resume\"}".to_string());
+    fn current_location(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let location = self.analyser.lock().unwrap().get_location();
+
+        Box::new(future::lazy(move || {
+            Ok(serde_json::json!({"status":"OK","location":location}))
+        }))
+    }
+
+    fn list_sources(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let sources = self.analyser.lock().unwrap().get_sources();
+
+        Box::new(future::lazy(move || {
+            Ok(serde_json::json!({"status":"OK","sources":sources}))
+        }))
+    }
+
+    /// Node can only pause on all exceptions or uncaught ones, so pausing on caught
+    /// exceptions also pauses on uncaught ones
+    fn exception_breakpoint(
+        &mut self,
+        caught: bool,
+        uncaught: bool,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let state = match (caught, uncaught) {
+            (true, _) => "all",
+            (false, true) => "uncaught",
+            (false, false) => "none",
+        };
+
+        let msg = OwnedMessage::Text(format!(
+            "{{\
+             \"method\":\"Debugger.setPauseOnExceptions\",\
+             \"params\":{{\"state\":\"{}\"}}\
+             }}",
+            state,
+        ));
 
         let f = self
             .ws_handler
             .lock()
             .unwrap()
             .send_and_receive_message(msg)
-            .map(|response| {
+            .map(move |response| {
                 if response["error"].is_null() {
-                    serde_json::json!({"status":"OK"})
+                    serde_json::json!({
+                        "status": "OK",
+                        "caught": caught,
+                        "uncaught": caught || uncaught,
+                    })
                 } else {
                     serde_json::json!({"status":"ERROR"})
                 }
@@ -261,21 +627,120 @@ impl DebuggerV1 for ImplDebugger {
     fn print(
         &mut self,
         variable: &Variable,
-        _: Arc<Mutex<Config>>,
+        frame: Option<u64>,
+        config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let msg = OwnedMessage::Text(format!(
-            "{{\
-             \"method\":\"Debugger.evaluateOnCallFrame\",\
-             \"params\":{{\
-             \"callFrameId\":\"{{\\\"ordinal\\\":0,\\\"injectedScriptId\\\":1}}\",\
-             \"expression\":\"{}\",\
-             \"returnByValue\":true\
-             }}\
-             }}",
-            variable.name,
-        ));
+        // Nothing can be evaluated until Node's running the program
+        if self.analyser.lock().unwrap().get_pid().is_none() {
+            return not_running();
+        }
 
+        let call_frame_id = self
+            .analyser
+            .lock()
+            .unwrap()
+            .get_call_frame_id(frame.unwrap_or(0));
+
+        let call_frame_id = match (call_frame_id, frame) {
+            (Some(id), _) => id,
+            (None, None) => TOP_CALL_FRAME_ID.to_string(),
+            (None, Some(frame)) => {
+                log_msg(LogLevel::WARN, &format!("No frame {} to print in", frame));
+                return Box::new(future::ok(serde_json::json!({"status":"ERROR"})));
+            }
+        };
+
+        // Evaluated as a remote object so that objects can be referred to by `getChildren`,
+        // their value is fetched from that object rather than evaluating them again
+        let msg = evaluate_message(&variable.name, &call_frame_id, false);
+
+        let max_bytes = config.lock().unwrap().get_config("MaxValueBytes").unwrap() as usize;
         let variable = variable.name.clone();
+        let ws_handler = self.ws_handler.clone();
+
+        let f =
+            self.ws_handler
+                .lock()
+                .unwrap()
+                .send_and_receive_message(msg)
+                .and_then(
+                    move |response| -> Box<
+                        dyn Future<Item = serde_json::Value, Error = io::Error> + Send,
+                    > {
+                        let object_id = match response["result"]["exceptionDetails"].is_null() {
+                            true => response["result"]["result"]["objectId"].as_str(),
+                            false => None,
+                        };
+
+                        let object_id = match object_id {
+                            Some(id) => id.to_string(),
+                            None => {
+                                let mut resp = print_response(&variable, &response);
+                                if resp["status"] == "OK" {
+                                    resp["value"] =
+                                        truncate_json_value(resp["value"].take(), max_bytes);
+                                }
+                                return Box::new(future::ok(resp));
+                            }
+                        };
+
+                        let msg = OwnedMessage::Text(
+                            serde_json::json!({
+                                "method": "Runtime.callFunctionOn",
+                                "params": {
+                                    "objectId": object_id,
+                                    "functionDeclaration": "function() { return this; }",
+                                    "returnByValue": true,
+                                },
+                            })
+                            .to_string(),
+                        );
+
+                        let f = ws_handler
+                            .lock()
+                            .unwrap()
+                            .send_and_receive_message(msg)
+                            .map(move |response| {
+                                let mut resp = print_response(&variable, &response);
+                                if resp["status"] == "OK" {
+                                    resp["value"] =
+                                        truncate_json_value(resp["value"].take(), max_bytes);
+                                    if resp["type"] == "object" && !resp["value"].is_null() {
+                                        resp["reference"] = serde_json::json!(object_id);
+                                    }
+                                }
+                                resp
+                            });
+
+                        Box::new(f)
+                    },
+                );
+
+        Box::new(f)
+    }
+
+    /// The type is the class name of objects, e.g. `Array` or `Map`, otherwise the
+    /// JavaScript type, e.g. `number`
+    fn type_of(
+        &mut self,
+        expression: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if self.analyser.lock().unwrap().get_pid().is_none() {
+            return not_running();
+        }
+
+        let call_frame_id = self
+            .analyser
+            .lock()
+            .unwrap()
+            .get_call_frame_id(0)
+            .unwrap_or_else(|| TOP_CALL_FRAME_ID.to_string());
+
+        // Evaluated as a remote object so that objects aren't serialised just for their type
+        let msg = evaluate_message(expression, &call_frame_id, false);
+
+        let expression = expression.to_string();
 
         let f = self
             .ws_handler
@@ -283,15 +748,250 @@ impl DebuggerV1 for ImplDebugger {
             .unwrap()
             .send_and_receive_message(msg)
             .map(move |response| {
+                if !response["error"].is_null() || !response["result"]["exceptionDetails"].is_null()
+                {
+                    log_msg(
+                        LogLevel::WARN,
+                        &format!("Can't get the type of '{}'", expression),
+                    );
+                    return serde_json::json!({"status":"ERROR"});
+                }
+
+                let result = &response["result"]["result"];
+                let type_ = match result["className"].is_null() {
+                    true => result["type"].clone(),
+                    false => result["className"].clone(),
+                };
+
+                serde_json::json!({
+                    "status": "OK",
+                    "expression": expression,
+                    "type": type_,
+                })
+            });
+
+        Box::new(f)
+    }
+
+    /// The expression is true when it's truthy in JavaScript, the value is its JSON value
+    /// or the description of values JSON can't represent
+    fn assert(
+        &mut self,
+        expression: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let call_frame_id = self
+            .analyser
+            .lock()
+            .unwrap()
+            .get_call_frame_id(0)
+            .unwrap_or_else(|| TOP_CALL_FRAME_ID.to_string());
+
+        let msg = evaluate_message(expression, &call_frame_id, true);
+
+        let expression = expression.to_string();
+
+        let f = self
+            .ws_handler
+            .lock()
+            .unwrap()
+            .send_and_receive_message(msg)
+            .and_then(move |response| {
+                let reason = if !response["error"].is_null() {
+                    Some(response["error"]["message"].clone())
+                } else if !response["result"]["exceptionDetails"].is_null() {
+                    let details = &response["result"]["exceptionDetails"];
+                    match details["exception"]["description"].is_null() {
+                        true => Some(details["text"].clone()),
+                        false => Some(details["exception"]["description"].clone()),
+                    }
+                } else {
+                    None
+                };
+
+                if let Some(reason) = reason {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Can't evaluate '{}': {}",
+                            expression,
+                            // Exceptions are described with their stack after the message
+                            reason
+                                .as_str()
+                                .and_then(|r| r.lines().next())
+                                .unwrap_or("unknown error")
+                        ),
+                    ));
+                }
+
+                let result = &response["result"]["result"];
+                let value = match result.get("value") {
+                    Some(v) => v.clone(),
+                    None => result["description"].clone(),
+                };
+
+                Ok(serde_json::json!({
+                    "status": "OK",
+                    "passed": is_truthy(result),
+                    "value": value,
+                }))
+            });
+
+        Box::new(f)
+    }
+
+    /// The source is that of the script itself, not the original source of any source map
+    fn frame_source(
+        &mut self,
+        before: u64,
+        after: u64,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let (script_id, line) = match self.analyser.lock().unwrap().get_paused_script() {
+            Some(paused_script) => paused_script,
+            None => {
+                log_msg(LogLevel::WARN, "The program isn't paused anywhere");
+                return Box::new(future::ok(serde_json::json!({"status":"ERROR"})));
+            }
+        };
+
+        let msg = OwnedMessage::Text(
+            serde_json::json!({
+                "method": "Debugger.getScriptSource",
+                "params": {
+                    "scriptId": script_id,
+                },
+            })
+            .to_string(),
+        );
+
+        let start = line.saturating_sub(before).max(1);
+
+        let f = self
+            .ws_handler
+            .lock()
+            .unwrap()
+            .send_and_receive_message(msg)
+            .map(move |response| {
+                let source = match response["result"]["scriptSource"].as_str() {
+                    Some(source) => source,
+                    None => return serde_json::json!({"status":"ERROR"}),
+                };
+
+                let lines: Vec<&str> = source
+                    .lines()
+                    .skip(start as usize - 1)
+                    .take((line + after - start + 1) as usize)
+                    .collect();
+
+                serde_json::json!({
+                    "status": "OK",
+                    "startLine": match lines.is_empty() {
+                        true => None,
+                        false => Some(start),
+                    },
+                    "lines": lines,
+                })
+            });
+
+        Box::new(f)
+    }
+
+    fn set_cwd(
+        &mut self,
+        dir: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let resp = self
+            .process
+            .lock()
+            .unwrap()
+            .set_cwd(dir.to_string())
+            .map(|_| serde_json::json!({"status":"OK"}));
+        Box::new(future::lazy(move || resp))
+    }
+
+    /// The reference is the `objectId` of the object, only valid while paused
+    fn get_children(
+        &mut self,
+        reference: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let max_bytes = config.lock().unwrap().get_config("MaxValueBytes").unwrap() as usize;
+        let msg = OwnedMessage::Text(
+            serde_json::json!({
+                "method": "Runtime.getProperties",
+                "params": {
+                    "objectId": reference,
+                    "ownProperties": true,
+                },
+            })
+            .to_string(),
+        );
+
+        let reference = reference.to_string();
+
+        let f = self
+            .ws_handler
+            .lock()
+            .unwrap()
+            .send_and_receive_message(msg)
+            .map(
+                move |response| match response["result"]["result"].as_array() {
+                    Some(properties) => serde_json::json!({
+                        "status": "OK",
+                        "children": properties
+                            .iter()
+                            .filter_map(|p| property_to_child(p, max_bytes))
+                            .collect::<Vec<VariableChild>>(),
+                    }),
+                    None => {
+                        log_msg(
+                            LogLevel::WARN,
+                            &format!("Can't get children of '{}': {}", reference, response),
+                        );
+                        serde_json::json!({"status":"ERROR"})
+                    }
+                },
+            );
+
+        Box::new(f)
+    }
+
+    fn raw(
+        &mut self,
+        command: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        // Raw commands for Node are sent as is over the websocket so must be
+        // a valid JSON inspector protocol message.
+        match serde_json::from_str::<serde_json::Value>(command) {
+            Ok(_) => {}
+            Err(e) => {
+                log_msg(
+                    LogLevel::ERROR,
+                    &format!("Can't understand raw command {}: {}", command, e),
+                );
+
+                return Box::new(future::lazy(move || {
+                    let resp = serde_json::json!({"status":"ERROR"});
+                    Ok(resp)
+                }));
+            }
+        };
+
+        let msg = OwnedMessage::Text(command.to_string());
+
+        let f = self
+            .ws_handler
+            .lock()
+            .unwrap()
+            .send_and_receive_message(msg)
+            .map(|response| {
                 if response["error"].is_null() {
-                    let mut json = response;
-                    let variable_type = json["result"]["result"]["type"].take();
-                    let value = json["result"]["result"]["value"].take();
                     serde_json::json!({
                         "status": "OK",
-                        "type": variable_type,
-                        "variable": variable,
-                        "value": value,
+                        "output": response["result"].to_string(),
                     })
                 } else {
                     serde_json::json!({"status":"ERROR"})
@@ -3,6 +3,7 @@
 mod analyser;
 mod debugger;
 mod process;
+mod sourcemap;
 mod ws;
 
 pub use self::debugger::ImplDebugger;
@@ -2,9 +2,12 @@
 //!
 //! This module performs the basic setup and spawning of the Node process.
 
-use std::io::BufReader;
+use std::io::{self, BufReader};
+use std::sync::{Arc, Mutex};
 
-use crate::util::{check_and_spawn_process, read_output, setup_stdin};
+use crate::debugger::{cwd_after_run_error, LOGPOINT_PREFIX};
+use crate::notifier::{logpoint_hit, program_output};
+use crate::util::{check_and_spawn_process, read_output, setup_stdin, split_lines};
 
 use regex::Regex;
 use tokio::prelude::*;
@@ -16,29 +19,35 @@ use tokio_process::{Child, ChildStderr, ChildStdout};
 pub struct Process {
     debugger_cmd: Option<String>,
     run_cmd: Option<Vec<String>>,
-    process: Option<Child>,
+    cwd: Option<String>,
+    env: Vec<(String, String)>,
+    process: Arc<Mutex<Option<Child>>>,
 }
 
 impl Process {
     /// Create a new Process
-    pub fn new(debugger_cmd: String, run_cmd: Vec<String>) -> Self {
+    pub fn new(debugger_cmd: String, run_cmd: Vec<String>, env: Vec<(String, String)>) -> Self {
         Process {
             debugger_cmd: Some(debugger_cmd),
             run_cmd: Some(run_cmd),
-            process: None,
+            cwd: None,
+            env,
+            process: Arc::new(Mutex::new(None)),
         }
     }
 
     /// Run Node program, including handling forwarding stdin onto the Node interpreter but
     /// not used to analyse the program as some of the other debuggers are.
-    pub fn run(&mut self, tx: Sender<String>) {
+    pub fn run(&mut self, tx: Sender<String>) -> Result<(), io::Error> {
         let mut process = check_and_spawn_process(
             vec![
-                self.debugger_cmd.take().unwrap(),
+                self.debugger_cmd.clone().unwrap(),
                 "--inspect-brk=0".to_string(),
             ],
-            self.run_cmd.take().unwrap(),
-        );
+            self.run_cmd.clone().unwrap(),
+            self.cwd.as_deref(),
+            &self.env,
+        )?;
 
         setup_stdin(
             process
@@ -63,19 +72,74 @@ impl Process {
             tx,
         );
 
-        self.process = Some(process);
+        *self.process.lock().unwrap() = Some(process);
+
+        Ok(())
+    }
+
+    /// Wait for the program that was last run to exit, with its exit code
+    ///
+    /// Programs killed by a signal have no exit code and give -1. The future never
+    /// finishes if the program's torn down first.
+    pub fn wait_for_exit(&self) -> Box<dyn Future<Item = i64, Error = ()> + Send> {
+        let process = self.process.clone();
+
+        let f = future::poll_fn(move || match process.lock().unwrap().as_mut() {
+            Some(child) => child.poll(),
+            None => Ok(Async::NotReady),
+        })
+        .map(|status| status.code().map(i64::from).unwrap_or(-1))
+        .map_err(|e| eprintln!("Err waiting for Node: {}", e));
+
+        Box::new(f)
+    }
+
+    /// Set the directory the program is run in, only before it's been run
+    pub fn set_cwd(&mut self, dir: String) -> Result<(), io::Error> {
+        if self.process.lock().unwrap().is_some() {
+            return Err(cwd_after_run_error());
+        }
+        self.cwd = Some(dir);
+        Ok(())
+    }
+
+    /// Drop the child process, killing it if it's still running
+    pub fn teardown(&mut self) {
+        *self.process.lock().unwrap() = None;
     }
 
     pub fn get_pid(&self) -> u64 {
-        self.process.as_ref().unwrap().id() as u64
+        self.process.lock().unwrap().as_ref().unwrap().id() as u64
     }
 
     /// Perform setup of reading Node stdout and writing it back to PADRE stdout.
+    ///
+    /// The output is also sent to the editor tagged as `stdout`, apart from the messages
+    /// logged by logpoints which are notified instead.
     fn setup_stdout(&mut self, stdout: ChildStdout) {
         tokio::spawn(
             read_output(BufReader::new(stdout))
                 .for_each(move |text| {
                     print!("{}", text);
+
+                    if !text.contains(LOGPOINT_PREFIX) {
+                        program_output("stdout", &text);
+                        return Ok(());
+                    }
+
+                    let mut output = String::new();
+                    for line in split_lines(&text) {
+                        if let Some(message) = line.strip_prefix(LOGPOINT_PREFIX) {
+                            logpoint_hit(message);
+                        } else if !line.is_empty() {
+                            output.push_str(line);
+                            output.push('\n');
+                        }
+                    }
+                    if !output.is_empty() {
+                        program_output("stdout", &output);
+                    }
+
                     Ok(())
                 })
                 .map_err(|e| eprintln!("Err reading Node stdout: {}", e)),
@@ -85,7 +149,8 @@ impl Process {
     /// Perform setup of reading Node stderr and writing it back to PADRE stderr.
     ///
     /// Also checks for the line about where the Debugger is listening as this is
-    /// required for the websocket setup.
+    /// required for the websocket setup. After that the output is also sent to the
+    /// editor tagged as `stderr`.
     fn setup_stderr(&mut self, stderr: ChildStderr, tx: Sender<String>) {
         lazy_static! {
             static ref RE_NODE_STARTED: Regex =
@@ -98,7 +163,7 @@ impl Process {
             read_output(BufReader::new(stderr))
                 .for_each(move |text| {
                     if !node_setup {
-                        'node_setup_start: for line in text.split("\n") {
+                        'node_setup_start: for line in split_lines(&text) {
                             for cap in RE_NODE_STARTED.captures_iter(&line) {
                                 tx.clone().send(cap[1].to_string()).wait().unwrap();
                                 node_setup = true;
@@ -107,6 +172,7 @@ impl Process {
                         }
                     } else {
                         eprint!("{}", text);
+                        program_output("stderr", &text);
                     }
                     Ok(())
                 })
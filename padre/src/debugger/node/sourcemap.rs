@@ -0,0 +1,313 @@
+//! Source maps
+//!
+//! Minimal support for version 3 source maps so that positions in languages compiled to
+//! JavaScript (e.g. TypeScript) can be mapped to and from the JavaScript Node actually runs.
+//!
+//! Only line information is used, all lines are numbered from 0 as in both Node and the
+//! source map format.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const DATA_PREFIX: &str = "data:application/json;base64,";
+
+/// A line in an original source file and the line of generated code it maps to
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Mapping {
+    generated_line: u64,
+    source: usize,
+    original_line: u64,
+}
+
+/// A parsed source map
+#[derive(Debug, Eq, PartialEq)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// Load the source map for a script given the `sourceMapURL` Node reports for it
+    ///
+    /// The URL can be an inline `data:` URL, a `file://` URL or a path relative to the
+    /// script.
+    pub fn load(script_file: &str, source_map_url: &str) -> Result<SourceMap, io::Error> {
+        let script_dir = Path::new(script_file)
+            .parent()
+            .unwrap_or(Path::new("/"))
+            .to_path_buf();
+
+        if let Some(data) = source_map_url.strip_prefix(DATA_PREFIX) {
+            let json = decode_base64(data).ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Can't decode inline source map",
+            ))?;
+            return SourceMap::parse(&String::from_utf8_lossy(&json), &script_dir);
+        }
+
+        let path = match source_map_url.starts_with("file://") {
+            true => PathBuf::from(&source_map_url[7..]),
+            false => script_dir.join(source_map_url),
+        };
+
+        let json = fs::read_to_string(&path)?;
+        let map_dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+        SourceMap::parse(&json, &map_dir)
+    }
+
+    /// Parse a source map, sources are resolved relative to `map_dir`
+    pub fn parse(json: &str, map_dir: &Path) -> Result<SourceMap, io::Error> {
+        let map: serde_json::Value = serde_json::from_str(json)?;
+
+        if map["version"] != 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported source map version {}", map["version"]),
+            ));
+        }
+
+        let source_root = map["sourceRoot"].as_str().unwrap_or("");
+
+        let sources: Vec<String> = match map["sources"].as_array() {
+            Some(sources) => sources
+                .iter()
+                .map(|s| resolve_source(map_dir, source_root, s.as_str().unwrap_or("")))
+                .collect(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Source map has no sources",
+                ));
+            }
+        };
+
+        let mappings = match map["mappings"].as_str() {
+            Some(m) => parse_mappings(m)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Source map has no mappings",
+                ));
+            }
+        };
+
+        if let Some(m) = mappings.iter().find(|m| m.source >= sources.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Source map refers to source {} that it doesn't have",
+                    m.source
+                ),
+            ));
+        }
+
+        Ok(SourceMap { sources, mappings })
+    }
+
+    /// The original sources in this map
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Whether the file is one of the original sources in this map
+    pub fn has_source(&self, file: &str) -> bool {
+        self.sources.iter().any(|s| s == file)
+    }
+
+    /// Find the generated line for a line in an original source file
+    ///
+    /// If nothing is generated for that line exactly the next line that does have code
+    /// generated for it is used, much like a debugger would.
+    pub fn generated_line(&self, file: &str, line: u64) -> Option<u64> {
+        let source = self.sources.iter().position(|s| s == file)?;
+
+        self.mappings
+            .iter()
+            .filter(|m| m.source == source && m.original_line >= line)
+            .min_by_key(|m| (m.original_line, m.generated_line))
+            .map(|m| m.generated_line)
+    }
+
+    /// Find the original file and line for a generated line
+    pub fn original_position(&self, generated_line: u64) -> Option<(&str, u64)> {
+        self.mappings
+            .iter()
+            .find(|m| m.generated_line == generated_line)
+            .map(|m| (&self.sources[m.source][..], m.original_line))
+    }
+}
+
+fn resolve_source(map_dir: &Path, source_root: &str, source: &str) -> String {
+    let source = source.trim_start_matches("file://");
+    let path = map_dir.join(source_root).join(source);
+    match path.canonicalize() {
+        Ok(p) => p.to_string_lossy().to_string(),
+        Err(_) => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Parse the `mappings` field, only segments with an original position are kept
+fn parse_mappings(mappings: &str) -> Result<Vec<Mapping>, io::Error> {
+    let mut ret = vec![];
+
+    // Everything but the generated column is relative to the previous segment in the
+    // whole map rather than just the line.
+    let mut source: i64 = 0;
+    let mut original_line: i64 = 0;
+
+    for (generated_line, line) in mappings.split(';').enumerate() {
+        for segment in line.split(',') {
+            if segment.is_empty() {
+                continue;
+            }
+
+            let fields = decode_vlq(segment).ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Can't decode source map segment {}", segment),
+            ))?;
+
+            if fields.len() < 4 {
+                continue;
+            }
+
+            source += fields[1];
+            original_line += fields[2];
+
+            if source < 0 || original_line < 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad source map segment {}", segment),
+                ));
+            }
+
+            ret.push(Mapping {
+                generated_line: generated_line as u64,
+                source: source as usize,
+                original_line: original_line as u64,
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Decode a segment of base 64 VLQ encoded values
+fn decode_vlq(segment: &str) -> Option<Vec<i64>> {
+    let mut values = vec![];
+    let mut value: i64 = 0;
+    let mut shift = 0;
+
+    for c in segment.bytes() {
+        let digit = BASE64_CHARS.iter().position(|&b| b == c)? as i64;
+
+        value += (digit & 0b11111) << shift;
+
+        if digit & 0b100000 != 0 {
+            shift += 5;
+            // Values are at most 32 bits and a sign, any longer and they'd overflow
+            if shift > 30 {
+                return None;
+            }
+        } else {
+            let negative = value & 1 == 1;
+            value >>= 1;
+            values.push(if negative { -value } else { value });
+            value = 0;
+            shift = 0;
+        }
+    }
+
+    match shift {
+        0 => Some(values),
+        _ => None,
+    }
+}
+
+/// Decode standard base 64, as used for inline source maps
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let mut ret = vec![];
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in s.bytes() {
+        if c == b'=' {
+            break;
+        }
+
+        let digit = BASE64_CHARS.iter().position(|&b| b == c)? as u32;
+
+        buffer = (buffer << 6) | digit;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            ret.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn check_decode_vlq() {
+        assert_eq!(super::decode_vlq("AAAA"), Some(vec![0, 0, 0, 0]));
+        assert_eq!(super::decode_vlq("AACA"), Some(vec![0, 0, 1, 0]));
+        assert_eq!(super::decode_vlq("D"), Some(vec![-1]));
+        assert_eq!(super::decode_vlq("gB"), Some(vec![16]));
+        assert_eq!(super::decode_vlq("g"), None);
+        assert_eq!(super::decode_vlq("//////////////A"), None);
+    }
+
+    #[test]
+    fn check_decode_base64() {
+        assert_eq!(
+            super::decode_base64("eyJhIjoxfQ=="),
+            Some(b"{\"a\":1}".to_vec())
+        );
+    }
+
+    #[test]
+    fn check_source_map_lines() {
+        // test.ts lines 0, 1 and 3 generate lines 2, 3 and 4 of test.js
+        let json = r#"{
+            "version": 3,
+            "file": "test.js",
+            "sourceRoot": "",
+            "sources": ["test.ts"],
+            "names": [],
+            "mappings": ";;AAAA;AACA;AAEA"
+        }"#;
+
+        let source_map = super::SourceMap::parse(json, Path::new("/home/me")).unwrap();
+
+        assert!(source_map.has_source("/home/me/test.ts"));
+        assert!(!source_map.has_source("/home/me/other.ts"));
+        assert_eq!(source_map.generated_line("/home/me/test.ts", 1), Some(3));
+        assert_eq!(source_map.generated_line("/home/me/test.ts", 2), Some(4));
+        assert_eq!(source_map.generated_line("/home/me/test.ts", 4), None);
+        assert_eq!(
+            source_map.original_position(4),
+            Some(("/home/me/test.ts", 3))
+        );
+        assert_eq!(source_map.original_position(0), None);
+    }
+    #[test]
+    fn check_source_map_with_bad_source_rejected() {
+        // The second segment refers to source 1 when there's only source 0
+        let json = r#"{
+            "version": 3,
+            "sources": ["test.ts"],
+            "mappings": "AAAA;ACAA"
+        }"#;
+
+        assert!(super::SourceMap::parse(json, Path::new("/home/me")).is_err());
+    }
+}
@@ -2,19 +2,36 @@
 
 use std::collections::HashMap;
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::notifier::{log_msg, LogLevel};
+use crate::notifier::{log_msg, protocol_trace, LogLevel};
 
 use tokio::prelude::*;
 use tokio::sync::mpsc::{self, Sender};
+use tokio::timer::Delay;
 use websocket::result::WebSocketError;
 use websocket::{ClientBuilder, OwnedMessage};
 
+/// How many times to try to reconnect when the websocket drops before giving up
+const MAX_RECONNECT_ATTEMPTS: u64 = 3;
+
+/// How long to wait before each attempt to reconnect
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Called with every message from Node that isn't a response to a request
+type MessageHandler = Arc<dyn Fn(serde_json::Value) -> Option<OwnedMessage> + Sync + Send>;
+
 #[derive(Debug)]
 pub struct WSHandler {
     response_listeners: Arc<Mutex<HashMap<u64, Sender<serde_json::Value>>>>,
-    ws_tx: Option<Sender<OwnedMessage>>,
+    ws_tx: Arc<Mutex<Option<Sender<OwnedMessage>>>>,
+    // Messages sent while reconnecting, they're sent once reconnected
+    unsent: Arc<Mutex<Vec<OwnedMessage>>>,
+    // Set once we've closed the websocket ourselves, or given up reconnecting, so that
+    // nothing more is sent
+    closed: Arc<AtomicBool>,
     ws_id: u64,
 }
 
@@ -22,24 +39,136 @@ impl WSHandler {
     pub fn new() -> WSHandler {
         WSHandler {
             response_listeners: Arc::new(Mutex::new(HashMap::new())),
-            ws_tx: None,
+            ws_tx: Arc::new(Mutex::new(None)),
+            unsent: Arc::new(Mutex::new(vec![])),
+            closed: Arc::new(AtomicBool::new(false)),
             ws_id: 1,
         }
     }
 
+    /// Connect to Node's websocket at the URI given, if the connection drops it's
+    /// reconnected to the same URI a few times before giving up
     pub fn connect<F>(&mut self, uri: &str, f: F)
     where
         F: Fn(serde_json::Value) -> Option<OwnedMessage> + Sync + Send + 'static,
     {
+        let connection = Connection {
+            uri: uri.to_string(),
+            handler: Arc::new(f),
+            response_listeners: self.response_listeners.clone(),
+            ws_tx: self.ws_tx.clone(),
+            unsent: self.unsent.clone(),
+            closed: self.closed.clone(),
+        };
+
+        connection.open(0);
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        let tx = match self.ws_tx.lock().unwrap().clone() {
+            Some(tx) => tx,
+            None => return,
+        };
+
+        tokio::spawn(tx.send(OwnedMessage::Close(None)).map(|_| {}).map_err(|e| {
+            eprintln!("Error sending message: {:?}", e);
+        }));
+    }
+
+    /// Send a message to Node and wait for the response to it
+    ///
+    /// Messages sent while reconnecting are held on to until reconnected, it's an error
+    /// if the connection is lost before there's a response.
+    pub fn send_and_receive_message(
+        &mut self,
+        msg: OwnedMessage,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let id = self.get_next_ws_id();
+        let msg = self.add_id_to_message(msg, id);
+
+        if let OwnedMessage::Text(s) = &msg {
+            protocol_trace("command", s);
+        }
+
+        let (listener_tx, listener_rx) = mpsc::channel(1);
+
+        self.response_listeners
+            .lock()
+            .unwrap()
+            .insert(id, listener_tx);
+
+        match self.ws_tx.lock().unwrap().clone() {
+            Some(tx) => {
+                tokio::spawn(tx.send(msg).map(|_| {}).map_err(|e| {
+                    eprintln!("Error sending message: {:?}", e);
+                }));
+            }
+            None if self.closed.load(Ordering::SeqCst) => {
+                self.response_listeners.lock().unwrap().remove(&id);
+            }
+            None => self.unsent.lock().unwrap().push(msg),
+        }
+
+        let f = listener_rx.into_future().then(|response| match response {
+            Ok((Some(response), _)) => Ok(response),
+            _ => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "Lost connection to node",
+            )),
+        });
+
+        Box::new(f)
+    }
+
+    fn get_next_ws_id(&mut self) -> u64 {
+        let id = self.ws_id;
+        self.ws_id += 1;
+        id
+    }
+
+    fn add_id_to_message(&self, msg: OwnedMessage, id: u64) -> OwnedMessage {
+        if let OwnedMessage::Text(s) = &msg {
+            let mut json: serde_json::Value = serde_json::from_str(s).unwrap();
+            json["id"] = serde_json::json!(id);
+            OwnedMessage::Text(json.to_string())
+        } else {
+            unreachable!();
+        }
+    }
+}
+
+/// Everything needed to connect, and reconnect, to Node's websocket
+#[derive(Clone)]
+struct Connection {
+    uri: String,
+    handler: MessageHandler,
+    response_listeners: Arc<Mutex<HashMap<u64, Sender<serde_json::Value>>>>,
+    ws_tx: Arc<Mutex<Option<Sender<OwnedMessage>>>>,
+    unsent: Arc<Mutex<Vec<OwnedMessage>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Connection {
+    /// Connect to the websocket, `attempt` is 0 for the first connection and counts the
+    /// attempts to reconnect after that
+    fn open(self, attempt: u64) {
         let (tx, rx) = mpsc::channel(1);
 
-        self.ws_tx = Some(tx.clone());
+        let connected = Arc::new(AtomicBool::new(false));
+        let connected_2 = connected.clone();
         let response_listeners = self.response_listeners.clone();
+        let f = self.handler.clone();
+        let connection = self.clone();
 
-        let fut = ClientBuilder::new(uri)
+        let fut = ClientBuilder::new(&self.uri)
             .unwrap()
             .async_connect_insecure()
             .and_then(move |(duplex, _)| {
+                connected.store(true, Ordering::SeqCst);
+                connection.connected(tx, attempt);
+
                 let (sink, stream) = duplex.split();
 
                 stream
@@ -77,73 +206,104 @@ impl WSHandler {
                         };
                         None
                     })
-                    .select(rx.map_err(|_| WebSocketError::NoDataAvailable))
+                    // Marks the end of the websocket so that the connection finishes
+                    // then rather than waiting for more messages to send
+                    .map(Some)
+                    .chain(stream::once(Ok(None)))
+                    .select(rx.map(Some).map_err(|_| WebSocketError::NoDataAvailable))
+                    .take_while(|message| Ok(message.is_some()))
+                    .map(Option::unwrap)
                     .forward(sink)
             })
-            .map(|_| ())
-            .map_err(|e| eprintln!("WebSocket err: {:?}", e));
+            .then(move |result| {
+                if let Err(e) = result {
+                    eprintln!("WebSocket err: {:?}", e);
+                }
+                self.disconnected(attempt, connected_2.load(Ordering::SeqCst));
+                Ok(())
+            });
 
         tokio::spawn(fut);
     }
 
-    pub fn close(&self) {
-        let tx = self.ws_tx.clone();
+    /// Start sending messages over a new connection, after reconnecting Node has to be
+    /// told again to report what it's doing
+    fn connected(&self, tx: Sender<OwnedMessage>, attempt: u64) {
+        *self.ws_tx.lock().unwrap() = Some(tx.clone());
+
+        let mut msgs = vec![];
+
+        if attempt > 0 {
+            log_msg(LogLevel::INFO, "Reconnected to Node");
+
+            // No request is ever given the id 0 so the responses to these are ignored
+            for method in &["Runtime.enable", "Debugger.enable"] {
+                msgs.push(OwnedMessage::Text(
+                    serde_json::json!({"id": 0, "method": method}).to_string(),
+                ));
+            }
+        }
+
+        msgs.append(&mut self.unsent.lock().unwrap());
 
         tokio::spawn(
-            tx.unwrap()
-                .send(OwnedMessage::Close(None))
+            tx.send_all(stream::iter_ok(msgs))
                 .map(|_| {})
-                .map_err(|e| {
-                    eprintln!("Error sending message: {:?}", e);
-                }),
+                .map_err(|e| eprintln!("Error sending message: {:?}", e)),
         );
     }
 
-    pub fn send_and_receive_message(
-        &mut self,
-        msg: OwnedMessage,
-    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let id = self.get_next_ws_id();
-        let msg = self.add_id_to_message(msg, id);
-
-        let (listener_tx, listener_rx) = mpsc::channel(1);
-
-        self.response_listeners
-            .lock()
-            .unwrap()
-            .insert(id, listener_tx);
+    /// Reconnect after the connection's dropped, or a connection attempt's failed, unless
+    /// we closed it or have run out of attempts
+    fn disconnected(self, attempt: u64, was_connected: bool) {
+        *self.ws_tx.lock().unwrap() = None;
 
-        let tx = self.ws_tx.clone();
+        // Nothing sent over the old connection will be answered now
+        if was_connected {
+            self.response_listeners.lock().unwrap().clear();
+        }
 
-        tokio::spawn(tx.unwrap().send(msg).map(|_| {}).map_err(|e| {
-            eprintln!("Error sending message: {:?}", e);
-        }));
+        if self.closed.load(Ordering::SeqCst) {
+            return;
+        }
 
-        let f = listener_rx
-            .into_future()
-            .map(move |response| response.0.unwrap())
-            .map_err(|e| {
-                eprintln!("Error sending to node: {:?}", e.0);
-                io::Error::new(io::ErrorKind::Other, "Timed out sending to node")
-            });
+        let attempt = match was_connected {
+            true => 1,
+            false => attempt + 1,
+        };
 
-        Box::new(f)
-    }
+        if attempt > MAX_RECONNECT_ATTEMPTS {
+            log_msg(
+                LogLevel::ERROR,
+                &format!(
+                    "Can't reconnect to Node after {} attempts, giving up",
+                    MAX_RECONNECT_ATTEMPTS
+                ),
+            );
+            self.closed.store(true, Ordering::SeqCst);
+            self.response_listeners.lock().unwrap().clear();
+            self.unsent.lock().unwrap().clear();
+            return;
+        }
 
-    fn get_next_ws_id(&mut self) -> u64 {
-        let id = self.ws_id;
-        self.ws_id += 1;
-        id
-    }
+        log_msg(
+            LogLevel::WARN,
+            &format!(
+                "{}, reconnecting (attempt {} of {})",
+                match was_connected {
+                    true => "Lost connection to Node",
+                    false => "Can't connect to Node",
+                },
+                attempt,
+                MAX_RECONNECT_ATTEMPTS
+            ),
+        );
 
-    fn add_id_to_message(&self, msg: OwnedMessage, id: u64) -> OwnedMessage {
-        if let OwnedMessage::Text(s) = &msg {
-            let mut json: serde_json::Value = serde_json::from_str(s).unwrap();
-            json["id"] = serde_json::json!(id);
-            OwnedMessage::Text(json.to_string())
-        } else {
-            unreachable!();
-        }
+        tokio::spawn(
+            Delay::new(Instant::now() + RECONNECT_DELAY)
+                .map(move |_| self.open(attempt))
+                .map_err(|e| eprintln!("Timer error reconnecting to Node: {:?}", e)),
+        );
     }
 }
 
@@ -171,4 +331,24 @@ mod tests {
         assert_eq!(expected, json);
         assert_eq!(2, ws_handler.ws_id);
     }
+
+    #[test]
+    fn check_message_held_until_connected() {
+        let mut ws_handler = super::WSHandler::new();
+
+        let msg = OwnedMessage::Text("{\"method\":\"Debugger.pause\"}".to_string());
+        let _ = ws_handler.send_and_receive_message(msg);
+
+        assert_eq!(
+            *ws_handler.unsent.lock().unwrap(),
+            vec![OwnedMessage::Text(
+                "{\"id\":1,\"method\":\"Debugger.pause\"}".to_string()
+            )]
+        );
+        assert!(ws_handler
+            .response_listeners
+            .lock()
+            .unwrap()
+            .contains_key(&1));
+    }
 }
@@ -11,124 +11,178 @@ use std::time::Duration;
 
 use super::process::{Event, Listener, PDBStatus, Process};
 use crate::config::Config;
-use crate::debugger::{DebuggerV1, FileLocation, Variable};
-use crate::notifier::{log_msg, LogLevel};
+use crate::debugger::{
+    assert_response, not_running, parse_log_message, source_listing_response, Capabilities,
+    DebuggerV1, FileLocation, HitCondition, LogMessagePart, ProgramStatus, StopLocation, Variable,
+    LOGPOINT_PREFIX,
+};
+use crate::notifier::{breakpoint_pending, debugger_ready, log_msg, step_progress, LogLevel};
+use crate::util::{absolute_path, truncate_value, typed_value};
 
 use bytes::Bytes;
 use tokio::prelude::*;
 use tokio::sync::mpsc;
 
+/// What decides whether a breakpoint stops when it's hit
+#[derive(Clone, Debug)]
+enum Condition {
+    Hit(HitCondition),
+    Log(String),
+}
+
 #[derive(Debug)]
 pub struct ImplDebugger {
     process: Arc<Mutex<Process>>,
-    pending_breakpoints: Option<Vec<FileLocation>>,
+    pending_breakpoints: Option<Vec<(FileLocation, Option<Condition>)>>,
+    init_commands: Vec<String>,
+    caught_exceptions: bool,
 }
 
 impl ImplDebugger {
-    pub fn new(debugger_cmd: String, run_cmd: Vec<String>) -> ImplDebugger {
+    pub fn new(
+        debugger_cmd: String,
+        run_cmd: Vec<String>,
+        prompt: Option<String>,
+        init_commands: Vec<String>,
+        debugger_env: Vec<(String, String)>,
+    ) -> ImplDebugger {
+        let process = Process::new(debugger_cmd, run_cmd, debugger_env);
+        if let Some(prompt) = prompt {
+            process.set_prompt(prompt);
+        }
+
         ImplDebugger {
-            process: Arc::new(Mutex::new(Process::new(debugger_cmd, run_cmd))),
+            process: Arc::new(Mutex::new(process)),
             pending_breakpoints: Some(vec![]),
+            init_commands,
+            caught_exceptions: false,
         }
     }
 
-    fn check_process_running(
-        &self,
-    ) -> Option<Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>> {
-        match self.process.lock().unwrap().get_status() {
-            PDBStatus::None => {
-                let f = future::lazy(move || {
-                    let resp = serde_json::json!({"status":"ERROR"});
-                    Ok(resp)
-                });
-                return Some(Box::new(f));
-            }
-            _ => None,
+    fn toggle_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        kind: &str,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process_running() {
+            return f;
         }
-    }
-}
 
-impl DebuggerV1 for ImplDebugger {
-    fn setup(&mut self) {}
+        let file_location = match PathBuf::from(&file_location.name).canonicalize() {
+            Ok(s) => FileLocation::new(s.to_string_lossy().to_string(), file_location.line_num),
+            Err(_) => file_location.clone(),
+        };
+
+        let ids = self
+            .process
+            .lock()
+            .unwrap()
+            .get_breakpoints_set_at(&file_location);
+
+        if ids.is_empty() {
+            log_msg(
+                LogLevel::WARN,
+                &format!(
+                    "No breakpoint set in file {} at line number {}",
+                    file_location.name, file_location.line_num
+                ),
+            );
+            return Box::new(future::lazy(move || {
+                let resp = serde_json::json!({"status":"ERROR"});
+                Ok(resp)
+            }));
+        }
 
-    fn teardown(&mut self) {
-        exit(0);
+        let ids = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let stmt = format!("{} {}\n", kind, ids);
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        let f = future::lazy(move || {
+            let resp = serde_json::json!({"status":"OK"});
+            Ok(resp)
+        });
+
+        Box::new(f)
     }
 
-    fn run(
+    /// Step (or continue) `count` times, waiting each time for the program to stop
+    ///
+    /// If the program exits the response has `terminated` set. If it doesn't stop
+    /// within the StepTimeout it's still running and we just report OK.
+    fn step(
         &mut self,
+        kind: &str,
+        count: u64,
+        report_each: bool,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        let pending_breakpoints = match self.pending_breakpoints.take() {
-            Some(pb) => pb,
-            None => {
-                let msg = "Process already running, not launching";
-                eprintln!("{}", msg);
-                log_msg(LogLevel::WARN, msg);
-                let f = future::lazy(move || {
-                    let resp = serde_json::json!({"status":"ERROR"});
-                    Ok(resp)
-                });
-                return Box::new(f);
-            }
-        };
-
-        log_msg(LogLevel::INFO, "Launching process");
-
-        let (tx, rx) = mpsc::channel(1);
+        if let Some(f) = self.check_process_running() {
+            return f;
+        }
 
-        self.process
+        let stmt = format!("{}\n", kind);
+        let timeout = config.lock().unwrap().get_config("StepTimeout").unwrap() as u64;
+        let progress_interval = config
             .lock()
             .unwrap()
-            .add_listener(Listener::Launch, tx);
-
+            .get_config("StepProgressInterval")
+            .unwrap() as u64;
         let process = self.process.clone();
-        let process2 = self.process.clone();
+        let process_finished = self.process.clone();
 
-        let f = rx
-            .take(1)
-            .into_future()
-            .and_then(move |event| {
-                match event.0.unwrap() {
-                    Event::Launched => {
-                        for bkpt in &pending_breakpoints {
-                            let stmt = format!("break {}:{}\n", bkpt.name, bkpt.line_num);
-                            process
-                                .clone()
-                                .lock()
-                                .unwrap()
-                                .write_stdin(Bytes::from(stmt));
+        let f = future::loop_fn(count, move |remaining| {
+            let (tx, rx) = mpsc::channel(1);
+
+            let mut process = process.lock().unwrap();
+            process.report_stops(report_each || remaining == 1);
+            process.add_listener(Listener::Stopped, tx);
+            process.write_stdin(Bytes::from(stmt.clone()));
+
+            rx.take(1)
+                .into_future()
+                .timeout(Duration::new(timeout, 0))
+                .then(move |event| match event {
+                    Ok((Some(Event::Stopped(_)), _)) if remaining > 1 => {
+                        let done = count - remaining + 1;
+                        if progress_interval > 0 && done.is_multiple_of(progress_interval) {
+                            step_progress(done, count);
                         }
+                        Ok(future::Loop::Continue(remaining - 1))
                     }
-                    _ => unreachable!(),
-                }
-                Ok(())
-            })
-            .timeout(Duration::new(
-                config
-                    .lock()
-                    .unwrap()
-                    .get_config("ProcessSpawnTimeout")
-                    .unwrap() as u64,
-                0,
-            ))
-            .map(move |_| {
-                let pid = process2.lock().unwrap().get_pid();
-                serde_json::json!({"status":"OK","pid":pid})
-            })
-            .map_err(|e| {
-                eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out setting breakpoint")
-            });
-
-        self.process.lock().unwrap().run();
+                    Ok((None, _)) => Err(python_terminated()),
+                    Ok((Some(Event::ProcessExited(_)), _)) => Ok(future::Loop::Break(
+                        serde_json::json!({"status":"OK","terminated":true}),
+                    )),
+                    Ok(_) => Ok(future::Loop::Break(serde_json::json!({"status":"OK"}))),
+                    Err(ref e) if e.is_elapsed() => {
+                        Ok(future::Loop::Break(serde_json::json!({"status":"OK"})))
+                    }
+                    Err(e) => {
+                        eprintln!("Reading stdin error {:?}", e);
+                        Err(io::Error::other("Failed stepping"))
+                    }
+                })
+        })
+        .then(move |resp| {
+            // Where it stopped if it stopped early without it being reported
+            process_finished.lock().unwrap().report_last_stop();
+            resp
+        });
 
         Box::new(f)
     }
 
-    fn breakpoint(
+    /// Set a breakpoint, or add it to those set when the program is run if it isn't yet
+    fn set_breakpoint(
         &mut self,
         file_location: &FileLocation,
+        condition: Option<Condition>,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
         log_msg(
@@ -143,9 +197,10 @@ impl DebuggerV1 for ImplDebugger {
         match self.process.lock().unwrap().get_status() {
             PDBStatus::None => {
                 match self.pending_breakpoints {
-                    Some(ref mut x) => x.push(file_location.clone()),
+                    Some(ref mut x) => x.push((file_location.clone(), condition)),
                     None => {}
                 };
+                breakpoint_pending(&file_location.name, file_location.line_num);
                 let f = future::lazy(move || {
                     let resp = serde_json::json!({"status":"PENDING"});
                     Ok(resp)
@@ -173,21 +228,27 @@ impl DebuggerV1 for ImplDebugger {
                     .unwrap() as u64,
                 0,
             ))
-            .map(move |event| match event.0.unwrap() {
-                Event::BreakpointSet(_) => serde_json::json!({"status":"OK"}),
-                _ => unreachable!(),
-            })
-            .map_err(|e| {
-                eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out setting breakpoint")
+            .then(move |event| match event {
+                Ok((Some(Event::BreakpointSet(_)), _)) => Ok(serde_json::json!({"status":"OK"})),
+                Ok((None, _)) => Err(python_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out setting breakpoint",
+                    ))
+                }
             });
 
         let full_file_path = PathBuf::from(format!("{}", file_location.name));
         let full_file_name = full_file_path.canonicalize().unwrap();
-        let stmt = format!(
-            "break {}:{}\n",
-            full_file_name.to_str().unwrap(),
-            file_location.line_num
+        let stmt = break_stmt(
+            &FileLocation::new(
+                full_file_name.to_string_lossy().to_string(),
+                file_location.line_num,
+            ),
+            condition.as_ref(),
         );
 
         self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
@@ -195,113 +256,857 @@ impl DebuggerV1 for ImplDebugger {
         Box::new(f)
     }
 
-    fn step_in(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        match self.check_process_running() {
-            Some(f) => return f,
-            None => {}
-        };
+    fn check_process_running(
+        &self,
+    ) -> Option<Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>> {
+        match self.process.lock().unwrap().get_status() {
+            PDBStatus::None => Some(not_running()),
+            _ => None,
+        }
+    }
+}
+
+impl DebuggerV1 for ImplDebugger {
+    fn setup(&mut self) -> Result<(), io::Error> {
+        // pdb isn't started until the program is run
+        debugger_ready();
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new(
+            "python",
+            vec![
+                "run",
+                "breakpoint",
+                "disableBreakpoint",
+                "enableBreakpoint",
+                "clearAllBreakpoints",
+                "exceptionBreakpoint",
+                "stepIn",
+                "stepOver",
+                "continue",
+                "interrupt",
+                "finish",
+                "print",
+                "assert",
+                "typeOf",
+                "frameSource",
+                "raw",
+                "currentLocation",
+                "setNextStatement",
+                "setCwd",
+            ],
+        )
+    }
+
+    fn pid(&self) -> Option<u64> {
+        let process = self.process.lock().unwrap();
+        match process.get_status() {
+            PDBStatus::None => None,
+            _ => Some(process.get_pid()),
+        }
+    }
+
+    fn program_status(&self) -> ProgramStatus {
+        let process = self.process.lock().unwrap();
+        match process.get_status() {
+            PDBStatus::None => ProgramStatus::NotRunning,
+            _ if process.is_stopped() => ProgramStatus::Stopped,
+            _ => ProgramStatus::Running,
+        }
+    }
+
+    fn location(&self) -> Option<StopLocation> {
+        self.process.lock().unwrap().get_location()
+    }
+
+    fn teardown(&mut self, exit_code: i32) {
+        self.process.lock().unwrap().teardown();
+        exit(exit_code);
+    }
+
+    fn exit_code(&self) -> Option<i64> {
+        self.process.lock().unwrap().get_exit_code()
+    }
+
+    fn run(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if self.pending_breakpoints.is_none() {
+            let msg = "Process already running, not launching";
+            eprintln!("{}", msg);
+            log_msg(LogLevel::WARN, msg);
+            let f = future::lazy(move || {
+                let resp = serde_json::json!({"status":"ERROR"});
+                Ok(resp)
+            });
+            return Box::new(f);
+        }
+
+        log_msg(LogLevel::INFO, "Launching process");
+
+        let (tx, rx) = mpsc::channel(1);
 
         self.process
             .lock()
             .unwrap()
-            .write_stdin(Bytes::from("step\n"));
+            .add_listener(Listener::Launch, tx);
 
-        let f = future::lazy(move || {
-            let resp = serde_json::json!({"status":"OK"});
-            Ok(resp)
-        });
+        match self.process.lock().unwrap().run() {
+            Ok(_) => {}
+            Err(e) => return Box::new(future::lazy(move || Err(e))),
+        };
+
+        let pending_breakpoints = self.pending_breakpoints.take().unwrap();
+
+        let stop_on_entry = config.lock().unwrap().get_config("StopOnEntry") == Some(1);
+        let raw_timeout = config
+            .lock()
+            .unwrap()
+            .get_config("RawCommandTimeout")
+            .unwrap() as u64;
+
+        let process = self.process.clone();
+        let process2 = self.process.clone();
+        let process3 = self.process.clone();
+        let mut init_commands = self.init_commands.clone();
+        if self.caught_exceptions {
+            init_commands.push(exception_stmt(true));
+        }
+
+        let f = rx
+            .take(1)
+            .into_future()
+            .timeout(Duration::new(
+                config
+                    .lock()
+                    .unwrap()
+                    .get_config("ProcessSpawnTimeout")
+                    .unwrap() as u64,
+                0,
+            ))
+            .then(move |event| match event {
+                Ok((Some(Event::Launched), _)) => Ok(()),
+                Ok((None, _)) => Err(python_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out setting breakpoint",
+                    ))
+                }
+            })
+            .and_then(move |_| {
+                run_init_commands(process3, init_commands, raw_timeout).then(move |_| {
+                    for (bkpt, condition) in &pending_breakpoints {
+                        let stmt = break_stmt(bkpt, condition.as_ref());
+                        process
+                            .clone()
+                            .lock()
+                            .unwrap()
+                            .write_stdin(Bytes::from(stmt));
+                    }
+                    if !stop_on_entry {
+                        process
+                            .lock()
+                            .unwrap()
+                            .write_stdin(Bytes::from("continue\n"));
+                    }
+                    let pid = process2.lock().unwrap().get_pid();
+                    Ok(serde_json::json!({"status":"OK","pid":pid}))
+                })
+            });
 
         Box::new(f)
     }
 
-    fn step_over(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        match self.check_process_running() {
-            Some(f) => return f,
-            None => {}
-        };
-
-        self.process
+    fn set_cwd(
+        &mut self,
+        dir: &str,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let resp = self
+            .process
             .lock()
             .unwrap()
-            .write_stdin(Bytes::from("next\n"));
+            .set_cwd(dir.to_string())
+            .map(|_| serde_json::json!({"status":"OK"}));
+        Box::new(future::lazy(move || resp))
+    }
 
-        let f = future::lazy(move || {
-            let resp = serde_json::json!({"status":"OK"});
-            Ok(resp)
-        });
+    fn breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.set_breakpoint(file_location, None, config)
+    }
+
+    fn hit_condition_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        hit_condition: HitCondition,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.set_breakpoint(file_location, Some(Condition::Hit(hit_condition)), config)
+    }
+
+    fn logpoint(
+        &mut self,
+        file_location: &FileLocation,
+        message: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.set_breakpoint(
+            file_location,
+            Some(Condition::Log(message.to_string())),
+            config,
+        )
+    }
+
+    fn disable_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.toggle_breakpoint(file_location, "disable")
+    }
+
+    fn enable_breakpoint(
+        &mut self,
+        file_location: &FileLocation,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.toggle_breakpoint(file_location, "enable")
+    }
+
+    /// pdb asks whether to clear every breakpoint so the answer's sent with the command
+    fn clear_all_breakpoints(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let process = self.process.clone();
+
+        let f = self
+            .raw("clear\ny", config)
+            .map(move |resp| match resp["output"].as_str() {
+                Some(output) => {
+                    let removed = output.matches("Deleted breakpoint ").count();
+                    process.lock().unwrap().clear_breakpoints();
+                    log_msg(LogLevel::INFO, &format!("Removed {} breakpoints", removed));
+                    serde_json::json!({"status":"OK"})
+                }
+                None => resp,
+            });
 
         Box::new(f)
     }
 
-    fn continue_(&mut self) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        match self.check_process_running() {
-            Some(f) => return f,
-            None => {}
-        };
+    /// pdb always stops after an uncaught exception so only stopping on caught exceptions
+    /// can be changed, which is done by tracing every frame for where an exception's raised
+    fn exception_breakpoint(
+        &mut self,
+        caught: bool,
+        _uncaught: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.caught_exceptions = caught;
 
-        self.process
+        // Set when the program is run if it isn't yet
+        if let PDBStatus::None = self.process.lock().unwrap().get_status() {
+            return Box::new(future::lazy(move || {
+                Ok(serde_json::json!({"status":"OK","caught":caught,"uncaught":true}))
+            }));
+        }
+
+        let timeout = config
             .lock()
             .unwrap()
-            .write_stdin(Bytes::from("continue\n"));
+            .get_config("RawCommandTimeout")
+            .unwrap() as u64;
 
-        let f = future::lazy(move || {
-            let resp = serde_json::json!({"status":"OK"});
-            Ok(resp)
+        let f = raw_command(&self.process, &exception_stmt(caught), timeout).map(move |output| {
+            match output.lines().find(|l| l.starts_with("*** ")) {
+                Some(error) => {
+                    log_msg(
+                        LogLevel::ERROR,
+                        &format!("Can't set exception breakpoint: {}", error),
+                    );
+                    serde_json::json!({"status":"ERROR"})
+                }
+                None => serde_json::json!({"status":"OK","caught":caught,"uncaught":true}),
+            }
         });
 
         Box::new(f)
     }
 
-    fn print(
+    fn step_in(
         &mut self,
-        variable: &Variable,
+        count: u64,
+        report_each: bool,
         config: Arc<Mutex<Config>>,
     ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
-        match self.check_process_running() {
-            Some(f) => return f,
-            None => {}
-        };
+        self.step("step", count, report_each, config)
+    }
+
+    fn step_over(
+        &mut self,
+        count: u64,
+        report_each: bool,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("next", count, report_each, config)
+    }
+
+    fn continue_(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        self.step("continue", 1, true, config)
+    }
+
+    fn finish(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let process = self.process.clone();
+
+        let f = self.step("return", 1, true, config).map(move |mut resp| {
+            if resp["terminated"].is_null() {
+                resp["returnValue"] = serde_json::json!(process.lock().unwrap().get_return_value());
+            }
+            resp
+        });
+
+        Box::new(f)
+    }
+
+    fn interrupt(
+        &mut self,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process_running() {
+            return f;
+        }
 
         let (tx, rx) = mpsc::channel(1);
 
         self.process
             .lock()
             .unwrap()
-            .set_status(PDBStatus::Printing(variable.clone()));
+            .add_listener(Listener::Stopped, tx);
 
-        self.process
-            .lock()
-            .unwrap()
-            .add_listener(Listener::PrintVariable, tx);
+        match self.process.lock().unwrap().interrupt() {
+            Ok(_) => {}
+            Err(e) => return Box::new(future::lazy(move || Err(e))),
+        };
 
         let f = rx
             .take(1)
             .into_future()
             .timeout(Duration::new(
-                config
-                    .lock()
-                    .unwrap()
-                    .get_config("PrintVariableTimeout")
-                    .unwrap() as u64,
+                config.lock().unwrap().get_config("StepTimeout").unwrap() as u64,
                 0,
             ))
-            .map(move |event| match event.0.unwrap() {
-                Event::PrintVariable(variable, value) => serde_json::json!({
+            .then(move |event| match event {
+                Ok((Some(Event::Stopped(_)), _)) => Ok(serde_json::json!({"status":"OK"})),
+                Ok((Some(Event::ProcessExited(_)), _)) => {
+                    Ok(serde_json::json!({"status":"OK","terminated":true}))
+                }
+                Ok((None, _)) => Err(python_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out interrupting process",
+                    ))
+                }
+            });
+
+        Box::new(f)
+    }
+
+    fn current_location(
+        &mut self,
+        _: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let location = self.process.lock().unwrap().get_location();
+
+        Box::new(future::lazy(move || {
+            Ok(serde_json::json!({"status":"OK","location":location}))
+        }))
+    }
+
+    /// Uses pdb's `jump`, which can only jump within the frame the program's stopped in, so
+    /// the line has to be in the same file
+    fn set_next_statement(
+        &mut self,
+        file_location: &FileLocation,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process_running() {
+            return f;
+        }
+
+        let same_file = match self.process.lock().unwrap().get_location() {
+            Some(location) => absolute_path(location.file()) == absolute_path(&file_location.name),
+            None => false,
+        };
+
+        if !same_file {
+            log_msg(
+                LogLevel::WARN,
+                &format!(
+                    "Can't jump to {}:{}, pdb can only jump to a line in the file it's stopped in",
+                    file_location.name, file_location.line_num
+                ),
+            );
+            return Box::new(future::ok(serde_json::json!({"status":"ERROR"})));
+        }
+
+        let (tx, rx) = mpsc::channel(1);
+
+        self.process.lock().unwrap().add_listener(Listener::Raw, tx);
+
+        let timeout = config.lock().unwrap().get_config("StepTimeout").unwrap() as u64;
+        let process = self.process.clone();
+        let line = file_location.line_num;
+
+        // pdb prints the new position when the jump works, which is reported as usual
+        let f = rx
+            .take(1)
+            .into_future()
+            .timeout(Duration::new(timeout, 0))
+            .then(move |event| match event {
+                Ok((Some(Event::RawOutput(output)), _)) => {
+                    match output.lines().find(|l| l.starts_with("*** ")) {
+                        Some(error) => {
+                            log_msg(
+                                LogLevel::WARN,
+                                &format!("Can't jump to line {}: {}", line, &error["*** ".len()..]),
+                            );
+                            Ok(serde_json::json!({"status":"ERROR"}))
+                        }
+                        None => {
+                            let location = process.lock().unwrap().get_location();
+                            Ok(serde_json::json!({"status":"OK","location":location}))
+                        }
+                    }
+                }
+                Ok((None, _)) => Err(python_terminated()),
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    eprintln!("Reading stdin error {:?}", e);
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out jumping to another line",
+                    ))
+                }
+            });
+
+        let stmt = format!("jump {}\n", line);
+
+        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+        Box::new(f)
+    }
+
+    fn print(
+        &mut self,
+        variable: &Variable,
+        frame: Option<u64>,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        match self.check_process_running() {
+            Some(f) => return f,
+            None => {}
+        };
+
+        let typed_values = config.lock().unwrap().get_config("TypedValues") == Some(1);
+        let max_bytes = config.lock().unwrap().get_config("MaxValueBytes").unwrap() as usize;
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
+
+        let print_stmt = |expression: &str| match frame {
+            Some(frame) => print_in_frame_stmt(expression, frame),
+            None => format!("print({})\n", expression),
+        };
+
+        // The type's printed on the line before the value so that the expression's only
+        // evaluated once, if it can't be found the value's still given with an unknown type
+        let stmt = print_stmt(&format!(
+            "(lambda v: type(v).__name__ + '\\n' + str(v))(({}))",
+            variable.name
+        ));
+        let variable = variable.clone();
+
+        let f =
+            print_value(self.process.clone(), variable.clone(), stmt, timeout).map(move |output| {
+                let (type_, value) = match output.starts_with("*** ") {
+                    true => (None, &output[..]),
+                    false => {
+                        let mut parts = output.splitn(2, '\n');
+                        let type_ = parts.next().map(|t| t.trim_end_matches('\r'));
+                        (type_, parts.next().unwrap_or(""))
+                    }
+                };
+                let type_ = match type_ {
+                    Some(t) => t.to_string(),
+                    None => {
+                        log_msg(
+                            LogLevel::WARN,
+                            &format!("Can't get the type of '{}'", variable.name),
+                        );
+                        "unknown".to_string()
+                    }
+                };
+                let value = truncate_value(value, max_bytes);
+                serde_json::json!({
                     "status": "OK",
                     "variable": variable.name,
-                    "value": value,
-                }),
-                _ => unreachable!(),
+                    "value": match typed_values {
+                        true => typed_value(&value),
+                        false => serde_json::json!(value),
+                    },
+                    "type": type_,
+                })
+            });
+
+        Box::new(f)
+    }
+
+    /// The type is the name of the expression's class, e.g. `int` or `dict`
+    fn type_of(
+        &mut self,
+        expression: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process_running() {
+            return f;
+        }
+
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("PrintVariableTimeout")
+            .unwrap() as u64;
+
+        let type_variable = Variable::new(format!("type({}).__name__", expression));
+        let stmt = format!("print({})\n", type_variable.name);
+        let expression = expression.to_string();
+
+        let f = print_value(self.process.clone(), type_variable, stmt, timeout).map(move |type_| {
+            // pdb shows errors evaluating it prefixed by `***`
+            if let Some(error) = type_.strip_prefix("*** ") {
+                log_msg(
+                    LogLevel::WARN,
+                    &format!("Can't get the type of '{}': {}", expression, error),
+                );
+                return serde_json::json!({"status":"ERROR"});
+            }
+            serde_json::json!({
+                "status": "OK",
+                "expression": expression,
+                "type": type_,
             })
-            .map_err(|e| {
-                eprintln!("Reading stdin error {:?}", e);
-                io::Error::new(io::ErrorKind::Other, "Timed out printing variable")
+        });
+
+        Box::new(f)
+    }
+
+    /// Python's `bool` decides whether the expression is true, the value is printed the
+    /// same as `print` prints it
+    fn assert(
+        &mut self,
+        expression: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let typed_values = config.lock().unwrap().get_config("TypedValues") == Some(1);
+
+        let variable = Variable::new(format!(
+            "(lambda v: str(bool(v)) + ' ' + str(v))(({}))",
+            expression
+        ));
+        let expression = expression.to_string();
+
+        let f = self.print(&variable, None, config).and_then(move |resp| {
+            match (&resp["status"], resp["value"].as_str()) {
+                (status, Some(output)) if status == "OK" => {
+                    assert_response(&expression, output, typed_values)
+                }
+                _ => Ok(resp),
+            }
+        });
+
+        Box::new(f)
+    }
+
+    /// pdb's `list` lists the file of the current frame
+    fn frame_source(
+        &mut self,
+        before: u64,
+        after: u64,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        let location = match self.process.lock().unwrap().get_location() {
+            Some(location) => location,
+            None => {
+                log_msg(LogLevel::WARN, "The program isn't stopped anywhere");
+                return Box::new(future::lazy(|| Ok(serde_json::json!({"status":"ERROR"}))));
+            }
+        };
+
+        let start = location.line().saturating_sub(before).max(1);
+        let end = location.line() + after;
+
+        let f = self
+            .raw(&format!("list {}, {}", start, end), config)
+            .map(move |resp| match resp["output"].as_str() {
+                Some(output) => source_listing_response(output, start, end),
+                None => resp,
             });
 
-        let stmt = format!("print({})\n", variable.name);
+        Box::new(f)
+    }
 
-        self.process.lock().unwrap().write_stdin(Bytes::from(stmt));
+    fn raw(
+        &mut self,
+        command: &str,
+        config: Arc<Mutex<Config>>,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+        if let Some(f) = self.check_process_running() {
+            return f;
+        }
+
+        let timeout = config
+            .lock()
+            .unwrap()
+            .get_config("RawCommandTimeout")
+            .unwrap() as u64;
+
+        let f = raw_command(&self.process, command, timeout).map(|output| {
+            serde_json::json!({
+                "status": "OK",
+                "output": output,
+            })
+        });
 
         Box::new(f)
     }
 }
+
+/// The pdb command to set a breakpoint
+///
+/// A hit condition is set as the breakpoint's condition, checking the number of hits pdb
+/// has counted for it by its location so that it doesn't need the breakpoint number. A
+/// logpoint's condition prints its message and is always false so it never stops.
+fn break_stmt(file_location: &FileLocation, condition: Option<&Condition>) -> String {
+    let location = format!("{}:{}", file_location.name, file_location.line_num);
+
+    let hit_condition = match condition {
+        Some(Condition::Hit(h)) => h,
+        Some(Condition::Log(message)) => {
+            return format!("break {}, {}\n", location, log_condition(message))
+        }
+        None => return format!("break {}\n", location),
+    };
+
+    let hits = format!(
+        "__import__('bdb').Breakpoint.bplist[({:?}, {})][-1].hits",
+        file_location.name, file_location.line_num
+    );
+
+    let condition = match *hit_condition {
+        HitCondition::AtLeast(n) => format!("{} >= {}", hits, n),
+        HitCondition::Above(n) => format!("{} > {}", hits, n),
+        HitCondition::Equal(n) => format!("{} == {}", hits, n),
+        HitCondition::Multiple(n) => format!("{} % {} == 0", hits, n),
+    };
+
+    format!("break {}, {}\n", location, condition)
+}
+
+/// Print a logpoint's message with its expressions evaluated, `print` returns `None` so
+/// as a condition this never stops
+fn log_condition(message: &str) -> String {
+    let message = parse_log_message(message)
+        .iter()
+        .map(|part| match part {
+            LogMessagePart::Text(s) => serde_json::to_string(s).unwrap(),
+            LogMessagePart::Expression(e) => format!("str(({}))", e),
+        })
+        .collect::<Vec<String>>();
+
+    format!(
+        "print({}, {}, sep='', flush=True)",
+        serde_json::to_string(LOGPOINT_PREFIX).unwrap(),
+        message.join(", ")
+    )
+}
+
+/// Print an expression evaluated in a frame further up the stack without moving there
+///
+/// The frame is found from pdb's stack, pdb runs the statement in its `default` method so
+/// that's the frame above it. A frame past the top of the stack fails with an
+/// `IndexError`.
+fn print_in_frame_stmt(expression: &str, frame: u64) -> String {
+    format!(
+        "print((lambda d, n: (lambda f: eval({}, f.f_globals, f.f_locals))(d.stack[: d.curindex + 1][-1 - n][0]))(__import__('sys')._getframe(1).f_locals['self'], {}))\n",
+        serde_json::to_string(expression).unwrap(),
+        frame
+    )
+}
+
+/// The statement to make pdb stop wherever an exception's raised, or stop doing so
+///
+/// pdb only stops on an exception in a frame it's stepping in, and stops tracing
+/// altogether on `continue` with no breakpoints, so its `Pdb` instance is changed to keep
+/// tracing every frame and stop in the frame an exception's raised in. The generator
+/// exceptions Python raises itself are ignored.
+fn exception_stmt(caught: bool) -> String {
+    let code = "from bdb import BdbQuit
+for name in ('dispatch_call', 'dispatch_exception', 'set_continue'):
+    d.__dict__.pop(name, None)
+if caught:
+    call, exception = d.dispatch_call, d.dispatch_exception
+    def dispatch_call(frame, arg):
+        return call(frame, arg) or d.trace_dispatch
+    def dispatch_exception(frame, arg):
+        if d.stop_here(frame) or arg[2].tb_next is not None or issubclass(arg[0], (StopIteration, StopAsyncIteration, GeneratorExit)):
+            return exception(frame, arg)
+        d.user_exception(frame, arg)
+        if d.quitting:
+            raise BdbQuit
+        return d.trace_dispatch
+    d.dispatch_call, d.dispatch_exception = dispatch_call, dispatch_exception
+    d.set_continue = lambda: d._set_stopinfo(d.botframe, None, -1)
+    frame = d.curframe
+    while frame is not None and frame is not d.botframe:
+        frame.f_trace = d.trace_dispatch
+        frame = frame.f_back
+";
+
+    format!(
+        "exec({}, {{'d': __import__('sys')._getframe(1).f_locals['self'], 'caught': {}}})",
+        serde_json::to_string(code).unwrap(),
+        if caught { "True" } else { "False" }
+    )
+}
+
+/// Send a statement to pdb that prints something and return what it printed
+fn print_value(
+    process: Arc<Mutex<Process>>,
+    variable: Variable,
+    stmt: String,
+    timeout: u64,
+) -> Box<dyn Future<Item = String, Error = io::Error> + Send> {
+    let (tx, rx) = mpsc::channel(1);
+
+    process
+        .lock()
+        .unwrap()
+        .set_status(PDBStatus::Printing(variable));
+
+    process
+        .lock()
+        .unwrap()
+        .add_listener(Listener::PrintVariable, tx);
+
+    let f = rx
+        .take(1)
+        .into_future()
+        .timeout(Duration::new(timeout, 0))
+        .then(move |event| match event {
+            Ok((Some(Event::PrintVariable(_, value)), _)) => Ok(value),
+            Ok((None, _)) => Err(python_terminated()),
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                eprintln!("Reading stdin error {:?}", e);
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Timed out printing variable",
+                ))
+            }
+        });
+
+    process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+    Box::new(f)
+}
+
+/// Send a command to pdb and return everything it outputs before the next prompt
+fn raw_command(
+    process: &Arc<Mutex<Process>>,
+    command: &str,
+    timeout: u64,
+) -> Box<dyn Future<Item = String, Error = io::Error> + Send> {
+    let (tx, rx) = mpsc::channel(1);
+
+    process.lock().unwrap().add_listener(Listener::Raw, tx);
+
+    let f = rx
+        .take(1)
+        .into_future()
+        .timeout(Duration::new(timeout, 0))
+        .then(move |event| match event {
+            Ok((Some(Event::RawOutput(output)), _)) => Ok(output),
+            Ok((None, _)) => Err(python_terminated()),
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                eprintln!("Reading stdin error {:?}", e);
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Timed out running raw command",
+                ))
+            }
+        });
+
+    let stmt = format!("{}\n", command);
+
+    process.lock().unwrap().write_stdin(Bytes::from(stmt));
+
+    Box::new(f)
+}
+
+/// Send the commands given with `--init-commands` to pdb once it's started, before any
+/// breakpoints are set
+///
+/// Each command is waited on before sending the next so they're run in order, one that
+/// fails is logged and the rest are still sent.
+fn run_init_commands(
+    process: Arc<Mutex<Process>>,
+    commands: Vec<String>,
+    timeout: u64,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let f = stream::iter_ok(commands).for_each(move |command| {
+        raw_command(&process, &command, timeout).then(move |output| {
+            match output {
+                Ok(ref output) if !output.lines().any(|l| l.starts_with("*** ")) => {}
+                Ok(output) => log_msg(
+                    LogLevel::WARN,
+                    &format!("Init command '{}' failed: {}", command, output.trim()),
+                ),
+                Err(e) => log_msg(
+                    LogLevel::WARN,
+                    &format!("Init command '{}' failed: {}", command, e),
+                ),
+            }
+            Ok(())
+        })
+    });
+
+    Box::new(f)
+}
+
+/// The error reported for any command when the Python debugger itself has gone away
+fn python_terminated() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Python debugger has terminated")
+}
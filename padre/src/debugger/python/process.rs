@@ -5,19 +5,21 @@
 //! happening then.
 
 use std::collections::HashMap;
-use std::io::BufReader;
-use std::path::Path;
-#[cfg(not(test))]
-use std::process::exit;
+use std::io::{self, BufReader};
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
 
-use crate::debugger::{FileLocation, Variable};
-use crate::notifier::{breakpoint_set, jump_to_position, signal_exited};
+use crate::debugger::cwd_after_run_error;
+use crate::debugger::{FileLocation, StopLocation, Variable, LOGPOINT_PREFIX};
+use crate::notifier::{
+    breakpoint_set, jump_to_position, jump_to_position_with_source, logpoint_hit, protocol_trace,
+    signal_exited,
+};
 use crate::notifier::{log_msg, LogLevel};
-#[cfg(not(test))]
-use crate::util::{file_exists, get_file_full_path};
-use crate::util::{read_output, setup_stdin};
+use crate::util::{
+    absolute_path, file_exists, get_file_full_path, read_output, setup_stdin, split_lines,
+    split_run_cmd,
+};
 
 use bytes::Bytes;
 use regex::Regex;
@@ -35,11 +37,15 @@ pub enum PDBStatus {
 /// You can register to listen for one of the following events:
 /// - Breakpoint: A breakpoint event has happened
 /// - PrintVariable: A variable printing event
+/// - Raw: A raw command has been sent to pdb and this is the output up to the next prompt
+/// - Stopped: The program has stopped at a position or has exited
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum Listener {
     Launch,
     Breakpoint,
     PrintVariable,
+    Raw,
+    Stopped,
 }
 
 /// A Python event is something that can be registered for being listened to and can be triggered
@@ -49,6 +55,10 @@ pub enum Event {
     Launched,
     BreakpointSet(FileLocation),
     PrintVariable(Variable, String),
+    RawOutput(String),
+    Stopped(FileLocation),
+    // (Exit code)
+    ProcessExited(i64),
 }
 
 /// Main handler for spawning the Python process
@@ -56,6 +66,8 @@ pub enum Event {
 pub struct Process {
     debugger_cmd: Option<String>,
     run_cmd: Option<Vec<String>>,
+    cwd: Option<String>,
+    env: Vec<(String, String)>,
     process: Option<Child>,
     stdin_tx: Option<Sender<Bytes>>,
     analyser: Arc<Mutex<Analyser>>,
@@ -63,10 +75,12 @@ pub struct Process {
 
 impl Process {
     /// Create a new Process
-    pub fn new(debugger_cmd: String, run_cmd: Vec<String>) -> Self {
+    pub fn new(debugger_cmd: String, run_cmd: Vec<String>, env: Vec<(String, String)>) -> Self {
         Process {
             debugger_cmd: Some(debugger_cmd),
             run_cmd: Some(run_cmd),
+            cwd: None,
+            env,
             process: None,
             stdin_tx: None,
             analyser: Arc::new(Mutex::new(Analyser::new())),
@@ -79,20 +93,51 @@ impl Process {
     /// In particular:
     /// - Sets up a `ReadOutput` from `util.rs` in order to read stdout and stderr;
     /// - Sets up a thread to read stdin and forward it onto Python interpreter;
-    /// - Checks that Python and the program to be ran both exist, otherwise panics.
-    pub fn run(&mut self) {
+    /// - Checks that Python exists, otherwise returns an error.
+    pub fn run(&mut self) -> Result<(), io::Error> {
+        // Now check the debugger exists, if not error
+        let debugger_cmd = self.debugger_cmd.as_ref().unwrap();
+        if !file_exists(debugger_cmd) && !file_exists(&get_file_full_path(debugger_cmd)) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Can't spawn debugger as {} does not exist", debugger_cmd),
+            ));
+        }
+
         let debugger_cmd = self.debugger_cmd.take().unwrap();
         let run_cmd = self.run_cmd.take().unwrap();
 
-        let args = get_python_args(&debugger_cmd[..], run_cmd.iter().map(|x| &x[..]).collect());
+        let mut args: Vec<String> =
+            get_python_args(&debugger_cmd[..], run_cmd.iter().map(|x| &x[..]).collect())
+                .iter()
+                .map(|x| x.to_string())
+                .collect();
+
+        let mut command = Command::new(&debugger_cmd);
+
+        // The script has to be found from the working directory
+        if let Some(dir) = &self.cwd {
+            if let Some(i) = args.iter().position(|x| x == "--") {
+                if let Some(script) = args.get_mut(i + 1) {
+                    *script = absolute_path(script);
+                }
+            }
+            command.current_dir(dir);
+        }
 
-        let mut process = Command::new(&debugger_cmd)
+        let mut process = command
             .args(&args)
+            .envs(self.env.iter().cloned())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn_async()
-            .expect("Failed to spawn debugger");
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Can't spawn debugger {}: {}", debugger_cmd, e),
+                )
+            })?;
 
         self.setup_stdout(
             process
@@ -118,6 +163,8 @@ impl Process {
 
         self.stdin_tx = Some(stdin_tx);
         self.process = Some(process);
+
+        Ok(())
     }
 
     pub fn add_listener(&self, kind: Listener, sender: Sender<Event>) {
@@ -128,16 +175,91 @@ impl Process {
         self.process.as_ref().unwrap().id() as u64
     }
 
+    /// Set the directory the program is run in, only before it's been run
+    pub fn set_cwd(&mut self, dir: String) -> Result<(), io::Error> {
+        if self.process.is_some() {
+            return Err(cwd_after_run_error());
+        }
+        self.cwd = Some(dir);
+        Ok(())
+    }
+
+    /// Drop the child process, killing it if it's still running
+    pub fn teardown(&mut self) {
+        self.process = None;
+    }
+
+    /// Interrupt the running program by sending it SIGINT, pdb then stops wherever the
+    /// program has got to
+    pub fn interrupt(&self) -> Result<(), io::Error> {
+        let status = Command::new("kill")
+            .args(["-INT", &self.get_pid().to_string()])
+            .status()?;
+
+        match status.success() {
+            true => Ok(()),
+            false => Err(io::Error::other(format!(
+                "Can't interrupt process {}",
+                self.get_pid()
+            ))),
+        }
+    }
+
     pub fn get_status(&self) -> PDBStatus {
         self.analyser.lock().unwrap().get_status()
     }
 
+    /// Use a different prompt to the usual one to tell when the debugger's finished
+    /// a command
+    pub fn set_prompt(&self, prompt: String) {
+        self.analyser.lock().unwrap().prompt = prompt;
+    }
+
+    pub fn get_location(&self) -> Option<StopLocation> {
+        self.analyser.lock().unwrap().location.clone()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.analyser.lock().unwrap().is_stopped()
+    }
+
+    pub fn get_exit_code(&self) -> Option<i64> {
+        self.analyser.lock().unwrap().exit_code
+    }
+
+    /// Set whether the stops of the next step are notified, any stop before that wasn't
+    /// is forgotten
+    pub fn report_stops(&self, report: bool) {
+        self.analyser.lock().unwrap().report_stops(report);
+    }
+
+    /// Notify of stops again, including the last one if it wasn't
+    pub fn report_last_stop(&self) {
+        self.analyser.lock().unwrap().report_last_stop();
+    }
+
+    pub fn get_return_value(&self) -> Option<String> {
+        self.analyser.lock().unwrap().return_value.clone()
+    }
+
+    pub fn get_breakpoints_set_at(&self, file_location: &FileLocation) -> Vec<u64> {
+        self.analyser
+            .lock()
+            .unwrap()
+            .get_breakpoints_set_at(file_location)
+    }
+
+    pub fn clear_breakpoints(&self) {
+        self.analyser.lock().unwrap().breakpoints.clear();
+    }
+
     pub fn set_status(&self, status: PDBStatus) {
-        self.analyser.lock().unwrap().status = status;
+        self.analyser.lock().unwrap().set_status(status);
     }
 
     /// Send a message to write to stdin
     pub fn write_stdin(&mut self, bytes: Bytes) {
+        protocol_trace("command", &String::from_utf8_lossy(&bytes));
         let tx = self.stdin_tx.clone();
         tokio::spawn(
             tx.clone()
@@ -149,8 +271,12 @@ impl Process {
     }
 
     /// Perform setup of reading Python stdout, analysing it and writing it back to stdout.
+    ///
+    /// When Python's stdout closes the debugger has gone away so the analyser is told
+    /// about it.
     fn setup_stdout(&mut self, stdout: ChildStdout) {
         let analyser = self.analyser.clone();
+        let analyser_eof = self.analyser.clone();
         tokio::spawn(
             read_output(BufReader::new(stdout))
                 .for_each(move |text| {
@@ -158,7 +284,11 @@ impl Process {
                     analyser.lock().unwrap().analyse_stdout(&text);
                     Ok(())
                 })
-                .map_err(|e| eprintln!("Err reading Python stdout: {}", e)),
+                .map_err(|e| eprintln!("Err reading Python stdout: {}", e))
+                .then(move |_| {
+                    analyser_eof.lock().unwrap().debugger_terminated();
+                    Ok(())
+                }),
         );
     }
 
@@ -175,31 +305,18 @@ impl Process {
     }
 }
 
+/// Notify of a position pdb has stopped at, with the source line if pdb showed it
+fn notify_position(file: &str, line: u64, source_line: Option<&str>) {
+    match source_line {
+        Some(s) => jump_to_position_with_source(file, line, s),
+        None => jump_to_position(file, line, None),
+    }
+}
+
 /// Work out the arguments to send to python based on the python command given and the
 /// run command specified
 fn get_python_args<'a>(debugger_cmd: &str, run_cmd: Vec<&'a str>) -> Vec<&'a str> {
-    let mut python_args = vec![];
-    let mut script_args = vec![];
-
-    // Now check the debugger and program to debug exist, if not error
-    #[cfg(not(test))]
-    {
-        // Try getting the full path if the debugger doesn't exist
-        if !file_exists(&debugger_cmd) {
-            let debugger_cmd = get_file_full_path(&debugger_cmd);
-
-            if !file_exists(&debugger_cmd) {
-                let msg = format!("Can't spawn debugger as {} does not exist", debugger_cmd);
-                log_msg(LogLevel::CRITICAL, &msg);
-                println!("{}", msg);
-
-                exit(1);
-            }
-        }
-    }
-
-    python_args.push("-m");
-    python_args.push("pdb");
+    let mut python_args = vec!["-m", "pdb"];
 
     // If we have the command `python -m mymod` say and `python` is specified
     // as the debugger then we have then we don't want to run
@@ -207,43 +324,11 @@ fn get_python_args<'a>(debugger_cmd: &str, run_cmd: Vec<&'a str>) -> Vec<&'a str
     // On the other hand if we specified `./script.py -a test` we want that to
     // run
     // `python -m pdb -- ./script.py -a test`
-    // so we keep track of whether they're likely to be a python arg or a script
-    // arg here.
-    //
-    // tl;dr We assume all args are script args if the 0th element doesn't
-    // match the debugger, if it does we wait until we see `--` and then we
-    // assume script args.
-    let mut is_script_arg = true;
-
-    for (i, arg) in run_cmd.iter().enumerate() {
-        // Skip the python part if specified as we add that from the -d option
-        if i == 0 {
-            let debugger_cmd_path = Path::new(debugger_cmd);
-
-            let debugger_cmd = match debugger_cmd_path.file_name() {
-                Some(s) => s.to_str().unwrap(),
-                None => debugger_cmd,
-            };
-
-            if debugger_cmd == *arg {
-                is_script_arg = false;
-                continue;
-            } else {
-                is_script_arg = true;
-            }
-        }
-
-        if *arg == "--" {
-            is_script_arg = true;
-            continue;
-        }
+    // so the run command is split into python args and script args by
+    // `split_run_cmd` the same as for every debugger.
+    let (mut debugger_args, mut script_args) = split_run_cmd(debugger_cmd, run_cmd);
 
-        if is_script_arg {
-            script_args.push(&arg[..]);
-        } else {
-            python_args.push(arg);
-        }
-    }
+    python_args.append(&mut debugger_args);
 
     if script_args.len() > 0 {
         python_args.push("--");
@@ -253,11 +338,31 @@ fn get_python_args<'a>(debugger_cmd: &str, run_cmd: Vec<&'a str>) -> Vec<&'a str
     python_args
 }
 
+/// The prompt pdb shows unless told otherwise with `--prompt`
+const DEFAULT_PROMPT: &str = "(Pdb) ";
+
 #[derive(Debug)]
 pub struct Analyser {
     status: PDBStatus,
     pid: Option<u64>,
+    // The exit code the program last finished with, pdb restarts it after
+    exit_code: Option<i64>,
+    terminated: bool,
+    raw_output: Option<String>,
+    // What's been printed so far for the variable being printed
+    print_output: Option<String>,
+    // Where pdb last stopped, pdb doesn't say why
+    location: Option<StopLocation>,
+    // Only the last stop of a counted step is notified unless every one is asked for, the
+    // position and source line of one that wasn't is kept in case the step ends there
+    reporting_location: bool,
+    unreported_location: Option<(String, u64, Option<String>)>,
+    // The value returned if pdb last stopped returning from a function
+    return_value: Option<String>,
+    breakpoints: HashMap<u64, FileLocation>,
     listeners: HashMap<Listener, Sender<Event>>,
+    // Shown by pdb when it's ready for the next command
+    prompt: String,
 }
 
 impl Analyser {
@@ -265,7 +370,17 @@ impl Analyser {
         Analyser {
             status: PDBStatus::None,
             pid: None,
+            exit_code: None,
+            terminated: false,
+            raw_output: None,
+            print_output: None,
+            location: None,
+            reporting_location: true,
+            unreported_location: None,
+            return_value: None,
+            breakpoints: HashMap::new(),
             listeners: HashMap::new(),
+            prompt: DEFAULT_PROMPT.to_string(),
         }
     }
 
@@ -273,6 +388,16 @@ impl Analyser {
         self.status.clone()
     }
 
+    fn set_status(&mut self, status: PDBStatus) {
+        if status != self.status {
+            protocol_trace("status", &format!("{:?} -> {:?}", self.status, status));
+        }
+        if let PDBStatus::Printing(_) = status {
+            self.print_output = None;
+        }
+        self.status = status;
+    }
+
     pub fn analyse_stdout(&mut self, s: &str) {
         lazy_static! {
             static ref RE_BREAKPOINT: Regex =
@@ -288,8 +413,16 @@ impl Analyser {
                     .unwrap();
         }
 
-        for line in s.split("\n") {
-            if line.contains("(Pdb) ") {
+        let lines = split_lines(s);
+
+        for (i, line) in lines.iter().enumerate() {
+            // pdb shows the source of the line it stopped at on the following line
+            let source_line = match lines.get(i + 1) {
+                Some(l) if l.starts_with("-> ") => Some(&l[3..]),
+                _ => None,
+            };
+
+            if line.contains(&self.prompt) {
                 match self.status {
                     PDBStatus::None => {
                         self.python_launched();
@@ -299,32 +432,39 @@ impl Analyser {
             }
 
             for cap in RE_BREAKPOINT.captures_iter(line) {
+                let id = cap[1].parse::<u64>().unwrap();
                 let file = cap[2].to_string();
                 let line = cap[3].parse::<u64>().unwrap();
-                self.found_breakpoint(file, line);
+                self.found_breakpoint(id, file, line);
             }
 
             for cap in RE_RETURNING.captures_iter(line) {
                 let file = cap[1].to_string();
                 let line = cap[2].parse::<u64>().unwrap();
                 let return_value = cap[3].to_string();
-                jump_to_position(&file, line);
                 log_msg(LogLevel::INFO, &format!("Returning value {}", return_value));
+                self.return_value = Some(return_value);
+                self.jump_to_position(&file, line, source_line);
             }
 
             for cap in RE_JUMP_TO_POSITION.captures_iter(line) {
                 let file = cap[1].to_string();
                 let line = cap[2].parse::<u64>().unwrap();
-                jump_to_position(&file, line);
+                self.return_value = None;
+                self.jump_to_position(&file, line, source_line);
+            }
+
+            if let Some(message) = line.strip_prefix(LOGPOINT_PREFIX) {
+                logpoint_hit(message);
             }
 
             for _ in RE_PROCESS_EXITED.captures_iter(line) {
-                signal_exited(self.pid.unwrap(), 0);
+                self.process_exited(0);
             }
 
             for cap in RE_PROCESS_EXITED_WITH_CODE.captures_iter(line) {
                 let exit_code = cap[1].parse::<i64>().unwrap();
-                signal_exited(self.pid.unwrap(), exit_code);
+                self.process_exited(exit_code);
             }
         }
 
@@ -334,18 +474,72 @@ impl Analyser {
             }
             _ => {}
         }
+
+        self.raw_output(s);
+    }
+
+    pub fn report_stops(&mut self, report: bool) {
+        self.reporting_location = report;
+        self.unreported_location = None;
+    }
+
+    pub fn report_last_stop(&mut self) {
+        self.reporting_location = true;
+        if let Some((file, line, source_line)) = self.unreported_location.take() {
+            notify_position(&file, line, source_line.as_ref().map(|s| &s[..]));
+        }
     }
 
+    /// Get the ids of all the breakpoints pdb has told us are set at the location
+    pub fn get_breakpoints_set_at(&self, file_location: &FileLocation) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .breakpoints
+            .iter()
+            .filter(|(_, fl)| fl.matches(file_location))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Whether the program is stopped, it isn't when it's been told to carry on and
+    /// hasn't stopped again yet
+    pub fn is_stopped(&self) -> bool {
+        self.location.is_some() && !self.listeners.contains_key(&Listener::Stopped)
+    }
+
+    /// Register for an event, listeners are dropped straight away if the debugger has
+    /// terminated so that they're notified of that immediately.
     pub fn add_listener(&mut self, kind: Listener, sender: Sender<Event>) {
+        if self.terminated {
+            return;
+        }
+        if kind == Listener::Raw {
+            self.raw_output = Some("".to_string());
+        }
         self.listeners.insert(kind, sender);
     }
 
+    /// The debugger has gone away, drop all listeners so anything waiting on it finds out
+    pub fn debugger_terminated(&mut self) {
+        if self.terminated {
+            return;
+        }
+        self.terminated = true;
+        self.set_status(PDBStatus::None);
+        self.raw_output = None;
+        self.listeners.clear();
+        log_msg(LogLevel::CRITICAL, "Python debugger process ended");
+    }
+
     pub fn set_pid(&mut self, pid: u64) {
         self.pid = Some(pid);
     }
 
+    /// pdb stops at the first line of the program when it's launched
     fn python_launched(&mut self) {
-        self.status = PDBStatus::Running;
+        self.set_status(PDBStatus::Running);
+        self.location = self.location.take().map(|l| l.with_reason("entry"));
         match self.listeners.remove(&Listener::Launch) {
             Some(listener) => {
                 listener.send(Event::Launched).wait().unwrap();
@@ -354,9 +548,34 @@ impl Analyser {
         }
     }
 
-    fn found_breakpoint(&mut self, file: String, line: u64) {
-        breakpoint_set(&file, line);
+    fn jump_to_position(&mut self, file: &str, line: u64, source_line: Option<&str>) {
+        if self.reporting_location {
+            notify_position(file, line, source_line);
+        } else {
+            self.unreported_location =
+                Some((file.to_string(), line, source_line.map(|s| s.to_string())));
+        }
+        self.location = Some(StopLocation::new(file.to_string(), line, None));
+        // The step may have given up waiting if the program ran for a while
+        if let Some(listener) = self.listeners.remove(&Listener::Stopped) {
+            let file_location = FileLocation::new(file.to_string(), line);
+            let _ = listener.send(Event::Stopped(file_location)).wait();
+        }
+    }
+
+    fn process_exited(&mut self, exit_code: i64) {
+        self.location = None;
+        self.exit_code = Some(exit_code);
+        signal_exited(self.pid.unwrap(), exit_code);
+        if let Some(listener) = self.listeners.remove(&Listener::Stopped) {
+            let _ = listener.send(Event::ProcessExited(exit_code)).wait();
+        }
+    }
+
+    fn found_breakpoint(&mut self, id: u64, file: String, line: u64) {
+        breakpoint_set(&file, line, None);
         let file_location = FileLocation::new(file, line);
+        self.breakpoints.insert(id, file_location.clone());
         match self.listeners.remove(&Listener::Breakpoint) {
             Some(listener) => {
                 listener
@@ -368,27 +587,217 @@ impl Analyser {
         }
     }
 
+    /// Collect what's printed for a variable until pdb shows the prompt again, a value
+    /// can be spread over several lines and arrive in several pieces, e.g. a long dict
     fn print_variable(&mut self, variable: Variable, data: &str) {
-        let len = data.len();
-        if len < 2 {
+        let output = self.print_output.get_or_insert_with(String::new);
+        output.push_str(data);
+        if !output.ends_with(&self.prompt) {
             return;
         }
 
-        let to = data.len() - 2;
+        let output = self.print_output.take().unwrap();
+        let value = output[..output.len() - self.prompt.len()]
+            .trim_end_matches(['\n', '\r'])
+            .to_string();
+
+        self.set_status(PDBStatus::Running);
+
         match self.listeners.remove(&Listener::PrintVariable) {
+            // The print may have given up waiting
             Some(listener) => {
-                listener
-                    .send(Event::PrintVariable(variable, data[0..to].to_string()))
-                    .wait()
-                    .unwrap();
+                let _ = listener.send(Event::PrintVariable(variable, value)).wait();
             }
             None => {}
         }
     }
+
+    /// Collect the output of a raw command until pdb shows the prompt again
+    fn raw_output(&mut self, s: &str) {
+        let finished = match self.raw_output {
+            Some(ref mut output) => {
+                output.push_str(s);
+                output.ends_with(&self.prompt)
+            }
+            None => false,
+        };
+
+        if !finished {
+            return;
+        }
+
+        let output = self.raw_output.take().unwrap();
+        let output = output[..output.len() - self.prompt.len()]
+            .trim_end()
+            .to_string();
+
+        if let Some(listener) = self.listeners.remove(&Listener::Raw) {
+            listener.send(Event::RawOutput(output)).wait().unwrap();
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::debugger::{FileLocation, StopLocation, Variable};
+    use tokio::prelude::*;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn check_run_with_bogus_debugger() {
+        let mut process = super::Process::new(
+            "bogus_python_that_doesnt_exist".to_string(),
+            vec!["test.py".to_string()],
+            vec![],
+        );
+        let err = process.run().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn check_breakpoints_set_are_recorded() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Breakpoint 1 at /home/me/test.py:12\n");
+
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("/home/me/test.py".to_string(), 12)),
+            vec![1]
+        );
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("/home/me/test.py".to_string(), 13)),
+            Vec::<u64>::new()
+        );
+    }
+
+    #[test]
+    fn check_paths_with_spaces() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout("Breakpoint 1 at /tmp/my project/main.py:12\n");
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new(
+                "/tmp/my project/main.py".to_string(),
+                12
+            )),
+            vec![1]
+        );
+
+        // The first stop is where pdb stops when it launches the program
+        analyser.analyse_stdout("> /tmp/my project/main.py(12)<module>()\n-> a()\n(Pdb) ");
+        assert_eq!(
+            analyser.location,
+            Some(
+                StopLocation::new("/tmp/my project/main.py".to_string(), 12, None)
+                    .with_reason("entry")
+            )
+        );
+    }
+
+    #[test]
+    fn check_mixed_line_endings() {
+        let mut analyser = super::Analyser::new();
+        analyser.analyse_stdout(
+            "Breakpoint 1 at /home/me/test.py:12\r\nBreakpoint 2 at /home/me/test.py:13\n",
+        );
+
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("/home/me/test.py".to_string(), 12)),
+            vec![1]
+        );
+        assert_eq!(
+            analyser.get_breakpoints_set_at(&FileLocation::new("/home/me/test.py".to_string(), 13)),
+            vec![2]
+        );
+
+        analyser.analyse_stdout(
+            "--Return--\r\n> /home/me/test.py(4)a()->'abc'\r\n-> return 'abc'\r\n(Pdb) ",
+        );
+        assert_eq!(analyser.return_value, Some("'abc'".to_string()));
+    }
+
+    #[test]
+    fn check_unreported_stop_kept_until_next_step() {
+        let mut analyser = super::Analyser::new();
+
+        analyser.report_stops(false);
+        analyser.analyse_stdout("> /home/me/test.py(9)<module>()\n-> a()\n(Pdb) ");
+        assert_eq!(
+            analyser.unreported_location,
+            Some(("/home/me/test.py".to_string(), 9, Some("a()".to_string())))
+        );
+
+        analyser.report_stops(true);
+        assert_eq!(analyser.unreported_location, None);
+        analyser.analyse_stdout("> /home/me/test.py(10)<module>()\n-> b()\n(Pdb) ");
+        assert_eq!(analyser.unreported_location, None);
+    }
+
+    #[test]
+    fn check_return_value_recorded() {
+        let mut analyser = super::Analyser::new();
+        analyser
+            .analyse_stdout("--Return--\n> /home/me/test.py(4)a()->'abc'\n-> return 'abc'\n(Pdb) ");
+        assert_eq!(analyser.return_value, Some("'abc'".to_string()));
+
+        analyser.analyse_stdout("> /home/me/test.py(9)<module>()\n-> a()\n(Pdb) ");
+        assert_eq!(analyser.return_value, None);
+    }
+
+    #[test]
+    fn check_printed_value_without_prompt() {
+        let mut analyser = super::Analyser::new();
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.status = super::PDBStatus::Printing(Variable::new("a".to_string()));
+
+        analyser.analyse_stdout("1.25\n(Pdb) ");
+
+        assert_eq!(
+            rx.wait().next().unwrap().unwrap(),
+            super::Event::PrintVariable(Variable::new("a".to_string()), "1.25".to_string())
+        );
+    }
+
+    #[test]
+    fn check_printed_value_over_several_lines() {
+        let mut analyser = super::Analyser::new();
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.status = super::PDBStatus::Printing(Variable::new("d".to_string()));
+
+        analyser.analyse_stdout("{'first': [1, 2, 3],\n 'second': {'nested': 'a\\nb'},\n");
+        analyser.analyse_stdout(" 'third': None}\n(Pdb) ");
+
+        assert_eq!(
+            rx.wait().next().unwrap().unwrap(),
+            super::Event::PrintVariable(
+                Variable::new("d".to_string()),
+                "{'first': [1, 2, 3],\n 'second': {'nested': 'a\\nb'},\n 'third': None}"
+                    .to_string()
+            )
+        );
+        assert_eq!(analyser.get_status(), super::PDBStatus::Running);
+    }
+
+    #[test]
+    fn check_custom_prompt() {
+        let mut analyser = super::Analyser::new();
+        analyser.prompt = "[debug] ".to_string();
+
+        analyser.analyse_stdout("> /home/me/test.py(1)<module>()\n-> a()\n[debug] ");
+        assert_eq!(analyser.get_status(), super::PDBStatus::Running);
+
+        let (tx, rx) = mpsc::channel(1);
+        analyser.add_listener(super::Listener::PrintVariable, tx);
+        analyser.status = super::PDBStatus::Printing(Variable::new("a".to_string()));
+
+        analyser.analyse_stdout("1.25\n[debug] ");
+
+        assert_eq!(
+            rx.wait().next().unwrap().unwrap(),
+            super::Event::PrintVariable(Variable::new("a".to_string()), "1.25".to_string())
+        );
+    }
+
     #[test]
     fn check_get_args_basic_command() {
         let args = super::get_python_args("/usr/bin/python3", vec!["test.py", "arg1"]);
@@ -424,4 +833,17 @@ mod tests {
         );
         assert_eq!(args, vec!["-m", "pdb", "-c", "print('Hello, World!')"]);
     }
+
+    #[test]
+    fn check_listeners_dropped_when_debugger_terminates() {
+        let mut analyser = super::Analyser::new();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        analyser.add_listener(super::Listener::Raw, tx);
+        analyser.debugger_terminated();
+
+        let (event, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(event, None);
+        assert_eq!(analyser.get_status(), super::PDBStatus::None);
+    }
 }
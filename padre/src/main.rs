@@ -5,6 +5,35 @@
 //! Options supported:
 //!   -p/--port   Port to run socket interface on
 //!   -h/--host   Hostname to run on
+//!   -s/--socket Unix domain socket path to run socket interface on instead of TCP
+//!   --print-port-file
+//!               File to write the address PADRE is listening on to once it has bound
+//!               the TCP port, useful when the port is chosen for us
+//!   --log-file  File to append a log of all notifications and errors to
+//!   --record    File to append every request received to as lines of JSON, useful for
+//!               reporting bugs
+//!   --replay    File of requests recorded with `--record` to send to the debugger instead
+//!               of listening for connections, responses and notifications are printed.
+//!               Once they've all been answered and the program has exited PADRE exits
+//!               with the program's exit code
+//!   --auth-token
+//!               Token every connection must send in an `auth` request before anything
+//!               else, by default connections don't need to authenticate
+//!   --args-file File of arguments to pass to the program, one per line, after any given
+//!               on the command line
+//!   --init-commands
+//!               File of commands to send to the debugger when it starts, one per line,
+//!               e.g. LLDB type summaries or aliases. Not supported for Node
+//!   --debugger-env
+//!               Environment variable as `K=V` to set for the debugger process itself, e.g.
+//!               `LLDB_DEBUGSERVER_PATH` or `PYTHONPATH`, can be given more than once. For
+//!               Python and Node the program runs in the same process so sees these too,
+//!               for LLDB it depends on LLDB's `target.inherit-env` setting
+//!   --cwd       Directory to run the program in, by default the directory PADRE is run in
+//!   --prompt    The prompt the debugger shows when it's ready for a command, for when it's
+//!               been customised, by default `(lldb) ` for LLDB and `(Pdb) ` for Python
+//!   --no-update-check
+//!               Don't check with git whether PADRE is up to date when it starts
 //!   -t/--type   The type of debugger to spawn
 //!          Currently supported are
 //!            - lldb
@@ -23,14 +52,16 @@ extern crate lazy_static;
 #[macro_use]
 extern crate serde_derive;
 
+use std::fs;
 use std::io;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::process::exit;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use clap::{App, Arg, ArgMatches};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, UnixListener};
 use tokio::prelude::*;
 use tokio::runtime::current_thread::Runtime;
 use tokio::timer::Delay;
@@ -59,6 +90,60 @@ fn get_app_args<'a>() -> ArgMatches<'a> {
                  .long("host")
                  .takes_value(true)
                  .help("specify host to run on"))
+        .arg(Arg::with_name("socket")
+                 .short("s")
+                 .long("socket")
+                 .takes_value(true)
+                 .conflicts_with_all(&["port", "host"])
+                 .help("specify a unix domain socket path to run on instead of TCP"))
+        .arg(Arg::with_name("print_port_file")
+                 .long("print-port-file")
+                 .takes_value(true)
+                 .conflicts_with("socket")
+                 .help("specify a file to write the address listened on to once bound"))
+        .arg(Arg::with_name("log_file")
+                 .long("log-file")
+                 .takes_value(true)
+                 .help("specify a file to append a log of PADRE's activity to"))
+        .arg(Arg::with_name("record")
+                 .long("record")
+                 .takes_value(true)
+                 .help("specify a file to append every request received to"))
+        .arg(Arg::with_name("replay")
+                 .long("replay")
+                 .takes_value(true)
+                 .conflicts_with_all(&["port", "host", "socket", "print_port_file"])
+                 .help("specify a file of recorded requests to replay instead of listening"))
+        .arg(Arg::with_name("auth_token")
+                 .long("auth-token")
+                 .takes_value(true)
+                 .conflicts_with("replay")
+                 .help("specify a token connections must authenticate with first"))
+        .arg(Arg::with_name("args_file")
+                 .long("args-file")
+                 .takes_value(true)
+                 .help("specify a file of arguments for the program, one per line"))
+        .arg(Arg::with_name("init_commands")
+                 .long("init-commands")
+                 .takes_value(true)
+                 .help("specify a file of commands to send to the debugger when it starts"))
+        .arg(Arg::with_name("debugger_env")
+                 .long("debugger-env")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .help("specify K=V to set in the environment of the debugger itself rather than the program, can be repeated"))
+        .arg(Arg::with_name("cwd")
+                 .long("cwd")
+                 .takes_value(true)
+                 .help("specify the directory to run the program in"))
+        .arg(Arg::with_name("prompt")
+                 .long("prompt")
+                 .takes_value(true)
+                 .help("specify the prompt the debugger shows if it's been customised"))
+        .arg(Arg::with_name("no_update_check")
+                 .long("no-update-check")
+                 .help("don't check with git whether PADRE is up to date"))
         .arg(Arg::with_name("debugger")
                  .short("d")
                  .long("debugger")
@@ -108,7 +193,79 @@ fn exit_padre(debugger: Arc<Mutex<debugger::Debugger>>) {
             })
     });
 
-    debugger.lock().unwrap().stop();
+    debugger.lock().unwrap().stop(0);
+}
+
+/// Listen for connections over TCP and process them
+///
+/// If a `port_file` is specified the address actually bound to is written to it.
+fn listen_tcp(
+    connection_addr: SocketAddr,
+    port_file: Option<&str>,
+    debugger: Arc<Mutex<debugger::Debugger>>,
+    auth_token: Option<String>,
+) {
+    let listener = TcpListener::bind(&connection_addr)
+        .expect(&format!("Can't open TCP listener on {}", &connection_addr));
+
+    let bound_addr = listener.local_addr().unwrap();
+
+    println!("Listening on {}", &bound_addr);
+
+    if let Some(path) = port_file {
+        fs::write(path, format!("{}\n", &bound_addr))
+            .unwrap_or_else(|e| panic!("Can't write listening address to {}: {}", path, e));
+    }
+
+    tokio::spawn(
+        listener
+            .incoming()
+            .map_err(|e| eprintln!("failed to accept socket; error = {:?}", e))
+            .for_each(move |socket| {
+                let addr = socket.peer_addr().unwrap().to_string();
+                server::process_connection(socket, addr, debugger.clone(), auth_token.clone());
+
+                Ok(())
+            }),
+    );
+}
+
+/// Listen for connections over a Unix domain socket and process them
+///
+/// Any existing file at the socket path is removed first as it's likely left over from a
+/// previous run.
+fn listen_unix_socket(
+    path: &str,
+    debugger: Arc<Mutex<debugger::Debugger>>,
+    auth_token: Option<String>,
+) {
+    if Path::new(path).exists() {
+        fs::remove_file(path)
+            .unwrap_or_else(|e| panic!("Can't remove existing socket {}: {}", path, e));
+    }
+
+    let listener = UnixListener::bind(path)
+        .map(|listener| {
+            println!("Listening on {}", path);
+            listener
+        })
+        .unwrap_or_else(|e| panic!("Can't open Unix socket listener on {}: {}", path, e));
+
+    let path = path.to_string();
+    let mut connection_num: u64 = 0;
+
+    tokio::spawn(
+        listener
+            .incoming()
+            .map_err(|e| eprintln!("failed to accept socket; error = {:?}", e))
+            .for_each(move |socket| {
+                connection_num += 1;
+                let addr = format!("{}#{}", path, connection_num);
+                server::process_connection(socket, addr, debugger.clone(), auth_token.clone());
+
+                Ok(())
+            }),
+    );
 }
 
 struct Runner {}
@@ -120,26 +277,67 @@ impl Future for Runner {
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let args = get_app_args();
 
-        let debug_cmd: Vec<String> = args
+        if let Some(path) = args.value_of("log_file") {
+            util::open_log_file(path)
+                .unwrap_or_else(|e| panic!("Can't open log file {}: {}", path, e));
+        }
+
+        if let Some(path) = args.value_of("record") {
+            util::open_record_file(path)
+                .unwrap_or_else(|e| panic!("Can't open record file {}: {}", path, e));
+        }
+
+        let mut debug_cmd: Vec<String> = args
             .values_of("debug_cmd")
-            .expect("Can't find program to debug, please rerun with correct parameters")
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
+            .map(|x| x.map(|x| x.to_string()).collect())
+            .unwrap_or_default();
+
+        if let Some(path) = args.value_of("args_file") {
+            match util::read_args_file(path) {
+                Ok(file_args) => debug_cmd.extend(file_args),
+                Err(e) => {
+                    eprintln!("Can't read args file {}: {}", path, e);
+                    exit(1);
+                }
+            }
+        }
+
+        let init_commands = match args.value_of("init_commands") {
+            Some(path) => match util::read_init_commands_file(path) {
+                Ok(cmds) => cmds,
+                Err(e) => {
+                    eprintln!("Can't read init commands file {}: {}", path, e);
+                    exit(1);
+                }
+            },
+            None => vec![],
+        };
+
+        let mut debugger_env = vec![];
+
+        for var in args.values_of("debugger_env").unwrap_or_default() {
+            match util::parse_env_var(var) {
+                Ok(kv) => debugger_env.push(kv),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+
+        if debug_cmd.is_empty() {
+            panic!("Can't find program to debug, please rerun with correct parameters");
+        }
 
         let debugger = Arc::new(Mutex::new(debugger::get_debugger(
             args.value_of("debugger"),
             args.value_of("type"),
             debug_cmd,
+            args.value_of("prompt"),
+            init_commands,
+            debugger_env,
         )));
 
-        let connection_addr = get_connection(&args);
-        let listener = TcpListener::bind(&connection_addr)
-            .map(|listener| {
-                println!("Listening on {}", &connection_addr);
-                listener
-            })
-            .expect(&format!("Can't open TCP listener on {}", &connection_addr));
-
         let debugger_signal = debugger.clone();
         let signals = Signal::new(SIGINT)
             .flatten_stream()
@@ -179,16 +377,47 @@ impl Future for Runner {
 
         tokio::spawn(signals);
 
-        tokio::spawn(
-            listener
-                .incoming()
-                .map_err(|e| eprintln!("failed to accept socket; error = {:?}", e))
-                .for_each(move |socket| {
-                    server::process_connection(socket, debugger.clone());
-
-                    Ok(())
-                }),
-        );
+        if let Some(dir) = args.value_of("cwd") {
+            if !Path::new(dir).is_dir() {
+                panic!("Can't find directory {}", dir);
+            }
+
+            let cmd = debugger::DebuggerCmdV1::SetCwd(dir.to_string());
+            let f = debugger::run_in_order(debugger.clone(), move |d| {
+                d.handle_v1_cmd(&cmd, Arc::new(Mutex::new(config::Config::new())))
+            })
+            .map(|_| {})
+            .map_err(|e| {
+                notifier::log_msg(
+                    notifier::LogLevel::ERROR,
+                    &format!("Can't set the working directory: {}", e),
+                );
+            });
+
+            tokio::spawn(f);
+        }
+
+        if let Some(path) = args.value_of("replay") {
+            server::replay(path, debugger)
+                .unwrap_or_else(|e| panic!("Can't replay {}: {}", path, e));
+            return Ok(Async::Ready(()));
+        }
+
+        if !args.is_present("no_update_check") {
+            server::check_for_and_report_padre_updates();
+        }
+
+        let auth_token = args.value_of("auth_token").map(|t| t.to_string());
+
+        match args.value_of("socket") {
+            Some(path) => listen_unix_socket(path, debugger, auth_token),
+            None => listen_tcp(
+                get_connection(&args),
+                args.value_of("print_port_file"),
+                debugger,
+                auth_token,
+            ),
+        }
 
         Ok(Async::Ready(()))
     }
@@ -3,13 +3,18 @@
 //! This module contains tools for notifying every socket connection about an
 //! event.
 
-use std::net::SocketAddr;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::config::Config;
+use crate::debugger::ProgramStatus;
 use crate::server::{Notification, PadreSend};
+use crate::util;
 
 use tokio::prelude::*;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::timer::Delay;
 
 lazy_static! {
     static ref NOTIFIER: Mutex<Notifier> = { Mutex::new(Notifier::new()) };
@@ -28,11 +33,91 @@ pub enum LogLevel {
     DEBUG,
 }
 
+/// How many notifications can be waiting to be sent to a connection before any more are
+/// dropped
+const NOTIFICATION_BUFFER: usize = 1000;
+
+/// The queue's receiver and the connection, until the task sending between them is started
+type UnstartedQueue = Arc<Mutex<Option<(Receiver<Notification>, Sender<PadreSend>)>>>;
+
+/// The notifications waiting to be sent to a connection
+///
+/// A single task per connection sends them on in order, waiting for the connection to
+/// accept each one. When a connection isn't keeping up and the queue is full
+/// notifications are dropped, the connection is told how many were dropped once there's
+/// room again.
+///
+/// The task is started when the first notification is queued.
+#[derive(Clone, Debug)]
+struct NotificationQueue {
+    tx: Sender<Notification>,
+    unstarted: UnstartedQueue,
+    dropped: Arc<AtomicU64>,
+}
+
+impl NotificationQueue {
+    fn new(sender: Sender<PadreSend>) -> NotificationQueue {
+        let (tx, rx) = mpsc::channel(NOTIFICATION_BUFFER);
+
+        NotificationQueue {
+            tx,
+            unstarted: Arc::new(Mutex::new(Some((rx, sender)))),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Start sending what's queued to the connection if that's not already happening
+    fn start(&self) {
+        if let Some((rx, sender)) = self.unstarted.lock().unwrap().take() {
+            tokio::spawn(
+                rx.map(PadreSend::Notification)
+                    .map_err(|e| eprintln!("Notifier can't read queue: {}", e))
+                    .forward(
+                        sender.sink_map_err(|e| eprintln!("Notifier can't send to socket: {}", e)),
+                    )
+                    .map(|_| ()),
+            );
+        }
+    }
+
+    /// Queue a notification, dropping it if the queue's full
+    fn push(&mut self, msg: Notification) {
+        self.start();
+
+        let dropped = self.dropped.load(Ordering::SeqCst);
+        if dropped > 0 {
+            let warning = log_msg_notification(
+                LogLevel::WARN,
+                &format!("Dropped {} notifications, connection too slow", dropped),
+            );
+            if self.tx.try_send(warning).is_err() {
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            self.dropped.fetch_sub(dropped, Ordering::SeqCst);
+        }
+
+        if let Err(e) = self.tx.try_send(msg) {
+            if e.is_full() && self.dropped.fetch_add(1, Ordering::SeqCst) == 0 {
+                util::write_log("Notification queue full, dropping notifications");
+            }
+        }
+    }
+}
+
 /// A `Listener` is a wrapper around the ...
+///
+/// The `addr` identifies the connection, for TCP connections this is the peer address and
+/// for Unix domain socket connections it is the socket path and a connection number. The
+/// `config` is the connection's config so notifications can be tailored per connection.
+/// The `pending_jump` is the latest position that's being held back when jumps are
+/// coalesced.
 #[derive(Debug)]
 struct Listener {
-    sender: Sender<PadreSend>,
-    addr: SocketAddr,
+    queue: NotificationQueue,
+    addr: String,
+    config: Arc<Mutex<Config<'static>>>,
+    pending_jump: Arc<Mutex<Option<Notification>>>,
 }
 
 /// The `Notifier` creates the main singleton object for PADRE to communicate
@@ -43,6 +128,10 @@ struct Listener {
 #[derive(Debug)]
 struct Notifier {
     listeners: Vec<Listener>,
+    // Whether the debugger is ready, so connections made afterwards can be told
+    debugger_ready: bool,
+    // The version of the debugger once it's known, also told to later connections
+    debugger_version: Option<String>,
 }
 
 impl Notifier {
@@ -50,32 +139,109 @@ impl Notifier {
     fn new() -> Notifier {
         Notifier {
             listeners: Vec::new(),
+            debugger_ready: false,
+            debugger_version: None,
         }
     }
 
     /// Add a listener to the notifier
     ///
     /// Should be called when a new connection is added.
-    fn add_listener(&mut self, sender: Sender<PadreSend>, addr: SocketAddr) {
-        self.listeners.push(Listener { sender, addr });
+    fn add_listener(
+        &mut self,
+        sender: Sender<PadreSend>,
+        addr: String,
+        config: Arc<Mutex<Config<'static>>>,
+    ) {
+        let mut queue = NotificationQueue::new(sender);
+
+        if self.debugger_ready {
+            queue.push(debugger_ready_msg());
+        }
+
+        if let Some(version) = &self.debugger_version {
+            queue.push(debugger_info_msg(version));
+        }
+
+        self.listeners.push(Listener {
+            queue,
+            addr,
+            config,
+            pending_jump: Arc::new(Mutex::new(None)),
+        });
     }
 
     /// Remove a listener from the notifier
     ///
     /// Should be called when a connection is dropped.
-    fn remove_listener(&mut self, addr: &SocketAddr) {
+    fn remove_listener(&mut self, addr: &str) {
         self.listeners.retain(|listener| listener.addr != *addr);
     }
 
     /// Send the message to all clients
     fn send_msg(&mut self, msg: Notification) {
+        self.send_msg_per_config(|_| msg.clone());
+    }
+
+    /// Send a message to all clients where the message sent depends on each client's config
+    fn send_msg_per_config<F>(&mut self, f: F)
+    where
+        F: Fn(&Config) -> Notification,
+    {
+        let msg = f(&Config::new());
+        util::write_log(&format!("{} {}", msg.cmd(), serde_json::json!(msg.args())));
+
+        for listener in self.listeners.iter_mut() {
+            let msg = f(&listener.config.lock().unwrap());
+            listener.queue.push(msg);
+        }
+    }
+
+    /// Send a message only to the clients with the config item `key` set to 1
+    fn send_msg_if_config(&mut self, key: &str, msg: Notification) {
         for listener in self.listeners.iter_mut() {
-            let sender = listener.sender.clone();
+            if listener.config.lock().unwrap().get_config(key) != Some(1) {
+                continue;
+            }
+            listener.queue.push(msg.clone());
+        }
+    }
+
+    /// Send a jump to a position to all clients
+    ///
+    /// For clients with `JumpCoalesceMs` set the jump is held back that long and only the
+    /// latest jump in that time is sent.
+    fn send_jump_per_config<F>(&mut self, f: F)
+    where
+        F: Fn(&Config) -> Notification,
+    {
+        let msg = f(&Config::new());
+        util::write_log(&format!("{} {}", msg.cmd(), serde_json::json!(msg.args())));
+
+        for listener in self.listeners.iter_mut() {
+            let (msg, window) = {
+                let config = listener.config.lock().unwrap();
+                (f(&config), config.get_config("JumpCoalesceMs").unwrap_or(0))
+            };
+            if window == 0 {
+                listener.queue.push(msg);
+                continue;
+            }
+
+            // Already waiting to send a jump, this one's sent instead
+            if listener.pending_jump.lock().unwrap().replace(msg).is_some() {
+                continue;
+            }
+
+            let pending_jump = listener.pending_jump.clone();
+            let mut queue = listener.queue.clone();
             tokio::spawn(
-                sender
-                    .send(PadreSend::Notification(msg.clone()))
-                    .map(|_| ())
-                    .map_err(|e| eprintln!("Notifier can't send to socket: {}", e)),
+                Delay::new(Instant::now() + Duration::from_millis(window as u64))
+                    .map_err(|e| eprintln!("Jump timer error: {}", e))
+                    .map(move |_| {
+                        let msg = pending_jump.lock().unwrap().take().unwrap();
+                        queue.push(msg);
+                    }),
             );
         }
     }
@@ -84,17 +250,73 @@ impl Notifier {
 /// Add a listener to the notifier
 ///
 /// Should be called when a new connection is added.
-pub fn add_listener(sender: Sender<PadreSend>, addr: SocketAddr) {
-    NOTIFIER.lock().unwrap().add_listener(sender, addr);
+pub fn add_listener(sender: Sender<PadreSend>, addr: String, config: Arc<Mutex<Config<'static>>>) {
+    NOTIFIER.lock().unwrap().add_listener(sender, addr, config);
 }
 
 /// Remove a listener from the notifier
 ///
 /// Should be called when a connection is dropped.
-pub fn remove_listener(addr: &SocketAddr) {
+pub fn remove_listener(addr: &str) {
     NOTIFIER.lock().unwrap().remove_listener(addr);
 }
 
+fn debugger_ready_msg() -> Notification {
+    Notification::new("padre#debugger#DebuggerReady".to_string(), vec![])
+}
+
+/// Sent to a connection that hasn't made a request for a while to check it's still there
+pub fn ping_msg() -> Notification {
+    Notification::new("padre#debugger#Ping".to_string(), vec![])
+}
+
+/// Sent every `HeartbeatInterval` seconds with what the program is doing
+pub fn heartbeat_msg(status: ProgramStatus) -> Notification {
+    Notification::new(
+        "padre#debugger#Heartbeat".to_string(),
+        vec![serde_json::json!(status)],
+    )
+}
+
+/// Notify that the debugger has started up and is ready for commands
+///
+/// Connections made after this are notified as soon as they connect.
+pub fn debugger_ready() {
+    let mut notifier = NOTIFIER.lock().unwrap();
+    notifier.debugger_ready = true;
+    notifier.send_msg(debugger_ready_msg());
+}
+
+/// Whether the debugger has started up and is ready for commands
+pub fn is_debugger_ready() -> bool {
+    NOTIFIER.lock().unwrap().debugger_ready
+}
+
+fn debugger_info_msg(version: &str) -> Notification {
+    Notification::new(
+        "padre#debugger#DebuggerInfo".to_string(),
+        vec![serde_json::json!(version)],
+    )
+}
+
+/// Notify the version of the debugger, for bug reports
+///
+/// Connections made after this are notified as soon as they connect.
+pub fn debugger_info(version: &str) {
+    let mut notifier = NOTIFIER.lock().unwrap();
+    notifier.debugger_version = Some(version.to_string());
+    notifier.send_msg(debugger_info_msg(version));
+}
+
+/// Notify the message of a logpoint that's been hit
+pub fn logpoint_hit(message: &str) {
+    let msg = Notification::new(
+        "padre#debugger#Logpoint".to_string(),
+        vec![serde_json::json!(message)],
+    );
+    NOTIFIER.lock().unwrap().send_msg(msg);
+}
+
 /// Notify that a process has exited
 pub fn signal_exited(pid: u64, exit_code: i64) {
     let msg = Notification::new(
@@ -104,29 +326,145 @@ pub fn signal_exited(pid: u64, exit_code: i64) {
     NOTIFIER.lock().unwrap().send_msg(msg);
 }
 
+fn log_msg_notification(level: LogLevel, msg: &str) -> Notification {
+    Notification::new(
+        "padre#debugger#Log".to_string(),
+        vec![serde_json::json!(level as u8), serde_json::json!(msg)],
+    )
+}
+
 /// Send a log message
 pub fn log_msg(level: LogLevel, msg: &str) {
+    let msg = log_msg_notification(level, msg);
+    NOTIFIER.lock().unwrap().send_msg(msg);
+}
+
+/// Notify about a code position change, the column is only sent when the debugger
+/// reports it
+pub fn jump_to_position(file: &str, line: u64, column: Option<u64>) {
+    let mut args = vec![serde_json::json!(file), serde_json::json!(line)];
+    if let Some(c) = column {
+        args.push(serde_json::json!(c));
+    }
+    let msg = Notification::new("padre#debugger#JumpToPosition".to_string(), args);
+    NOTIFIER
+        .lock()
+        .unwrap()
+        .send_jump_per_config(|_| msg.clone());
+}
+
+/// Notify about a code position change including the source code at that line
+///
+/// The source line is only sent to connections with `IncludeSourceLine` set, after a
+/// null column as the debuggers that report the source line don't report the column.
+pub fn jump_to_position_with_source(file: &str, line: u64, source_line: &str) {
+    NOTIFIER.lock().unwrap().send_jump_per_config(|config| {
+        let mut args = vec![serde_json::json!(file), serde_json::json!(line)];
+        if config.get_config("IncludeSourceLine") == Some(1) {
+            args.push(serde_json::Value::Null);
+            args.push(serde_json::json!(source_line));
+        }
+        Notification::new("padre#debugger#JumpToPosition".to_string(), args)
+    });
+}
+
+/// Notify that a breakpoint has been set, the column is only sent when the debugger
+/// reports it
+pub fn breakpoint_set(file: &str, line: u64, column: Option<u64>) {
+    let mut args = vec![serde_json::json!(file), serde_json::json!(line)];
+    if let Some(c) = column {
+        args.push(serde_json::json!(c));
+    }
+    let msg = Notification::new("padre#debugger#BreakpointSet".to_string(), args);
+    NOTIFIER.lock().unwrap().send_msg(msg);
+}
+
+/// Notify that a breakpoint couldn't be set yet, e.g. because the program hasn't been run,
+/// and will be when it can be. The file and line are as requested.
+pub fn breakpoint_pending(file: &str, line: u64) {
     let msg = Notification::new(
-        "padre#debugger#Log".to_string(),
-        vec![serde_json::json!(level as u8), serde_json::json!(msg)],
+        "padre#debugger#BreakpointPending".to_string(),
+        vec![serde_json::json!(file), serde_json::json!(line)],
     );
     NOTIFIER.lock().unwrap().send_msg(msg);
 }
 
-/// Notify about a code position change
-pub fn jump_to_position(file: &str, line: u64) {
+/// Notify that a pending breakpoint has now been set, `resolved_line` is the line the
+/// debugger actually set it on which may differ from the line requested.
+pub fn breakpoint_verified(file: &str, line: u64, resolved_line: u64) {
     let msg = Notification::new(
-        "padre#debugger#JumpToPosition".to_string(),
-        vec![serde_json::json!(file), serde_json::json!(line)],
+        "padre#debugger#BreakpointVerified".to_string(),
+        vec![
+            serde_json::json!(file),
+            serde_json::json!(line),
+            serde_json::json!(resolved_line),
+        ],
     );
     NOTIFIER.lock().unwrap().send_msg(msg);
 }
 
-/// Notify that a breakpoint has been set
-pub fn breakpoint_set(file: &str, line: u64) {
+/// Notify about output from the program being debugged
+///
+/// `stream` is either `stdout` or `stderr` and is only known for debuggers that keep
+/// the program's output streams separate.
+pub fn program_output(stream: &str, output: &str) {
     let msg = Notification::new(
-        "padre#debugger#BreakpointSet".to_string(),
-        vec![serde_json::json!(file), serde_json::json!(line)],
+        "padre#debugger#ProgramOutput".to_string(),
+        vec![serde_json::json!(stream), serde_json::json!(output)],
+    );
+    NOTIFIER.lock().unwrap().send_msg(msg);
+}
+
+/// Notify how many of a counted step in or step over have been made so far
+pub fn step_progress(done: u64, total: u64) {
+    let msg = Notification::new(
+        "padre#debugger#StepProgress".to_string(),
+        vec![serde_json::json!(done), serde_json::json!(total)],
+    );
+    NOTIFIER.lock().unwrap().send_msg(msg);
+}
+
+/// Notify that a watchpoint has been hit with the value before, if LLDB reports it, and
+/// the value after
+pub fn watchpoint_hit(id: u64, old_value: Option<&str>, new_value: &str) {
+    let msg = Notification::new(
+        "padre#debugger#WatchpointHit".to_string(),
+        vec![
+            serde_json::json!(id),
+            serde_json::json!(old_value),
+            serde_json::json!(new_value),
+        ],
+    );
+    NOTIFIER.lock().unwrap().send_msg(msg);
+}
+
+/// Trace what PADRE is doing for clients with `DebugProtocol` set
+///
+/// `kind` is `status` for PADRE or an analyser changing what it's doing and `command`
+/// for something sent to the debugger.
+pub fn protocol_trace(kind: &str, detail: &str) {
+    let msg = Notification::new(
+        "padre#debugger#ProtocolTrace".to_string(),
+        vec![serde_json::json!(kind), serde_json::json!(detail)],
+    );
+    NOTIFIER
+        .lock()
+        .unwrap()
+        .send_msg_if_config("DebugProtocol", msg);
+}
+
+/// Notify with the latest values of the watch expressions
+///
+/// Sent as a list of `{"expression":...,"value":...}` objects in the order the watches
+/// were added.
+pub fn watches_updated(values: &[(String, serde_json::Value)]) {
+    let watches: Vec<serde_json::Value> = values
+        .iter()
+        .map(|(expression, value)| serde_json::json!({"expression": expression, "value": value}))
+        .collect();
+    let msg = Notification::new(
+        "padre#debugger#WatchesUpdated".to_string(),
+        vec![serde_json::json!(watches)],
     );
     NOTIFIER.lock().unwrap().send_msg(msg);
 }
@@ -134,24 +472,148 @@ pub fn breakpoint_set(file: &str, line: u64) {
 #[cfg(test)]
 mod tests {
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::{Arc, Mutex};
+    use tokio::prelude::*;
     use tokio::sync::mpsc;
 
+    use crate::config::Config;
+    use crate::server::{Notification, PadreSend};
+
     fn create_notifier_with_listeners() -> super::Notifier {
         let mut notifier = super::Notifier::new();
 
         let (sender, _) = mpsc::channel(1);
         let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
 
-        notifier.add_listener(sender, socket_addr);
+        notifier.add_listener(
+            sender,
+            socket_addr.to_string(),
+            Arc::new(Mutex::new(Config::new())),
+        );
 
         let (sender, _) = mpsc::channel(1);
         let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081);
 
-        notifier.add_listener(sender, socket_addr);
+        notifier.add_listener(
+            sender,
+            socket_addr.to_string(),
+            Arc::new(Mutex::new(Config::new())),
+        );
 
         notifier
     }
 
+    #[test]
+    fn check_new_listener_told_debugger_ready() {
+        let mut notifier = super::Notifier::new();
+        notifier.debugger_ready = true;
+
+        let (sender, rx) = mpsc::channel(1);
+
+        tokio::run(future::lazy(move || {
+            notifier.add_listener(
+                sender,
+                "test".to_string(),
+                Arc::new(Mutex::new(Config::new())),
+            );
+            Ok(())
+        }));
+
+        let (msg, _) = rx.into_future().wait().ok().unwrap();
+        assert_eq!(
+            msg,
+            Some(PadreSend::Notification(Notification::new(
+                "padre#debugger#DebuggerReady".to_string(),
+                vec![]
+            )))
+        );
+    }
+
+    #[test]
+    fn check_jumps_coalesced() {
+        let mut notifier = super::Notifier::new();
+
+        let mut config = Config::new();
+        config.set_config("JumpCoalesceMs", 50).unwrap();
+
+        let (sender, rx) = mpsc::channel(10);
+
+        tokio::run(future::lazy(move || {
+            notifier.add_listener(sender, "test".to_string(), Arc::new(Mutex::new(config)));
+            for line in 1..4 {
+                notifier.send_jump_per_config(|_| {
+                    Notification::new(
+                        "padre#debugger#JumpToPosition".to_string(),
+                        vec![serde_json::json!("test.py"), serde_json::json!(line)],
+                    )
+                });
+            }
+            Ok(())
+        }));
+
+        let msgs = rx.collect().wait().unwrap();
+        assert_eq!(
+            msgs,
+            vec![PadreSend::Notification(Notification::new(
+                "padre#debugger#JumpToPosition".to_string(),
+                vec![serde_json::json!("test.py"), serde_json::json!(3)]
+            ))]
+        );
+    }
+
+    #[test]
+    fn check_protocol_trace_only_sent_when_configured() {
+        let mut notifier = super::Notifier::new();
+
+        let mut config = Config::new();
+        config.set_config("DebugProtocol", 1).unwrap();
+
+        let (sender, rx) = mpsc::channel(10);
+        let (sender_2, rx_2) = mpsc::channel(10);
+
+        let msg = Notification::new(
+            "padre#debugger#ProtocolTrace".to_string(),
+            vec![serde_json::json!("command"), serde_json::json!("next\n")],
+        );
+        let msg_2 = msg.clone();
+
+        tokio::run(future::lazy(move || {
+            notifier.add_listener(sender, "test".to_string(), Arc::new(Mutex::new(config)));
+            notifier.add_listener(
+                sender_2,
+                "test2".to_string(),
+                Arc::new(Mutex::new(Config::new())),
+            );
+            notifier.send_msg_if_config("DebugProtocol", msg_2);
+            Ok(())
+        }));
+
+        assert_eq!(
+            rx.collect().wait().unwrap(),
+            vec![PadreSend::Notification(msg)]
+        );
+        assert_eq!(rx_2.collect().wait().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn check_notifications_dropped_when_queue_full() {
+        let (sender, receiver) = mpsc::channel(1);
+        let queue = super::NotificationQueue::new(sender);
+        let dropped = queue.dropped.clone();
+
+        tokio::run(future::lazy(move || {
+            let mut queue = queue;
+            for _ in 0..super::NOTIFICATION_BUFFER + 3 {
+                queue.push(Notification::new("padre#debugger#Test".to_string(), vec![]));
+            }
+            // Close the connection so the runtime can finish
+            drop(receiver);
+            Ok(())
+        }));
+
+        assert_eq!(dropped.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
     #[test]
     fn check_can_add_listeners() {
         let notifier = create_notifier_with_listeners();
@@ -164,10 +626,10 @@ mod tests {
         let mut notifier = create_notifier_with_listeners();
 
         let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081);
-        notifier.remove_listener(&socket_addr);
+        notifier.remove_listener(&socket_addr.to_string());
 
         let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
-        notifier.remove_listener(&socket_addr);
+        notifier.remove_listener(&socket_addr.to_string());
 
         assert_eq!(notifier.listeners.len(), 0);
     }
@@ -4,31 +4,75 @@
 //! padre and debuggers for actioning.
 
 use std::env::current_exe;
+use std::fs;
 use std::io;
+use std::path::Path;
 use std::process::{Command, Stdio};
-use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::config::Config;
-use crate::debugger::{Debugger, DebuggerCmd};
-use crate::notifier::{add_listener, log_msg, remove_listener, LogLevel};
-use crate::vimcodec::VimCodec;
+use crate::debugger::{
+    run_in_order, update_watches, Debugger, DebuggerCmd, DebuggerCmdV1, ProgramStatus,
+};
+use crate::notifier::{add_listener, heartbeat_msg, log_msg, ping_msg, remove_listener, LogLevel};
+use crate::util;
+use crate::vimcodec::{VimCodec, COMMANDS};
 
-use tokio::codec::Decoder;
-use tokio::net::TcpStream;
+use bytes::BytesMut;
+use tokio::codec::{Decoder, Encoder};
 use tokio::prelude::*;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::timer::{Delay, Interval};
+
+/// Requests on a connection started before the first of them has been answered, the
+/// debugger commands are still run in order but e.g. a cancel doesn't have to wait
+const MAX_REQUESTS_IN_PROGRESS: usize = 100;
+
+/// Longest in seconds that a debugger command is waited on, however many steps it takes
+const MAX_DEBUGGER_CMD_TIMEOUT: u64 = 24 * 60 * 60;
 
 // TODO: Get some of this out of pub use and just in this module?
 
 /// All padre commands
-#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub enum PadreCmd {
     Ping,
     Pings,
+    Echo(serde_json::Value),
     GetConfig(String),
     SetConfig(String, i64),
+    Capabilities,
+    GetPid,
+    Status,
+    Shutdown,
+    Auth(String),
+    Cancel,
+    // A request that couldn't be understood, answered with the reason
+    BadRequest(String),
+    // A command the codec doesn't know along with its arguments
+    Unknown(String, serde_json::Value),
+}
+
+/// Category of an error, sent as the `code` of an error response so that clients can tell
+/// errors apart without relying on log messages
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    DebuggerError,
+    RequestError,
+    Timeout,
+    Unsupported,
+    Cancelled,
+    // The command needs the program to be running and it isn't
+    NotRunning,
+}
+
+/// Create an error response with the error code given
+pub fn error_response(code: ErrorCode) -> serde_json::Value {
+    serde_json::json!({"status":"ERROR","code":code})
 }
 
 /// Contains command details of a request, either a `PadreCmd` or a `DebuggerCmd`
@@ -43,23 +87,41 @@ pub enum PadreCmd {
 /// let command = RequestCmd::CmdWithFileLocation("breakpoint", "test.c", 12)
 /// let command = RequestCmd::CmdWithVariable("print", "abc")
 /// ```
-#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub enum RequestCmd {
     PadreCmd(PadreCmd),
     DebuggerCmd(DebuggerCmd),
 }
 
 /// Contains full details of a request including an id to respond to and a `RequestCmd`
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct PadreRequest {
     id: u64,
     cmd: RequestCmd,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<serde_json::Value>,
 }
 
 impl PadreRequest {
     /// Create a request
     pub fn new(id: u64, cmd: RequestCmd) -> Self {
-        PadreRequest { id, cmd }
+        PadreRequest {
+            id,
+            cmd,
+            meta: None,
+        }
+    }
+
+    /// Attach the `meta` the client sent with the request, it's echoed back untouched in
+    /// the response for the client's own bookkeeping
+    pub fn with_meta(mut self, meta: serde_json::Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Return the `meta` the client sent with the request
+    pub fn meta(&self) -> Option<&serde_json::Value> {
+        self.meta.as_ref()
     }
 
     /// Return the request id
@@ -102,6 +164,14 @@ impl Response {
     pub fn resp(&self) -> &serde_json::Value {
         &self.resp
     }
+
+    /// Echo back the `meta` sent with the request being responded to, if there was one
+    pub fn with_meta(mut self, meta: Option<serde_json::Value>) -> Self {
+        if let (Some(meta), true) = (meta, self.resp.is_object()) {
+            self.resp["meta"] = meta;
+        }
+        self
+    }
 }
 
 /// A notification to be sent to all listeners of an event
@@ -144,19 +214,52 @@ pub enum PadreSend {
     Notification(Notification),
 }
 
-/// Process a TCP socket connection.
+/// Process a socket connection, either TCP or a Unix domain socket.
 ///
 /// Fully sets up a new socket connection including listening for requests and sending responses.
-pub fn process_connection(socket: TcpStream, debugger: Arc<Mutex<Debugger>>) {
-    let addr = socket.peer_addr().unwrap();
-
+/// The `addr` is used to identify the connection to the notifier.
+///
+/// When an `auth_token` is given the first request must be an `auth` request with that
+/// token, until then every other request is answered with an error and the connection
+/// isn't sent any notifications.
+///
+/// When the `IdleTimeout` config item is set the connection is pinged after that long
+/// without a request, if the ping can't be sent the connection is closed. When the
+/// `HeartbeatInterval` config item is set it's sent a heartbeat that often.
+pub fn process_connection<S>(
+    socket: S,
+    addr: String,
+    debugger: Arc<Mutex<Debugger>>,
+    auth_token: Option<String>,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
     let config = Arc::new(Mutex::new(Config::new()));
 
     let (request_tx, request_rx) = VimCodec::new().framed(socket).split();
 
     let (connection_tx, connection_rx) = mpsc::channel(1);
 
-    add_listener(connection_tx.clone(), addr.clone());
+    let mut authenticated = auth_token.is_none();
+    let auth_token = auth_token.unwrap_or_default();
+
+    let last_request = Arc::new(Mutex::new(Instant::now()));
+    let open = Arc::new(AtomicBool::new(true));
+
+    if authenticated {
+        start_notifying(
+            connection_tx.clone(),
+            addr.clone(),
+            config.clone(),
+            debugger.clone(),
+            open.clone(),
+        );
+    }
+
+    // Nothing is ever sent, reading requests stops when the sender is dropped because
+    // nothing more can be written to the socket
+    let (close_tx, close_rx) = mpsc::channel::<()>(1);
+    let addr_2 = addr.clone();
 
     tokio::spawn(
         request_tx
@@ -164,45 +267,330 @@ pub fn process_connection(socket: TcpStream, debugger: Arc<Mutex<Debugger>>) {
                 eprintln!("failed to retrieve message to send: {}", e);
                 io::Error::new(io::ErrorKind::Other, e)
             }))
-            .then(|res| {
+            .then(move |res| {
                 if let Err(e) = res {
                     eprintln!("failed to send data to socket; error = {:?}", e);
+                    remove_listener(&addr_2);
                 }
 
+                drop(close_tx);
+
                 Ok(())
             }),
     );
 
+    tokio::spawn(check_idle_connection(
+        connection_tx.clone(),
+        config.clone(),
+        last_request.clone(),
+        open.clone(),
+    ));
+
     let connection_tx_2 = connection_tx.clone();
+    let addr_3 = addr.clone();
+    let open_2 = open.clone();
+    let debugger_2 = debugger.clone();
+
+    let requests = request_rx
+        .map(
+            // Each response comes with whether it's to a shutdown, so that PADRE exits once
+            // it's been sent
+            move |req| -> Box<dyn Future<Item = (Response, bool), Error = io::Error> + Send> {
+                *last_request.lock().unwrap() = Instant::now();
+
+                let meta = req.meta().cloned();
+
+                if !authenticated {
+                    let (resp, ok) = check_auth(&req, &auth_token);
+                    if ok {
+                        authenticated = true;
+                        start_notifying(
+                            connection_tx.clone(),
+                            addr_3.clone(),
+                            config.clone(),
+                            debugger.clone(),
+                            open_2.clone(),
+                        );
+                    }
+                    return Box::new(future::ok((resp.with_meta(meta), false)));
+                }
+
+                util::record_request(&req);
+                let shutdown = req.cmd() == &RequestCmd::PadreCmd(PadreCmd::Shutdown);
+                Box::new(
+                    respond(req, debugger.clone(), config.clone())
+                        .map(move |resp| (resp.with_meta(meta), shutdown)),
+                )
+            },
+        )
+        .buffered(MAX_REQUESTS_IN_PROGRESS)
+        .for_each(move |(resp, shutdown)| {
+            let debugger = debugger_2.clone();
+            tokio::spawn(
+                connection_tx_2
+                    .clone()
+                    .send(PadreSend::Response(resp))
+                    .map(move |_| {
+                        if shutdown {
+                            exit_after_shutdown(debugger);
+                        }
+                    })
+                    .map_err(|e| println!("Error responding: {}", e)),
+            );
+            Ok(())
+        })
+        .map_err(move |e| {
+            match e.kind() {
+                // Remove socket from notifier if pipe broken, otherwise report error
+                std::io::ErrorKind::ConnectionReset => {
+                    remove_listener(&addr);
+                }
+                _ => unreachable!(),
+            }
+        });
+
+    tokio::spawn(
+        requests
+            .select(close_rx.into_future().map(|_| ()).map_err(|_| ()))
+            .then(move |_| {
+                open.store(false, Ordering::SeqCst);
+                Ok(())
+            }),
+    );
+}
+
+/// Start sending notifications to a connection
+fn start_notifying(
+    connection_tx: mpsc::Sender<PadreSend>,
+    addr: String,
+    config: Arc<Mutex<Config<'static>>>,
+    debugger: Arc<Mutex<Debugger>>,
+    open: Arc<AtomicBool>,
+) {
+    add_listener(connection_tx.clone(), addr, config.clone());
+
+    tokio::spawn(send_heartbeats(connection_tx, config, debugger, open));
+}
+
+/// Check a request on a connection that hasn't authenticated yet
+///
+/// Returns the response to it and whether the connection is now authenticated.
+fn check_auth(request: &PadreRequest, auth_token: &str) -> (Response, bool) {
+    let error = match request.cmd() {
+        RequestCmd::PadreCmd(PadreCmd::Auth(token)) if token == auth_token => {
+            return (
+                Response::new(request.id(), serde_json::json!({"status":"OK"})),
+                true,
+            );
+        }
+        RequestCmd::PadreCmd(PadreCmd::Auth(_)) => "Invalid auth token",
+        _ => "Not authenticated, the first request must be auth with the token",
+    };
+
+    log_msg(LogLevel::WARN, error);
+
+    let mut resp = error_response(ErrorCode::RequestError);
+    resp["error"] = serde_json::json!(error);
+
+    (Response::new(request.id(), resp), false)
+}
+
+/// Ping a connection whenever it's gone `IdleTimeout` seconds without a request
+///
+/// Checked every second so that changes to `IdleTimeout` are picked up, stops once the
+/// connection is closed. After a ping the connection gets another `IdleTimeout` seconds
+/// before the next one.
+fn check_idle_connection(
+    connection_tx: mpsc::Sender<PadreSend>,
+    config: Arc<Mutex<Config<'static>>>,
+    last_request: Arc<Mutex<Instant>>,
+    open: Arc<AtomicBool>,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let f = Interval::new_interval(Duration::new(1, 0))
+        .map_err(|e| eprintln!("Idle connection timer error: {}", e))
+        .take_while(move |_| Ok(open.load(Ordering::SeqCst)))
+        .for_each(move |_| {
+            let idle_timeout = config.lock().unwrap().get_config("IdleTimeout").unwrap();
+            let mut last_request = last_request.lock().unwrap();
+
+            if idle_timeout == 0 || last_request.elapsed() < Duration::new(idle_timeout as u64, 0) {
+                return Ok(());
+            }
+
+            *last_request = Instant::now();
+
+            tokio::spawn(
+                connection_tx
+                    .clone()
+                    .send(PadreSend::Notification(ping_msg()))
+                    .map(|_| ())
+                    .map_err(|e| eprintln!("Can't ping idle connection: {}", e)),
+            );
+
+            Ok(())
+        });
+
+    Box::new(f)
+}
+
+/// Send a connection a heartbeat every `HeartbeatInterval` seconds
+///
+/// Checked every second so that changes to `HeartbeatInterval` are picked up, stops once
+/// the connection is closed.
+fn send_heartbeats(
+    connection_tx: mpsc::Sender<PadreSend>,
+    config: Arc<Mutex<Config<'static>>>,
+    debugger: Arc<Mutex<Debugger>>,
+    open: Arc<AtomicBool>,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let mut last_heartbeat = Instant::now();
+
+    let f = Interval::new_interval(Duration::new(1, 0))
+        .map_err(|e| eprintln!("Heartbeat timer error: {}", e))
+        .take_while(move |_| Ok(open.load(Ordering::SeqCst)))
+        .for_each(move |_| {
+            let interval = config
+                .lock()
+                .unwrap()
+                .get_config("HeartbeatInterval")
+                .unwrap();
+
+            if interval == 0 || last_heartbeat.elapsed() < Duration::new(interval as u64, 0) {
+                return Ok(());
+            }
+
+            last_heartbeat = Instant::now();
+
+            let status = debugger.lock().unwrap().status();
+
+            tokio::spawn(
+                connection_tx
+                    .clone()
+                    .send(PadreSend::Notification(heartbeat_msg(status)))
+                    .map(|_| ())
+                    .map_err(|e| eprintln!("Can't send heartbeat: {}", e)),
+            );
+
+            Ok(())
+        });
+
+    Box::new(f)
+}
+
+/// Replay the requests recorded in a file with `--record` instead of listening for
+/// connections
+///
+/// Each request is sent as soon as the previous one has been answered. Responses and
+/// notifications are printed to stdout as they would be sent to a connection. Once every
+/// request has been answered PADRE exits with the exit code of the program, see
+/// `exit_after_program`.
+pub fn replay(path: &str, debugger: Arc<Mutex<Debugger>>) -> Result<(), io::Error> {
+    let contents = fs::read_to_string(path)?;
+
+    let config = Arc::new(Mutex::new(Config::new()));
+
+    let (connection_tx, connection_rx) = mpsc::channel(1);
+
+    add_listener(connection_tx.clone(), "replay".to_string(), config.clone());
 
     tokio::spawn(
-        request_rx
-            .and_then(move |req| respond(req, debugger.clone(), config.clone()))
-            .for_each(move |resp| {
-                tokio::spawn(
-                    connection_tx_2
-                        .clone()
+        connection_rx
+            .for_each(|msg| {
+                let mut buf = BytesMut::new();
+                VimCodec::new().encode(msg, &mut buf).unwrap();
+                print!("{}", String::from_utf8_lossy(&buf));
+                Ok(())
+            })
+            .map_err(|e| eprintln!("failed to retrieve message to print: {}", e)),
+    );
+
+    let requests = parse_recorded_requests(&contents);
+
+    let debugger_2 = debugger.clone();
+
+    tokio::spawn(
+        stream::iter_ok(requests)
+            .for_each(move |req| {
+                let connection_tx = connection_tx.clone();
+                respond(req, debugger.clone(), config.clone()).and_then(move |resp| {
+                    connection_tx
                         .send(PadreSend::Response(resp))
                         .map(|_| {})
-                        .map_err(|e| println!("Error responding: {}", e)),
-                );
-                Ok(())
+                        .map_err(io::Error::other)
+                })
             })
-            .map_err(move |e| {
-                match e.kind() {
-                    // Remove socket from notifier if pipe broken, otherwise report error
-                    std::io::ErrorKind::ConnectionReset => {
-                        remove_listener(&addr.clone());
-                    }
-                    _ => unreachable!(),
-                }
-            }),
+            .and_then(move |_| {
+                log_msg(LogLevel::INFO, "Finished replaying requests");
+                exit_after_program(debugger_2)
+            })
+            .map_err(|e| eprintln!("Error replaying requests: {}", e)),
     );
 
-    tokio::spawn(future::lazy(|| {
-        check_for_and_report_padre_updates();
-        Ok(())
-    }));
+    Ok(())
+}
+
+/// Wait for the program to exit and exit PADRE with its exit code
+///
+/// If the program was never run, or has exited and not been restarted, PADRE exits
+/// straight away, with 0 if the program never exited.
+fn exit_after_program(debugger: Arc<Mutex<Debugger>>) -> impl Future<Item = (), Error = io::Error> {
+    let debugger_2 = debugger.clone();
+
+    Interval::new_interval(Duration::from_millis(100))
+        .map_err(io::Error::other)
+        .skip_while(move |_| {
+            let debugger = debugger.lock().unwrap();
+            Ok(debugger.exit_code().is_none() && debugger.status() != ProgramStatus::NotRunning)
+        })
+        .into_future()
+        .map_err(|(e, _)| e)
+        .map(move |_| {
+            let mut debugger = debugger_2.lock().unwrap();
+            let exit_code = debugger.exit_code().unwrap_or(0);
+            log_msg(
+                LogLevel::INFO,
+                &format!("Program exited with {}, exiting", exit_code),
+            );
+            debugger.stop(exit_code as i32);
+        })
+}
+
+/// Exit PADRE after a `shutdown` has been answered, stopping the debugger
+///
+/// The response has only been queued to send by now so there's a moment's wait for it to
+/// be written to the socket first.
+fn exit_after_shutdown(debugger: Arc<Mutex<Debugger>>) {
+    tokio::spawn(
+        Delay::new(Instant::now() + Duration::from_millis(100)).then(move |_| {
+            debugger.lock().unwrap().stop(0);
+            Ok(())
+        }),
+    );
+}
+
+/// Read the requests recorded one per line, lines that aren't requests are skipped
+fn parse_recorded_requests(contents: &str) -> Vec<PadreRequest> {
+    let mut requests = vec![];
+
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(line) {
+            Ok(request) => requests.push(request),
+            Err(e) => log_msg(
+                LogLevel::WARN,
+                &format!(
+                    "Skipping line {} of recording, can't read request: {}",
+                    i + 1,
+                    e
+                ),
+            ),
+        }
+    }
+
+    requests
 }
 
 /// Process a PadreRequest.
@@ -211,46 +599,280 @@ pub fn process_connection(socket: TcpStream, debugger: Arc<Mutex<Debugger>>) {
 fn respond(
     request: PadreRequest,
     debugger: Arc<Mutex<Debugger>>,
-    config: Arc<Mutex<Config>>,
+    config: Arc<Mutex<Config<'static>>>,
 ) -> Box<dyn Future<Item = Response, Error = io::Error> + Send> {
     match request.cmd() {
+        RequestCmd::PadreCmd(PadreCmd::BadRequest(msg)) => {
+            let mut resp = error_response(ErrorCode::RequestError);
+            resp["error"] = serde_json::json!(msg);
+            Box::new(future::ok(Response::new(request.id(), resp)))
+        }
+        RequestCmd::PadreCmd(PadreCmd::Unknown(cmd, _)) => {
+            util::send_error_and_debug("Command unknown", &format!("Command unknown: '{}'", cmd));
+            let resp = unknown_command_response(cmd);
+            Box::new(future::ok(Response::new(request.id(), resp)))
+        }
+        // Answered straight away rather than waiting for the command it's cancelling
+        RequestCmd::PadreCmd(PadreCmd::Cancel) => {
+            let f = debugger.lock().unwrap().cancel(config);
+            Box::new(f.map(move |resp| Response::new(request.id(), with_error_code(resp))))
+        }
         RequestCmd::PadreCmd(cmd) => {
             let json_response = match cmd {
                 PadreCmd::Ping => ping(),
                 PadreCmd::Pings => pings(),
+                PadreCmd::Echo(payload) => echo(payload),
                 PadreCmd::GetConfig(key) => get_config(config, key),
                 PadreCmd::SetConfig(key, value) => set_config(config, key, *value),
+                PadreCmd::Capabilities => Ok(debugger.lock().unwrap().capabilities()),
+                PadreCmd::GetPid => Ok(debugger.lock().unwrap().pid()),
+                PadreCmd::Status => Ok(debugger.lock().unwrap().state()),
+                PadreCmd::Shutdown => {
+                    debugger.lock().unwrap().shut_down();
+                    Ok(serde_json::json!({"status":"OK"}))
+                }
+                // Only needed before anything else on the connection
+                PadreCmd::Auth(_) => Ok(serde_json::json!({"status":"OK"})),
+                PadreCmd::Cancel | PadreCmd::BadRequest(_) | PadreCmd::Unknown(_, _) => {
+                    unreachable!()
+                }
             };
 
             Box::new(future::lazy(move || match json_response {
-                Ok(args) => Ok(Response::new(request.id(), args)),
+                Ok(args) => Ok(Response::new(request.id(), with_error_code(args))),
                 Err(e) => {
                     log_msg(LogLevel::ERROR, &format!("{}", e));
-                    let resp = serde_json::json!({"status":"ERROR"});
+                    let mut resp = error_response(ErrorCode::RequestError);
+                    resp["error"] = serde_json::json!(e.to_string());
                     Ok(Response::new(request.id(), resp))
                 }
             }))
         }
         RequestCmd::DebuggerCmd(cmd) => {
-            let f = match cmd {
-                DebuggerCmd::V1(v1cmd) => debugger.lock().unwrap().handle_v1_cmd(v1cmd, config),
-            };
+            let timeout = get_debugger_cmd_timeout(cmd, &config.lock().unwrap());
+            let stops = is_stopping_cmd(cmd);
 
-            Box::new(
-                f.timeout(Duration::new(30, 0))
-                    .then(move |resp| match resp {
-                        Ok(s) => Ok(Response::new(request.id(), s)),
-                        Err(e) => {
-                            log_msg(LogLevel::ERROR, &format!("{}", e));
-                            let resp = serde_json::json!({"status":"ERROR"});
-                            Ok(Response::new(request.id(), resp))
-                        }
-                    }),
-            )
+            // The timeout only starts once the command does, not while it's waiting for
+            // earlier commands to finish.
+            let cmd = cmd.clone();
+            let cmd_config = config.clone();
+            let f = run_in_order(debugger.clone(), move |d| {
+                let f = match &cmd {
+                    DebuggerCmd::V1(v1cmd) => d.handle_v1_cmd(v1cmd, cmd_config),
+                };
+                with_timeout(f, &cmd, timeout)
+            });
+
+            Box::new(f.then(move |resp| match resp {
+                Ok(s) => {
+                    // Watches are re-evaluated whenever the program may have stopped
+                    // somewhere new
+                    if stops && s["status"] == "OK" && s["terminated"].is_null() {
+                        tokio::spawn(update_watches(debugger, config));
+                    }
+                    Ok(Response::new(request.id(), with_error_code(s)))
+                }
+                Err(e) => {
+                    log_msg(LogLevel::ERROR, &format!("{}", e));
+                    let resp = error_response(get_error_code(&e));
+                    Ok(Response::new(request.id(), resp))
+                }
+            }))
         }
     }
 }
 
+/// Give an error response without a code the `debugger_error` code, as the debuggers often
+/// just say there was an error
+fn with_error_code(mut resp: serde_json::Value) -> serde_json::Value {
+    if resp["status"] == "ERROR" && resp["code"].is_null() {
+        resp["code"] = serde_json::json!(ErrorCode::DebuggerError);
+    }
+    resp
+}
+
+/// Respond with a timeout error if a debugger command takes longer than `timeout` seconds
+///
+/// The error response says which command timed out and the timeout used.
+fn with_timeout(
+    f: Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send>,
+    cmd: &DebuggerCmd,
+    timeout: u64,
+) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+    let name = get_debugger_cmd_name(cmd);
+    let key = get_debugger_cmd_timeout_key(cmd);
+
+    let f = f
+        .timeout(Duration::new(timeout, 0))
+        .then(move |resp| match resp {
+            Ok(s) => Ok(s),
+            Err(ref e) if e.is_elapsed() => {
+                log_msg(
+                    LogLevel::ERROR,
+                    &format!(
+                        "Timed out after {} seconds waiting for {}, try increasing {}",
+                        timeout, name, key
+                    ),
+                );
+                let mut resp = error_response(ErrorCode::Timeout);
+                resp["cmd"] = serde_json::json!(name);
+                resp["timeout"] = serde_json::json!(timeout);
+                Ok(resp)
+            }
+            Err(e) => Err(match e.into_inner() {
+                Some(e) => e,
+                None => io::Error::other("Timer error"),
+            }),
+        });
+
+    Box::new(f)
+}
+
+/// Get the timeout in seconds for a debugger command
+///
+/// Falls back to the `CommandTimeout` config item when the command has no
+/// timeout of its own configured. It's never more than `MAX_DEBUGGER_CMD_TIMEOUT`.
+fn get_debugger_cmd_timeout(cmd: &DebuggerCmd, config: &Config) -> u64 {
+    let timeout = match config.get_config(get_debugger_cmd_timeout_key(cmd)) {
+        Some(t) => t as u64,
+        None => config.get_config("CommandTimeout").unwrap_or(30) as u64,
+    };
+
+    // Counted steps get the step timeout for each step, and the debugger gives up
+    // waiting for a stop itself after that so allow it a second longer to answer.
+    let timeout = match cmd {
+        DebuggerCmd::V1(DebuggerCmdV1::StepIn(count, _))
+        | DebuggerCmd::V1(DebuggerCmdV1::StepOver(count, _)) => {
+            timeout.saturating_mul((*count).max(1)).saturating_add(1)
+        }
+        DebuggerCmd::V1(DebuggerCmdV1::Continue) | DebuggerCmd::V1(DebuggerCmdV1::Interrupt) => {
+            timeout.saturating_add(1)
+        }
+        _ => timeout,
+    };
+
+    timeout.min(MAX_DEBUGGER_CMD_TIMEOUT)
+}
+
+/// Get the config item for the timeout of a debugger command
+fn get_debugger_cmd_timeout_key(cmd: &DebuggerCmd) -> &'static str {
+    match cmd {
+        DebuggerCmd::V1(v1cmd) => match v1cmd {
+            DebuggerCmdV1::Run => "ProcessSpawnTimeout",
+            DebuggerCmdV1::Breakpoint(_)
+            | DebuggerCmdV1::HitConditionBreakpoint(_, _)
+            | DebuggerCmdV1::Logpoint(_, _)
+            | DebuggerCmdV1::DisableBreakpoint(_)
+            | DebuggerCmdV1::EnableBreakpoint(_)
+            | DebuggerCmdV1::ClearAllBreakpoints
+            | DebuggerCmdV1::ValidateBreakpoint(_)
+            | DebuggerCmdV1::Watchpoint(_, _)
+            | DebuggerCmdV1::ExceptionBreakpoint { .. } => "BreakpointTimeout",
+            DebuggerCmdV1::StepIn(_, _)
+            | DebuggerCmdV1::StepOver(_, _)
+            | DebuggerCmdV1::Continue
+            | DebuggerCmdV1::Finish
+            | DebuggerCmdV1::ReverseContinue
+            | DebuggerCmdV1::ReverseStepOver
+            | DebuggerCmdV1::Interrupt
+            | DebuggerCmdV1::SetNextStatement(_) => "StepTimeout",
+            DebuggerCmdV1::Print(..)
+            | DebuggerCmdV1::Assert(_)
+            | DebuggerCmdV1::GetChildren { .. }
+            | DebuggerCmdV1::ReadSymbol(_)
+            | DebuggerCmdV1::TypeOf(_) => "PrintVariableTimeout",
+            DebuggerCmdV1::Raw(_) => "RawCommandTimeout",
+            DebuggerCmdV1::ListThreads
+            | DebuggerCmdV1::SetCwd(_)
+            | DebuggerCmdV1::ListSources
+            | DebuggerCmdV1::ListModules
+            | DebuggerCmdV1::GetFrameSource { .. }
+            | DebuggerCmdV1::StackWithLocals { .. }
+            | DebuggerCmdV1::Detach
+            | DebuggerCmdV1::SelectThread(_)
+            | DebuggerCmdV1::AddWatch(_)
+            | DebuggerCmdV1::RemoveWatch(_)
+            | DebuggerCmdV1::CurrentLocation => "CommandTimeout",
+        },
+    }
+}
+
+/// Get the name of a debugger command as sent in a request
+fn get_debugger_cmd_name(cmd: &DebuggerCmd) -> &'static str {
+    match cmd {
+        DebuggerCmd::V1(v1cmd) => match v1cmd {
+            DebuggerCmdV1::Run => "run",
+            DebuggerCmdV1::Breakpoint(_)
+            | DebuggerCmdV1::HitConditionBreakpoint(_, _)
+            | DebuggerCmdV1::Logpoint(_, _) => "breakpoint",
+            DebuggerCmdV1::DisableBreakpoint(_) => "disableBreakpoint",
+            DebuggerCmdV1::EnableBreakpoint(_) => "enableBreakpoint",
+            DebuggerCmdV1::ClearAllBreakpoints => "clearAllBreakpoints",
+            DebuggerCmdV1::ValidateBreakpoint(_) => "validateBreakpoint",
+            DebuggerCmdV1::StepIn(_, _) => "stepIn",
+            DebuggerCmdV1::StepOver(_, _) => "stepOver",
+            DebuggerCmdV1::Continue => "continue",
+            DebuggerCmdV1::Finish => "finish",
+            DebuggerCmdV1::ReverseContinue => "reverseContinue",
+            DebuggerCmdV1::ReverseStepOver => "reverseStepOver",
+            DebuggerCmdV1::SetCwd(_) => "setCwd",
+            DebuggerCmdV1::Interrupt => "interrupt",
+            DebuggerCmdV1::Detach => "detach",
+            DebuggerCmdV1::Print(..) => "print",
+            DebuggerCmdV1::Assert(_) => "assert",
+            DebuggerCmdV1::Raw(_) => "raw",
+            DebuggerCmdV1::ListThreads => "threads",
+            DebuggerCmdV1::SelectThread(_) => "selectThread",
+            DebuggerCmdV1::AddWatch(_) => "addWatch",
+            DebuggerCmdV1::RemoveWatch(_) => "removeWatch",
+            DebuggerCmdV1::Watchpoint(_, _) => "watch",
+            DebuggerCmdV1::CurrentLocation => "currentLocation",
+            DebuggerCmdV1::ExceptionBreakpoint { .. } => "exceptionBreakpoint",
+            DebuggerCmdV1::GetChildren { .. } => "getChildren",
+            DebuggerCmdV1::ListSources => "listSources",
+            DebuggerCmdV1::ListModules => "listModules",
+            DebuggerCmdV1::ReadSymbol(_) => "readSymbol",
+            DebuggerCmdV1::TypeOf(_) => "typeOf",
+            DebuggerCmdV1::SetNextStatement(_) => "setNextStatement",
+            DebuggerCmdV1::GetFrameSource { .. } => "frameSource",
+            DebuggerCmdV1::StackWithLocals { .. } => "stackWithLocals",
+        },
+    }
+}
+
+/// Get the error code to respond with for an error from a debugger
+fn get_error_code(e: &io::Error) -> ErrorCode {
+    match e.kind() {
+        io::ErrorKind::TimedOut => ErrorCode::Timeout,
+        io::ErrorKind::Interrupted => ErrorCode::Cancelled,
+        _ => ErrorCode::DebuggerError,
+    }
+}
+
+/// Whether the command can leave the program stopped at a new position
+fn is_stopping_cmd(cmd: &DebuggerCmd) -> bool {
+    matches!(
+        cmd,
+        DebuggerCmd::V1(DebuggerCmdV1::StepIn(_, _))
+            | DebuggerCmd::V1(DebuggerCmdV1::StepOver(_, _))
+            | DebuggerCmd::V1(DebuggerCmdV1::Continue)
+            | DebuggerCmd::V1(DebuggerCmdV1::Finish)
+            | DebuggerCmd::V1(DebuggerCmdV1::ReverseContinue)
+            | DebuggerCmd::V1(DebuggerCmdV1::ReverseStepOver)
+            | DebuggerCmd::V1(DebuggerCmdV1::Interrupt)
+            | DebuggerCmd::V1(DebuggerCmdV1::SetNextStatement(_))
+    )
+}
+
+/// The response to a command that isn't known, listing the commands that are
+fn unknown_command_response(cmd: &str) -> serde_json::Value {
+    let mut resp = error_response(ErrorCode::RequestError);
+    resp["error"] = serde_json::json!(format!("Command unknown: '{}'", cmd));
+    resp["command"] = serde_json::json!(cmd);
+    resp["knownCommands"] = serde_json::json!(COMMANDS);
+    resp
+}
+
 fn ping() -> Result<serde_json::Value, io::Error> {
     Ok(serde_json::json!({"status":"OK","ping":"pong"}))
 }
@@ -261,11 +883,20 @@ fn pings() -> Result<serde_json::Value, io::Error> {
     Ok(serde_json::json!({"status":"OK"}))
 }
 
+fn echo(payload: &serde_json::Value) -> Result<serde_json::Value, io::Error> {
+    let received = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(io::Error::other)?;
+    let received = received.as_secs() * 1000 + u64::from(received.subsec_millis());
+
+    Ok(serde_json::json!({"status":"OK","payload":payload,"received":received}))
+}
+
 fn get_config(config: Arc<Mutex<Config>>, key: &str) -> Result<serde_json::Value, io::Error> {
-    let value = config.lock().unwrap().get_config(key);
-    match value {
+    let config = config.lock().unwrap();
+    match config.get_config(key) {
         Some(v) => Ok(serde_json::json!({"status":"OK","value":v})),
-        None => Ok(serde_json::json!({"status":"ERROR"})),
+        None => Err(config.unknown_key(key)),
     }
 }
 
@@ -274,57 +905,518 @@ fn set_config(
     key: &str,
     value: i64,
 ) -> Result<serde_json::Value, io::Error> {
-    let config_set = config.lock().unwrap().set_config(key, value);
-    match config_set {
-        true => Ok(serde_json::json!({"status":"OK"})),
-        false => Ok(serde_json::json!({"status":"ERROR"})),
-    }
+    config.lock().unwrap().set_config(key, value)?;
+    Ok(serde_json::json!({"status":"OK"}))
 }
 
 /// Checks whether we're on the latest version with git and if not gives a warning
-fn check_for_and_report_padre_updates() {
-    let padre_exe = current_exe().unwrap();
-    let padre_dir = padre_exe.parent().unwrap();
+///
+/// Run once when PADRE starts unless `--no-update-check` is given. The git commands are run
+/// on a separate thread so as not to block anything and if PADRE isn't in a git checkout or
+/// git can't be found we don't report anything.
+pub fn check_for_and_report_padre_updates() {
+    let (tx, rx) = oneshot::channel();
+
+    thread::spawn(move || {
+        let _ = tx.send(is_padre_out_of_date());
+    });
+
+    tokio::spawn(
+        rx.map(|out_of_date| {
+            if out_of_date {
+                log_msg(LogLevel::WARN, "Your PADRE version is out of date and should be updated, please run `git pull` in your PADRE directory and and then rerun `make`.");
+            }
+        })
+        .map_err(|_| {}),
+    );
+}
+
+/// Returns true only if we can tell from git that PADRE is behind master
+fn is_padre_out_of_date() -> bool {
+    let padre_exe = match current_exe() {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    let padre_dir = match padre_exe.parent() {
+        Some(s) => s,
+        None => return false,
+    };
+
+    // TODO: Assumes git is used for now, add releasing option in later.
+    let status = match run_git(vec!["status"], padre_dir) {
+        Some(s) => s,
+        None => return false,
+    };
+
+    // TODO: Change
+    if status.split('\n').next() != Some("On branch master") {
+        return false;
+    }
 
-    // TODO: Assumes git is used for now and exists, add releasing option in later.
+    if run_git(vec!["remote", "update"], padre_dir).is_none() {
+        return false;
+    }
+
+    match run_git(vec!["status"], padre_dir) {
+        Some(status) => match status.split('\n').nth(1) {
+            Some(s) => s.starts_with("Your branch is behind "),
+            None => false,
+        },
+        None => false,
+    }
+}
+
+/// Run git in the directory specified and return stdout if it ran successfully
+fn run_git(args: Vec<&str>, dir: &Path) -> Option<String> {
     let output = Command::new("git")
-        .arg("status")
-        .current_dir(padre_dir)
+        .args(args)
+        .current_dir(dir)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .output()
-        .expect("Failed to execute git command, can't tell if PADRE needs updating");
+        .ok()?;
 
-    let status = str::from_utf8(&output.stdout)
-        .unwrap()
-        .split('\n')
-        .collect::<Vec<&str>>();
+    if !output.status.success() {
+        return None;
+    }
 
-    // TODO: Change
-    if *status.get(0).unwrap() == "On branch master" {
-        Command::new("git")
-            .args(vec!["remote", "update"])
-            .current_dir(padre_dir)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .expect("Failed to execute git command, can't tell if PADRE needs updating");
-
-        let output = Command::new("git")
-            .arg("status")
-            .current_dir(padre_dir)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .expect("Failed to execute git command, can't tell if PADRE needs updating");
-
-        let status = str::from_utf8(&output.stdout)
-            .unwrap()
-            .split('\n')
-            .collect::<Vec<&str>>();
+    String::from_utf8(output.stdout).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use crate::config::Config;
+    use crate::debugger::{
+        Capabilities, Debugger, DebuggerCmd, DebuggerCmdV1, DebuggerV1, FileLocation,
+        ProgramStatus, Variable,
+    };
+
+    use tokio::prelude::*;
+    use tokio::timer::Delay;
+
+    #[test]
+    fn check_error_codes() {
+        let e = io::Error::new(io::ErrorKind::TimedOut, "Timed out printing variable");
+        assert_eq!(
+            super::error_response(super::get_error_code(&e)),
+            serde_json::json!({"status":"ERROR","code":"timeout"})
+        );
+
+        let e = io::Error::other("LLDB process ended");
+        assert_eq!(
+            super::error_response(super::get_error_code(&e)),
+            serde_json::json!({"status":"ERROR","code":"debugger_error"})
+        );
+    }
+
+    #[test]
+    fn check_error_responses_given_a_code() {
+        assert_eq!(
+            super::with_error_code(serde_json::json!({"status":"ERROR"})),
+            serde_json::json!({"status":"ERROR","code":"debugger_error"})
+        );
+        assert_eq!(
+            super::with_error_code(serde_json::json!({"status":"ERROR","code":"timeout"})),
+            serde_json::json!({"status":"ERROR","code":"timeout"})
+        );
+        assert_eq!(
+            super::with_error_code(serde_json::json!({"status":"OK"})),
+            serde_json::json!({"status":"OK"})
+        );
+    }
 
-        if status.get(1).unwrap().starts_with("Your branch is behind ") {
-            log_msg(LogLevel::WARN, "Your PADRE version is out of date and should be updated, please run `git pull` in your PADRE directory and and then rerun `make`.");
+    #[test]
+    fn check_malformed_recorded_requests_skipped() {
+        let contents = "{\"id\":1,\"cmd\":{\"PadreCmd\":\"Ping\"}}\n\
+                        not a request\n\
+                        \n\
+                        {\"id\":2,\"cmd\":{\"DebuggerCmd\":{\"V1\":\"Run\"}}}\n";
+
+        assert_eq!(
+            super::parse_recorded_requests(contents),
+            vec![
+                super::PadreRequest::new(1, super::RequestCmd::PadreCmd(super::PadreCmd::Ping)),
+                super::PadreRequest::new(
+                    2,
+                    super::RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Run))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_requests_recorded_and_read_back() {
+        let requests = vec![
+            super::PadreRequest::new(
+                1,
+                super::RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Breakpoint(
+                    FileLocation::new("test.c".to_string(), 12),
+                ))),
+            ),
+            super::PadreRequest::new(
+                2,
+                super::RequestCmd::PadreCmd(super::PadreCmd::SetConfig(
+                    "StepTimeout".to_string(),
+                    5,
+                )),
+            ),
+        ];
+
+        for request in requests {
+            let json = serde_json::to_string(&request).unwrap();
+            let read: super::PadreRequest = serde_json::from_str(&json).unwrap();
+            assert_eq!(read, request);
+        }
+    }
+
+    /// A debugger that never replies to anything
+    #[derive(Debug)]
+    struct SilentDebugger;
+
+    impl DebuggerV1 for SilentDebugger {
+        fn setup(&mut self) -> Result<(), io::Error> {
+            Ok(())
         }
+
+        fn teardown(&mut self, _: i32) {}
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::new("silent", vec![])
+        }
+
+        fn pid(&self) -> Option<u64> {
+            None
+        }
+
+        fn run(
+            &mut self,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn breakpoint(
+            &mut self,
+            _: &FileLocation,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn disable_breakpoint(
+            &mut self,
+            _: &FileLocation,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn enable_breakpoint(
+            &mut self,
+            _: &FileLocation,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn step_in(
+            &mut self,
+            _: u64,
+            _: bool,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn step_over(
+            &mut self,
+            _: u64,
+            _: bool,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn continue_(
+            &mut self,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn interrupt(
+            &mut self,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn print(
+            &mut self,
+            _: &Variable,
+            _: Option<u64>,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn raw(
+            &mut self,
+            _: &str,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+
+        fn current_location(
+            &mut self,
+            _: Arc<Mutex<Config>>,
+        ) -> Box<dyn Future<Item = serde_json::Value, Error = io::Error> + Send> {
+            Box::new(future::empty())
+        }
+    }
+
+    #[test]
+    fn check_capabilities() {
+        let debugger = Debugger::new(Box::new(SilentDebugger));
+
+        assert_eq!(
+            debugger.capabilities(),
+            serde_json::json!({
+                "status": "OK",
+                "debugger": "silent",
+                "commands": ["validateBreakpoint", "addWatch", "removeWatch"],
+                "debuggerVersion": null,
+                "version": env!("CARGO_PKG_VERSION"),
+            })
+        );
+    }
+
+    #[test]
+    fn check_setting_unknown_config_key_explained() {
+        let debugger = Arc::new(Mutex::new(Debugger::new(Box::new(SilentDebugger))));
+        let config = Arc::new(Mutex::new(Config::new()));
+
+        let request = super::PadreRequest::new(
+            1,
+            super::RequestCmd::PadreCmd(super::PadreCmd::SetConfig("Foo".to_string(), 1)),
+        );
+
+        let response = super::respond(request, debugger, config).wait().unwrap();
+
+        assert_eq!(response.resp()["code"], "request_error");
+        assert!(response.resp()["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Unknown config key 'Foo', valid keys are "));
+    }
+
+    #[test]
+    fn check_unknown_command_explained() {
+        let debugger = Arc::new(Mutex::new(Debugger::new(Box::new(SilentDebugger))));
+        let config = Arc::new(Mutex::new(Config::new()));
+
+        let request = super::PadreRequest::new(
+            1,
+            super::RequestCmd::PadreCmd(super::PadreCmd::Unknown(
+                "frobnicate".to_string(),
+                serde_json::json!({"level": 11}),
+            )),
+        );
+
+        let response = super::respond(request, debugger, config).wait().unwrap();
+
+        assert_eq!(response.resp()["status"], "ERROR");
+        assert_eq!(response.resp()["code"], "request_error");
+        assert_eq!(response.resp()["error"], "Command unknown: 'frobnicate'");
+        assert_eq!(response.resp()["command"], "frobnicate");
+        let known = response.resp()["knownCommands"].as_array().unwrap();
+        assert!(known.contains(&serde_json::json!("breakpoint")));
+        assert!(known.contains(&serde_json::json!("status")));
+    }
+
+    #[test]
+    fn check_auth_required_before_other_requests() {
+        let ping = super::PadreRequest::new(1, super::RequestCmd::PadreCmd(super::PadreCmd::Ping));
+        let (response, ok) = super::check_auth(&ping, "secret");
+        assert!(!ok);
+        assert_eq!(response.resp()["code"], "request_error");
+
+        let auth = super::PadreRequest::new(
+            2,
+            super::RequestCmd::PadreCmd(super::PadreCmd::Auth("wrong".to_string())),
+        );
+        let (response, ok) = super::check_auth(&auth, "secret");
+        assert!(!ok);
+        assert_eq!(response.resp()["error"], "Invalid auth token");
+
+        let auth = super::PadreRequest::new(
+            3,
+            super::RequestCmd::PadreCmd(super::PadreCmd::Auth("secret".to_string())),
+        );
+        let (response, ok) = super::check_auth(&auth, "secret");
+        assert!(ok);
+        assert_eq!(response.resp(), &serde_json::json!({"status":"OK"}));
+    }
+
+    #[test]
+    fn check_pid_null_when_not_running() {
+        let debugger = Debugger::new(Box::new(SilentDebugger));
+
+        assert_eq!(
+            debugger.pid(),
+            serde_json::json!({"status": "OK", "pid": null})
+        );
+    }
+
+    #[test]
+    fn check_status_processing_while_command_runs() {
+        let debugger = Arc::new(Mutex::new(Debugger::new(Box::new(SilentDebugger))));
+        let mut config = Config::new();
+        config.set_config("BreakpointTimeout", 1).unwrap();
+        let config = Arc::new(Mutex::new(config));
+
+        assert_eq!(debugger.lock().unwrap().status(), ProgramStatus::NotRunning);
+
+        let breakpoint = super::PadreRequest::new(
+            1,
+            super::RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Breakpoint(
+                FileLocation::new("test.c".to_string(), 12),
+            ))),
+        );
+
+        let status = Arc::new(Mutex::new(None));
+        let status_inner = status.clone();
+        let debugger_inner = debugger.clone();
+
+        tokio::run(
+            super::respond(breakpoint, debugger.clone(), config)
+                .map(|_| ())
+                .map_err(|e| panic!("Request failed: {}", e))
+                .join(
+                    Delay::new(Instant::now() + Duration::from_millis(100))
+                        .map(move |_| {
+                            *status_inner.lock().unwrap() =
+                                Some(debugger_inner.lock().unwrap().status())
+                        })
+                        .map_err(|e| panic!("Timer failed: {}", e)),
+                )
+                .map(|_| ()),
+        );
+
+        assert_eq!(*status.lock().unwrap(), Some(ProgramStatus::Processing));
+        assert_eq!(debugger.lock().unwrap().status(), ProgramStatus::NotRunning);
+    }
+
+    #[test]
+    fn check_debugger_that_never_replies_times_out() {
+        let debugger = Arc::new(Mutex::new(Debugger::new(Box::new(SilentDebugger))));
+        let mut config = Config::new();
+        config.set_config("BreakpointTimeout", 1).unwrap();
+        config.set_config("PrintVariableTimeout", 1).unwrap();
+        let config = Arc::new(Mutex::new(config));
+
+        let breakpoint = super::PadreRequest::new(
+            1,
+            super::RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Breakpoint(
+                FileLocation::new("test.c".to_string(), 12),
+            ))),
+        );
+        let print = super::PadreRequest::new(
+            2,
+            super::RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Print(
+                Variable::new("a".to_string()),
+                None,
+                None,
+            ))),
+        );
+
+        let responses = Arc::new(Mutex::new(vec![]));
+        let responses_inner = responses.clone();
+
+        // The second command only starts once the first has timed out
+        tokio::run(
+            super::respond(breakpoint, debugger.clone(), config.clone())
+                .join(super::respond(print, debugger, config))
+                .map(move |(r1, r2)| *responses_inner.lock().unwrap() = vec![r1, r2])
+                .map_err(|e| panic!("Request failed: {}", e)),
+        );
+
+        assert_eq!(
+            *responses.lock().unwrap(),
+            vec![
+                super::Response::new(
+                    1,
+                    serde_json::json!({"status":"ERROR","code":"timeout","cmd":"breakpoint","timeout":1})
+                ),
+                super::Response::new(
+                    2,
+                    serde_json::json!({"status":"ERROR","code":"timeout","cmd":"print","timeout":1})
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_timeout_response() {
+        let cmd = DebuggerCmd::V1(DebuggerCmdV1::Print(
+            Variable::new("a".to_string()),
+            None,
+            None,
+        ));
+        let resp = Arc::new(Mutex::new(serde_json::json!(null)));
+        let resp_inner = resp.clone();
+
+        tokio::run(
+            super::with_timeout(Box::new(future::empty()), &cmd, 1)
+                .map(move |r| *resp_inner.lock().unwrap() = r)
+                .map_err(|e| panic!("Timeout failed: {}", e)),
+        );
+
+        assert_eq!(
+            *resp.lock().unwrap(),
+            serde_json::json!({"status":"ERROR","code":"timeout","cmd":"print","timeout":1})
+        );
+    }
+
+    #[test]
+    fn check_debugger_cmd_timeout_uses_command_config() {
+        let mut config = Config::new();
+        config.set_config("PrintVariableTimeout", 7).unwrap();
+        let cmd = DebuggerCmd::V1(DebuggerCmdV1::Print(
+            Variable::new("a".to_string()),
+            None,
+            None,
+        ));
+        assert_eq!(super::get_debugger_cmd_timeout(&cmd, &config), 7);
+    }
+
+    #[test]
+    fn check_debugger_cmd_timeout_for_steps() {
+        let config = Config::new();
+        let cmd = DebuggerCmd::V1(DebuggerCmdV1::StepOver(1, false));
+        assert_eq!(super::get_debugger_cmd_timeout(&cmd, &config), 3);
+    }
+
+    #[test]
+    fn check_debugger_cmd_timeout_for_counted_steps() {
+        let config = Config::new();
+        let cmd = DebuggerCmd::V1(DebuggerCmdV1::StepIn(3, false));
+        assert_eq!(super::get_debugger_cmd_timeout(&cmd, &config), 7);
+    }
+
+    #[test]
+    fn check_debugger_cmd_timeout_capped_for_huge_counts() {
+        let config = Config::new();
+        let cmd = DebuggerCmd::V1(DebuggerCmdV1::StepOver(i64::MAX as u64, false));
+        assert_eq!(
+            super::get_debugger_cmd_timeout(&cmd, &config),
+            super::MAX_DEBUGGER_CMD_TIMEOUT
+        );
     }
 }
@@ -3,16 +3,21 @@
 //! Various simple utilities for use in PADRE
 
 use std::env;
-use std::io::{self, BufRead};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
 use std::mem;
 use std::net::TcpListener;
 use std::path::{Path, PathBuf};
-use std::process::{exit, Command, Stdio};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::notifier::{log_msg, LogLevel};
 
 use bytes::Bytes;
+use regex::Regex;
+use serde::Serialize;
 use tokio::io::AsyncRead;
 use tokio::prelude::*;
 use tokio::sync::mpsc::{self, Sender};
@@ -20,6 +25,11 @@ use tokio_process::{Child, ChildStdin, CommandExt};
 
 const BUFSIZE: usize = 4096;
 
+lazy_static! {
+    static ref LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+    static ref RECORD_FILE: Mutex<Option<File>> = Mutex::new(None);
+}
+
 /// Get an unused port on the local system and return it. This port
 /// can subsequently be used.
 pub fn get_unused_localhost_port() -> u16 {
@@ -33,53 +43,264 @@ pub fn send_error_and_debug(err_msg: &str, debug_msg: &str) {
     log_msg(LogLevel::DEBUG, debug_msg);
 }
 
+/// Start appending log entries to the file specified, creating it if necessary
+pub fn open_log_file(path: &str) -> Result<(), io::Error> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Append a timestamped entry to the log file if one has been opened
+///
+/// The lock is held for the whole write so entries from different tasks never interleave.
+pub fn write_log(msg: &str) {
+    let mut log_file = LOG_FILE.lock().unwrap();
+    let file = match log_file.as_mut() {
+        Some(f) => f,
+        None => return,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    match writeln!(file, "{}.{:03} {}", now.as_secs(), now.subsec_millis(), msg) {
+        Ok(_) => {}
+        Err(e) => eprintln!("Can't write to log file: {}", e),
+    }
+}
+
+/// Start appending every request received to the file specified, creating it if necessary
+pub fn open_record_file(path: &str) -> Result<(), io::Error> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *RECORD_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Append a request to the record file as a line of JSON if one has been opened
+pub fn record_request<T: Serialize>(request: &T) {
+    let mut record_file = RECORD_FILE.lock().unwrap();
+    let file = match record_file.as_mut() {
+        Some(f) => f,
+        None => return,
+    };
+
+    let json = match serde_json::to_string(request) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Can't record request: {}", e);
+            return;
+        }
+    };
+
+    match writeln!(file, "{}", json) {
+        Ok(_) => {}
+        Err(e) => eprintln!("Can't write to record file: {}", e),
+    }
+}
+
+/// Split debugger output into lines, whether they end with `\r\n`, `\n` or `\r`
+///
+/// Like splitting on `\n` there's an empty last line when the output ends with a line
+/// ending.
+pub fn split_lines(s: &str) -> Vec<&str> {
+    let bytes = s.as_bytes();
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                lines.push(&s[start..i]);
+                start = i + 1;
+            }
+            b'\r' => {
+                lines.push(&s[start..i]);
+                if bytes.get(i + 1) == Some(&b'\n') {
+                    i += 1;
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    lines.push(&s[start..]);
+
+    lines
+}
+
+/// Read the arguments in a file given with `--args-file`, one per line
+///
+/// Each argument is taken as it is, spaces and quotes included, so there's no need to
+/// escape anything. Fails if the file can't be read or a line is empty, apart from after
+/// the last line ending.
+pub fn read_args_file(path: &str) -> Result<Vec<String>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut lines = split_lines(&contents);
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match line.is_empty() {
+            true => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} is empty, every line must be an argument", i + 1),
+            )),
+            false => Ok(line.to_string()),
+        })
+        .collect()
+}
+
+/// Read the debugger commands in a file given with `--init-commands`, one per line
+///
+/// Blank lines and lines starting with `#` are skipped, everything else is sent to the
+/// debugger as it is.
+pub fn read_init_commands_file(path: &str) -> Result<Vec<String>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+
+    Ok(split_lines(&contents)
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Parse a `K=V` environment variable given with `--debugger-env`
+///
+/// Only the first `=` separates the name from the value so values can contain `=`.
+pub fn parse_env_var(s: &str) -> Result<(String, String), io::Error> {
+    match s.find('=') {
+        Some(i) if i > 0 => Ok((s[..i].to_string(), s[i + 1..].to_string())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Can't understand environment variable {}, expected K=V", s),
+        )),
+    }
+}
+
+/// Split the run command given into arguments for the debugger and the program to run with
+/// its arguments
+///
+/// If the run command starts with the debugger itself, e.g. `lldb -s cmds -- ./prog a`,
+/// everything before the first `--` is for the debugger and everything after is the
+/// program. Otherwise it's all the program, e.g. `./prog -- -a` runs `./prog -a`. Either way
+/// only the first `--` is taken as the separator, any later ones are passed on.
+pub fn split_run_cmd<'a>(
+    debugger_cmd: &str,
+    run_cmd: Vec<&'a str>,
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let debugger_name = Path::new(debugger_cmd)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(debugger_cmd);
+
+    let is_debugger = match run_cmd.first() {
+        Some(cmd) => *cmd == debugger_cmd || *cmd == debugger_name,
+        None => false,
+    };
+
+    let run_cmd = match is_debugger {
+        true => &run_cmd[1..],
+        false => &run_cmd[..],
+    };
+
+    let (before, after) = match run_cmd.iter().position(|arg| *arg == "--") {
+        Some(i) => (run_cmd[..i].to_vec(), run_cmd[i + 1..].to_vec()),
+        None => (run_cmd.to_vec(), vec![]),
+    };
+
+    match is_debugger {
+        true => (before, after),
+        false => (vec![], before.into_iter().chain(after).collect()),
+    }
+}
+
 /// Check whether the specified debugger and program to debug exist, including change them to
-/// be the full path name if required. If it still can't find both it will panic, otherwise it
-/// will start a Child process for running the program.
-pub fn check_and_spawn_process(mut debugger_cmd: Vec<String>, run_cmd: Vec<String>) -> Child {
+/// be the full path name if required. If it still can't find both it will return an error,
+/// otherwise it will start a Child process for running the program.
+///
+/// The run command is split into debugger and program arguments by `split_run_cmd`.
+/// When `cwd` is given the process is run in that directory, the program to run is made
+/// absolute first so that it's still found from there.
+pub fn check_and_spawn_process(
+    mut debugger_cmd: Vec<String>,
+    run_cmd: Vec<String>,
+    cwd: Option<&str>,
+    env: &[(String, String)],
+) -> Result<Child, io::Error> {
     let mut not_found = None;
 
+    let (debugger_args, run_cmd) =
+        split_run_cmd(&debugger_cmd[0], run_cmd.iter().map(|x| &x[..]).collect());
+
     // Try getting the full path if the debugger doesn't exist
     if !file_exists(&debugger_cmd[0]) {
         debugger_cmd[0] = get_file_full_path(&debugger_cmd[0]);
     }
 
     // Now check the debugger and program to debug exist, if not error
-    if !file_exists(&run_cmd[0]) {
-        not_found = Some(&run_cmd[0]);
-    };
+    if let Some(cmd) = run_cmd.first() {
+        if !file_exists(cmd) {
+            not_found = Some(cmd.to_string());
+        }
+    }
 
     if !file_exists(&debugger_cmd[0]) {
-        not_found = Some(&debugger_cmd[0]);
+        not_found = Some(debugger_cmd[0].clone());
     }
 
     if let Some(s) = not_found {
-        let msg = format!("Can't spawn debugger as {} does not exist", s);
-        log_msg(LogLevel::CRITICAL, &msg);
-        println!("{}", msg);
-
-        exit(1);
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Can't spawn debugger as {} does not exist", s),
+        ));
     }
 
     let mut args = vec![];
 
     for arg in &debugger_cmd[1..] {
-        args.push(&arg[..]);
+        args.push(arg.clone());
     }
 
-    args.push("--");
+    args.extend(debugger_args.iter().map(|x| x.to_string()));
+
+    if !run_cmd.is_empty() {
+        args.push("--".to_string());
+        args.extend(run_cmd.iter().map(|x| x.to_string()));
 
-    for arg in &run_cmd {
-        args.push(&arg[..]);
+        if cwd.is_some() {
+            let program = args.len() - run_cmd.len();
+            args[program] = absolute_path(&args[program]);
+        }
     }
 
-    Command::new(&debugger_cmd[0])
+    let mut command = Command::new(&debugger_cmd[0]);
+
+    command
         .args(&args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn_async()
-        .expect("Failed to spawn debugger")
+        .envs(env.iter().cloned());
+
+    if let Some(dir) = cwd {
+        command.current_dir(dir);
+    }
+
+    command.spawn_async().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Can't spawn debugger {}: {}", debugger_cmd[0], e),
+        )
+    })
 }
 
 /// Perform setup of listening and forwarding of stdin and return a sender that will forward to the
@@ -186,6 +407,109 @@ pub fn file_exists(path: &str) -> bool {
     }
 }
 
+/// Expand a leading `~` and make the path absolute with symlinks resolved so that debuggers
+/// can find the file wherever they're run from
+///
+/// Falls back to the path given with a warning when it can't be resolved, for example when
+/// the file doesn't exist.
+pub fn canonicalize_file_path(path: &str) -> String {
+    let expanded = match (path, env::var("HOME")) {
+        ("~", Ok(home)) => PathBuf::from(home),
+        (p, Ok(home)) if p.starts_with("~/") => Path::new(&home).join(&p[2..]),
+        (p, _) => PathBuf::from(p),
+    };
+
+    match expanded.canonicalize() {
+        Ok(p) => p.to_string_lossy().to_string(),
+        Err(e) => {
+            log_msg(
+                LogLevel::WARN,
+                &format!("Can't find full path of file {}: {}", path, e),
+            );
+            path.to_string()
+        }
+    }
+}
+
+/// Make a relative path to a file that exists absolute so the file can still be found from
+/// another directory, anything else is left as it is
+pub fn absolute_path(path: &str) -> String {
+    if Path::new(path).is_absolute() || !file_exists(path) {
+        return path.to_string();
+    }
+
+    match Path::new(path).canonicalize() {
+        Ok(p) => p.to_string_lossy().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Turn a value printed by a debugger as text into a JSON number when it's obviously a
+/// number, anything else is left as a string
+///
+/// Only plain decimal numbers are converted, not hex, `inf` or the like, and integers too
+/// big to be represented exactly are left as strings.
+pub fn typed_value(value: &str) -> serde_json::Value {
+    lazy_static! {
+        static ref RE_NUMBER: Regex = Regex::new("^-?\\d+(\\.\\d+)?([eE][-+]?\\d+)?$").unwrap();
+    }
+
+    let cap = match RE_NUMBER.captures(value) {
+        Some(cap) => cap,
+        None => return serde_json::json!(value),
+    };
+
+    if cap.get(1).is_none() && cap.get(2).is_none() {
+        if let Ok(n) = value.parse::<i64>() {
+            return serde_json::json!(n);
+        }
+        if let Ok(n) = value.parse::<u64>() {
+            return serde_json::json!(n);
+        }
+    } else if let Ok(f) = value.parse::<f64>() {
+        if f.is_finite() {
+            return serde_json::json!(f);
+        }
+    }
+
+    serde_json::json!(value)
+}
+
+/// Cut a printed value down to at most `max_bytes` bytes, marking that it's been cut
+///
+/// The value is cut at a character boundary so can be slightly shorter, 0 means it's never
+/// cut.
+pub fn truncate_value(value: &str, max_bytes: usize) -> String {
+    if max_bytes == 0 || value.len() <= max_bytes {
+        return value.to_string();
+    }
+
+    let mut end = max_bytes;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}… (truncated)", &value[..end])
+}
+
+/// Cut a value given as JSON, e.g. by Node, down to at most `max_bytes` bytes
+///
+/// Strings are cut as with `truncate_value`, arrays and objects that are too big become
+/// their JSON text cut down. Anything else is left as it is.
+pub fn truncate_json_value(value: serde_json::Value, max_bytes: usize) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => serde_json::json!(truncate_value(&s, max_bytes)),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) if max_bytes > 0 => {
+            let text = value.to_string();
+            match text.len() > max_bytes {
+                true => serde_json::json!(truncate_value(&text, max_bytes)),
+                false => value,
+            }
+        }
+        _ => value,
+    }
+}
+
 /// Get the file type as output by the UNIX `file` command.
 fn get_file_type(cmd: &str) -> String {
     let output = Command::new("file")
@@ -204,6 +528,8 @@ fn get_file_type(cmd: &str) -> String {
 pub struct ReadOutput<A> {
     io: A,
     text: String,
+    // The start of a character that's been cut off at the end of a read
+    partial: Vec<u8>,
 }
 
 /// Creates a new stream from the I/O object
@@ -217,9 +543,25 @@ where
     ReadOutput {
         io: a,
         text: String::new(),
+        partial: vec![],
     }
 }
 
+/// Decode the bytes as UTF-8, leaving any character cut off at the end in `bytes` to be
+/// completed by the next read
+///
+/// Anything that's not valid UTF-8 is replaced rather than failing.
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(bytes) {
+        Err(ref e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => bytes.len(),
+    };
+    let rest = bytes.split_off(complete);
+    let text = String::from_utf8_lossy(bytes).to_string();
+    *bytes = rest;
+    text
+}
+
 impl<A> Stream for ReadOutput<A>
 where
     A: AsyncRead + BufRead,
@@ -238,18 +580,24 @@ where
                 Err(e) => return Err(e.into()),
             };
 
-            if n == 0 && self.text.len() == 0 {
-                return Ok(None.into());
+            if n == 0 {
+                // Nothing will complete a character that's been cut off now
+                let partial = mem::take(&mut self.partial);
+                self.text.push_str(&String::from_utf8_lossy(&partial));
+                if self.text.is_empty() {
+                    return Ok(None.into());
+                }
+                break;
             }
 
-            if n == BUFSIZE {
-                let bufstr = String::from_utf8_lossy(&buf[0..n]);
-                self.text.push_str(&bufstr);
+            self.partial.extend_from_slice(&buf[0..n]);
+            let bufstr = take_utf8(&mut self.partial);
+            self.text.push_str(&bufstr);
+
+            if n == BUFSIZE || self.text.is_empty() {
                 continue;
             }
 
-            let bufstr = String::from_utf8_lossy(&buf[0..n]);
-            self.text.push_str(&bufstr);
             break;
         }
         Ok(Some(mem::replace(&mut self.text, String::new())).into())
@@ -262,6 +610,188 @@ mod tests {
     use std::thread;
     use std::time::Duration;
 
+    #[test]
+    fn check_log_file_entries_appended() {
+        let path = std::env::temp_dir().join(format!("padre_test_{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        super::open_log_file(path).unwrap();
+        super::write_log("first entry");
+        super::write_log("second entry");
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let first = contents.find(" first entry\n").unwrap();
+        let second = contents.find(" second entry\n").unwrap();
+        assert!(first < second);
+    }
+
+    #[test]
+    fn check_read_args_file() {
+        let path = std::env::temp_dir().join(format!("padre_test_{}.args", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        std::fs::write(path, "--verbose\n--name=my name\n\"quoted\"\n").unwrap();
+        let args = super::read_args_file(path);
+
+        std::fs::write(path, "-a\n\n-b").unwrap();
+        let err = super::read_args_file(path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            args.unwrap(),
+            vec!["--verbose", "--name=my name", "\"quoted\""]
+        );
+        assert_eq!(
+            err.to_string(),
+            "line 2 is empty, every line must be an argument"
+        );
+        assert_eq!(
+            super::read_args_file(path).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn check_read_init_commands_file() {
+        let path = std::env::temp_dir().join(format!("padre_test_{}.init", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        std::fs::write(
+            path,
+            "# My settings\ntype summary add --summary-string \"${var.x}\" Point\n\n  command alias bfl breakpoint set -f %1 -l %2  \n",
+        )
+        .unwrap();
+        let cmds = super::read_init_commands_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            cmds.unwrap(),
+            vec![
+                "type summary add --summary-string \"${var.x}\" Point",
+                "command alias bfl breakpoint set -f %1 -l %2"
+            ]
+        );
+        assert_eq!(
+            super::read_init_commands_file(path).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn check_utf8_cut_off_between_reads() {
+        let mut bytes = "ab✓".as_bytes()[..3].to_vec();
+        assert_eq!(super::take_utf8(&mut bytes), "ab");
+        assert_eq!(bytes, vec![0xe2]);
+
+        bytes.extend_from_slice(&"✓c".as_bytes()[1..]);
+        assert_eq!(super::take_utf8(&mut bytes), "✓c");
+        assert!(bytes.is_empty());
+
+        let mut bytes = vec![b'a', 0xff, b'b'];
+        assert_eq!(super::take_utf8(&mut bytes), "a\u{fffd}b");
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn check_split_lines() {
+        assert_eq!(super::split_lines("a\nb\n"), vec!["a", "b", ""]);
+        assert_eq!(super::split_lines("a\r\nb\r\n"), vec!["a", "b", ""]);
+        assert_eq!(super::split_lines("a\rb"), vec!["a", "b"]);
+        assert_eq!(super::split_lines("no line ending"), vec!["no line ending"]);
+    }
+
+    #[test]
+    fn check_split_lines_mixed_line_endings() {
+        assert_eq!(
+            super::split_lines("a\r\nb\nc\rd\n\r\ne"),
+            vec!["a", "b", "c", "d", "", "e"]
+        );
+    }
+
+    #[test]
+    fn check_split_run_cmd_basic_command() {
+        let (debugger_args, run_cmd) = super::split_run_cmd("/usr/bin/lldb", vec!["prog", "arg1"]);
+        assert_eq!(debugger_args, Vec::<&str>::new());
+        assert_eq!(run_cmd, vec!["prog", "arg1"]);
+    }
+
+    #[test]
+    fn check_split_run_cmd_removes_separator() {
+        let (debugger_args, run_cmd) =
+            super::split_run_cmd("/usr/bin/lldb", vec!["prog", "--", "--flag", "--"]);
+        assert_eq!(debugger_args, Vec::<&str>::new());
+        assert_eq!(run_cmd, vec!["prog", "--flag", "--"]);
+    }
+
+    #[test]
+    fn check_split_run_cmd_recognises_matching_debugger_command() {
+        let (debugger_args, run_cmd) = super::split_run_cmd(
+            "/usr/bin/lldb",
+            vec!["lldb", "-s", "cmds", "--", "prog", "--flag"],
+        );
+        assert_eq!(debugger_args, vec!["-s", "cmds"]);
+        assert_eq!(run_cmd, vec!["prog", "--flag"]);
+    }
+
+    #[test]
+    fn check_split_run_cmd_leaves_non_matching_debugger_command() {
+        let (debugger_args, run_cmd) =
+            super::split_run_cmd("/usr/bin/lldb-8", vec!["lldb", "prog", "arg1"]);
+        assert_eq!(debugger_args, Vec::<&str>::new());
+        assert_eq!(run_cmd, vec!["lldb", "prog", "arg1"]);
+    }
+
+    #[test]
+    fn check_split_run_cmd_debugger_arguments_only() {
+        let (debugger_args, run_cmd) = super::split_run_cmd("node", vec!["node", "-e", "1"]);
+        assert_eq!(debugger_args, vec!["-e", "1"]);
+        assert_eq!(run_cmd, Vec::<&str>::new());
+    }
+
+    #[test]
+    fn check_typed_values() {
+        assert_eq!(super::typed_value("12"), serde_json::json!(12));
+        assert_eq!(super::typed_value("-3"), serde_json::json!(-3));
+        assert_eq!(super::typed_value("1.5"), serde_json::json!(1.5));
+        assert_eq!(super::typed_value("2e3"), serde_json::json!(2000.0));
+        assert_eq!(
+            super::typed_value("18446744073709551615"),
+            serde_json::json!(18446744073709551615u64)
+        );
+        assert_eq!(
+            super::typed_value("123456789012345678901234567890"),
+            serde_json::json!("123456789012345678901234567890")
+        );
+        assert_eq!(super::typed_value("0x10"), serde_json::json!("0x10"));
+        assert_eq!(super::typed_value("'a'"), serde_json::json!("'a'"));
+        assert_eq!(super::typed_value("inf"), serde_json::json!("inf"));
+        assert_eq!(super::typed_value(" 1"), serde_json::json!(" 1"));
+    }
+
+    #[test]
+    fn check_canonicalize_file_path() {
+        let home = std::env::var("HOME").unwrap();
+        let home = std::fs::canonicalize(home).unwrap();
+        assert_eq!(
+            super::canonicalize_file_path("~"),
+            home.to_string_lossy().to_string()
+        );
+
+        let expected = std::env::current_dir().unwrap().join("src/util.rs");
+        let expected = expected.canonicalize().unwrap();
+        assert_eq!(
+            super::canonicalize_file_path("src/../src/util.rs"),
+            expected.to_string_lossy().to_string()
+        );
+
+        assert_eq!(
+            super::canonicalize_file_path("~/padre_no_such_file.c"),
+            "~/padre_no_such_file.c"
+        );
+    }
+
     #[test]
     fn find_and_use_unused_port() {
         let port = super::get_unused_localhost_port();
@@ -285,6 +815,37 @@ mod tests {
         assert_eq!(true, super::file_is_text("./test_files/test_node.js"));
     }
 
+    #[test]
+    fn check_truncate_value() {
+        assert_eq!(super::truncate_value("hello", 10), "hello");
+        assert_eq!(super::truncate_value("hello", 5), "hello");
+        assert_eq!(super::truncate_value("hello", 3), "hel… (truncated)");
+        assert_eq!(super::truncate_value("hello", 0), "hello");
+        // Never cut in the middle of a character
+        assert_eq!(super::truncate_value("aé", 2), "a… (truncated)");
+
+        assert_eq!(
+            super::truncate_json_value(serde_json::json!("abcdef"), 2),
+            serde_json::json!("ab… (truncated)")
+        );
+        assert_eq!(
+            super::truncate_json_value(serde_json::json!([1, 2, 3]), 4),
+            serde_json::json!("[1,2… (truncated)")
+        );
+        assert_eq!(
+            super::truncate_json_value(serde_json::json!({"a": 1}), 20),
+            serde_json::json!({"a": 1})
+        );
+        assert_eq!(
+            super::truncate_json_value(serde_json::json!([1, 2, 3]), 0),
+            serde_json::json!([1, 2, 3])
+        );
+        assert_eq!(
+            super::truncate_json_value(serde_json::json!(123456), 2),
+            serde_json::json!(123456)
+        );
+    }
+
     #[test]
     fn test_file_exists() {
         assert_eq!(true, super::file_exists("./test_files/node"));
@@ -295,6 +856,70 @@ mod tests {
         assert_eq!(false, super::file_exists("./test_files/not_exists"));
     }
 
+    #[test]
+    fn test_spawn_process_with_bogus_debugger() {
+        let process = super::check_and_spawn_process(
+            vec!["bogus_debugger_that_doesnt_exist".to_string()],
+            vec!["./test_files/node".to_string()],
+            None,
+            &[],
+        );
+        let err = process.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(
+            err.to_string(),
+            "Can't spawn debugger as bogus_debugger_that_doesnt_exist does not exist"
+        );
+    }
+
+    #[test]
+    fn test_spawn_process_with_env() {
+        use std::sync::{Arc, Mutex};
+        use tokio::prelude::*;
+
+        let output = Arc::new(Mutex::new(String::new()));
+        let output_set = output.clone();
+
+        tokio::run(future::lazy(move || {
+            super::check_and_spawn_process(
+                vec!["env".to_string()],
+                vec![],
+                None,
+                &[("PADRE_TEST_ENV".to_string(), "a=b".to_string())],
+            )
+            .unwrap()
+            .wait_with_output()
+            .map(move |out| {
+                *output_set.lock().unwrap() = String::from_utf8_lossy(&out.stdout).to_string();
+            })
+            .map_err(|e| panic!("Can't run env: {}", e))
+        }));
+
+        assert!(output.lock().unwrap().contains("PADRE_TEST_ENV=a=b\n"));
+    }
+
+    #[test]
+    fn check_parse_env_var() {
+        assert_eq!(
+            super::parse_env_var("PYTHONPATH=/tmp/lib").unwrap(),
+            ("PYTHONPATH".to_string(), "/tmp/lib".to_string())
+        );
+        assert_eq!(
+            super::parse_env_var("OPTS=a=b").unwrap(),
+            ("OPTS".to_string(), "a=b".to_string())
+        );
+        assert_eq!(
+            super::parse_env_var("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), "".to_string())
+        );
+        for bad in ["NOVALUE", "=value", ""] {
+            assert_eq!(
+                super::parse_env_var(bad).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidInput
+            );
+        }
+    }
+
     #[test]
     fn test_getting_files_full_path_when_not_exists() {
         assert_eq!(
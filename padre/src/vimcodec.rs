@@ -2,14 +2,19 @@
 //!
 //! Rust Tokio Codec for communicating with VIM
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::io;
 
-use crate::debugger::{DebuggerCmd, DebuggerCmdV1, FileLocation, Variable};
+use crate::debugger::{
+    DebuggerCmd, DebuggerCmdV1, FileLocation, HitCondition, ValueFormat, Variable, WatchpointMode,
+};
 use crate::server::{PadreCmd, PadreRequest, PadreSend, RequestCmd};
 use crate::util;
 
 use bytes::{BufMut, BytesMut};
+use regex::Regex;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
 use tokio::codec::{Decoder, Encoder};
 
 /// Decodes requests and encodes responses sent by or to VIM over VIM's socket communication
@@ -20,20 +25,94 @@ use tokio::codec::{Decoder, Encoder};
 /// ```
 /// it decodes this into a PadreRequest with an `id` of `1` and a RequestCmd of `Breakpoint`
 /// with the correct file location.
+///
+/// A `batch` request of the form
+/// ```
+/// [1,{"cmd":"batch","requests":[[2,{"cmd":"breakpoint","file":"test.c","line":1}],...]}]
+/// ```
+/// is expanded into each of the requests it contains, which are then returned one at a
+/// time in order by successive calls to `decode`. A batch inside a batch is expanded in its
+/// place.
+///
+/// Any request can have a `meta` argument of any JSON, it's echoed back untouched in the
+/// response so that a client can tag requests for its own bookkeeping. For a `batch` it's
+/// given with each of the requests in it.
+///
+/// A request that isn't valid JSON, or has the same key twice in an object, is decoded as
+/// a `BadRequest` so that it's answered with an error. The id is read from the start of the
+/// request if it can be, e.g. `42` for `[42,{bad}]`, otherwise the error is sent with an id
+/// of `0` meaning the id couldn't be determined. So is a request with an argument that's the
+/// wrong type or out of range, e.g. a `line` of `0`, naming the argument and what's expected.
+///
+/// A request with a command that isn't one of `COMMANDS` is decoded as `Unknown` along with
+/// its arguments, it's up to the server to answer it.
 #[derive(Debug)]
-pub struct VimCodec {}
+pub struct VimCodec {
+    pending: VecDeque<PadreRequest>,
+    bad_request: Option<String>,
+}
+
+/// Every command that's decoded into a request, anything else is `Unknown`
+pub const COMMANDS: &[&str] = &[
+    "ping",
+    "pings",
+    "echo",
+    "capabilities",
+    "getPid",
+    "status",
+    "shutdown",
+    "cancel",
+    "auth",
+    "run",
+    "stepOver",
+    "finish",
+    "reverseContinue",
+    "reverseStepOver",
+    "stepIn",
+    "continue",
+    "interrupt",
+    "detach",
+    "breakpoint",
+    "disableBreakpoint",
+    "enableBreakpoint",
+    "validateBreakpoint",
+    "setNextStatement",
+    "print",
+    "setCwd",
+    "getChildren",
+    "frameSource",
+    "stackWithLocals",
+    "listSources",
+    "listModules",
+    "assert",
+    "addWatch",
+    "typeOf",
+    "readSymbol",
+    "removeWatch",
+    "watch",
+    "exceptionBreakpoint",
+    "clearAllBreakpoints",
+    "currentLocation",
+    "threads",
+    "selectThread",
+    "raw",
+    "batch",
+    "getConfig",
+    "setConfig",
+];
 
 impl VimCodec {
     /// Constructor for creating a new VimCodec
-    ///
-    /// Just creates the object at present.
     pub fn new() -> Self {
-        VimCodec {}
+        VimCodec {
+            pending: VecDeque::new(),
+            bad_request: None,
+        }
     }
 
-    /// Get and remove a `file location` from the arguments
+    /// Get and remove a `file location` from the arguments, the file is made an absolute path
     fn get_file_location(
-        &self,
+        &mut self,
         args: &mut HashMap<String, serde_json::Value>,
     ) -> Option<FileLocation> {
         match args.remove("file") {
@@ -41,24 +120,21 @@ impl VimCodec {
                 serde_json::Value::String(s) => match args.remove("line") {
                     Some(t) => match t {
                         serde_json::Value::Number(t) => {
+                            // Lines are numbered from 1, a line of 0 makes no sense
                             let t: u64 = match t.as_u64() {
-                                Some(t) => t,
-                                None => {
-                                    util::send_error_and_debug(
-                                        &format!("Badly specified 'line'"),
-                                        &format!("Badly specified 'line': {}", t),
+                                Some(t) if t > 0 => t,
+                                _ => {
+                                    self.bad_argument(
+                                        "line",
+                                        "a positive integer",
+                                        &serde_json::Value::Number(t.clone()),
                                     );
                                     return None;
                                 }
                             };
-                            return Some(FileLocation::new(s, t));
-                        }
-                        _ => {
-                            util::send_error_and_debug(
-                                "Can't read 'line' argument",
-                                &format!("Can't understand 'line': {}", t),
-                            );
+                            return Some(FileLocation::new(util::canonicalize_file_path(&s), t));
                         }
+                        _ => self.bad_argument("line", "a positive integer", &t),
                     },
                     None => {
                         util::send_error_and_debug(
@@ -67,12 +143,7 @@ impl VimCodec {
                         );
                     }
                 },
-                _ => {
-                    util::send_error_and_debug(
-                        &format!("Can't read 'file' argument"),
-                        &format!("Can't understand 'file': {}", s),
-                    );
-                }
+                _ => self.bad_argument("file", "a string", &s),
             },
             None => {
                 util::send_error_and_debug(
@@ -86,15 +157,12 @@ impl VimCodec {
     }
 
     /// Get and remove a `variable` from the arguments passed
-    fn get_variable(&self, args: &mut HashMap<String, serde_json::Value>) -> Option<Variable> {
+    fn get_variable(&mut self, args: &mut HashMap<String, serde_json::Value>) -> Option<Variable> {
         match args.remove("variable") {
             Some(s) => match s {
                 serde_json::Value::String(s) => Some(Variable::new(s)),
                 _ => {
-                    util::send_error_and_debug(
-                        "Badly specified 'variable'",
-                        &format!("Badly specified 'variable': {}", s),
-                    );
+                    self.bad_argument("variable", "a string", &s);
                     None
                 }
             },
@@ -110,7 +178,7 @@ impl VimCodec {
 
     /// Get and remove the key specified from the arguments as a String
     fn get_string(
-        &self,
+        &mut self,
         key: &str,
         args: &mut HashMap<String, serde_json::Value>,
     ) -> Option<String> {
@@ -118,10 +186,7 @@ impl VimCodec {
             Some(s) => match s {
                 serde_json::Value::String(s) => Some(s),
                 _ => {
-                    util::send_error_and_debug(
-                        &format!("Badly specified string '{}'", key),
-                        &format!("Badly specified string '{}': {}", key, s),
-                    );
+                    self.bad_argument(key, "a string", &s);
                     None
                 }
             },
@@ -135,25 +200,120 @@ impl VimCodec {
         }
     }
 
+    /// Get and remove an optional `count` from the arguments, defaulting to 1 if it's
+    /// not specified and must be positive if it is
+    fn get_count(&mut self, args: &mut HashMap<String, serde_json::Value>) -> Option<u64> {
+        if !args.contains_key("count") {
+            return Some(1);
+        }
+
+        match self.get_i64("count", args) {
+            Some(c) if c > 0 => Some(c as u64),
+            Some(c) => {
+                self.bad_argument("count", "a positive integer", &c.into());
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Get and remove an optional `maxFrames` from the arguments, defaulting to 20 if it's
+    /// not specified and must be positive if it is
+    fn get_max_frames(&mut self, args: &mut HashMap<String, serde_json::Value>) -> Option<u64> {
+        if !args.contains_key("maxFrames") {
+            return Some(20);
+        }
+
+        match self.get_i64("maxFrames", args) {
+            Some(n) if n > 0 => Some(n as u64),
+            Some(n) => {
+                self.bad_argument("maxFrames", "a positive integer", &n.into());
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Get and remove an optional `frame` index from the arguments, `None` if it's not
+    /// specified and it can't be negative
+    fn get_frame(&mut self, args: &mut HashMap<String, serde_json::Value>) -> Option<Option<u64>> {
+        if !args.contains_key("frame") {
+            return Some(None);
+        }
+
+        match self.get_i64("frame", args) {
+            Some(f) if f >= 0 => Some(Some(f as u64)),
+            Some(f) => {
+                self.bad_argument("frame", "a non-negative integer", &f.into());
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Get and remove an optional number of lines from the arguments, defaulting to 5 if
+    /// it's not specified and it can't be negative
+    fn get_line_count(
+        &mut self,
+        key: &str,
+        args: &mut HashMap<String, serde_json::Value>,
+    ) -> Option<u64> {
+        if !args.contains_key(key) {
+            return Some(5);
+        }
+
+        match self.get_i64(key, args) {
+            Some(n) if n >= 0 => Some(n as u64),
+            Some(n) => {
+                self.bad_argument(key, "a non-negative integer", &n.into());
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Get and remove an optional boolean from the arguments, defaulting to false if it's
+    /// not specified
+    fn get_optional_bool(
+        &mut self,
+        key: &str,
+        args: &mut HashMap<String, serde_json::Value>,
+    ) -> Option<bool> {
+        match args.remove(key) {
+            None => Some(false),
+            Some(serde_json::Value::Bool(b)) => Some(b),
+            Some(v) => {
+                self.bad_argument(key, "true or false", &v);
+                None
+            }
+        }
+    }
+
+    /// Get and remove the key specified from the arguments as any JSON value
+    fn get_json(
+        &mut self,
+        key: &str,
+        args: &mut HashMap<String, serde_json::Value>,
+    ) -> Option<serde_json::Value> {
+        match args.remove(key) {
+            Some(v) => Some(v),
+            None => {
+                util::send_error_and_debug(
+                    "Can't understand request",
+                    &format!("Need to specify a '{}'", key),
+                );
+                None
+            }
+        }
+    }
+
     /// Get and remove the key specified from the arguments as an i64
-    fn get_i64(&self, key: &str, args: &mut HashMap<String, serde_json::Value>) -> Option<i64> {
+    fn get_i64(&mut self, key: &str, args: &mut HashMap<String, serde_json::Value>) -> Option<i64> {
         match args.remove(key) {
-            Some(k) => match k.clone() {
-                serde_json::Value::Number(n) => match n.as_i64() {
-                    Some(i) => Some(i),
-                    None => {
-                        util::send_error_and_debug(
-                            &format!("Badly specified 64-bit integer '{}'", key),
-                            &format!("Badly specified 64-bit integer '{}': {}", key, &k),
-                        );
-                        None
-                    }
-                },
-                _ => {
-                    util::send_error_and_debug(
-                        &format!("Badly specified 64-bit integer '{}'", key),
-                        &format!("Badly specified 64-bit integer '{}': {}", key, &k),
-                    );
+            Some(k) => match k.as_i64() {
+                Some(i) => Some(i),
+                None => {
+                    self.bad_argument(key, "a 64-bit integer", &k);
                     None
                 }
             },
@@ -166,6 +326,15 @@ impl VimCodec {
             }
         }
     }
+
+    /// Report an argument that's the wrong type or out of range, naming what was expected
+    ///
+    /// The request is then answered with the same error as a `BadRequest`.
+    fn bad_argument(&mut self, key: &str, expected: &str, value: &serde_json::Value) {
+        let msg = format!("Badly specified '{}', expected {}", key, expected);
+        util::send_error_and_debug(&msg, &format!("{}: {}", msg, value));
+        self.bad_request = Some(msg);
+    }
 }
 
 impl Decoder for VimCodec {
@@ -173,6 +342,10 @@ impl Decoder for VimCodec {
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(request) = self.pending.pop_front() {
+            return Ok(Some(request));
+        }
+
         if src.len() == 0 {
             return Ok(None);
         }
@@ -180,7 +353,7 @@ impl Decoder for VimCodec {
         let mut stream = serde_json::Deserializer::from_slice(src).into_iter::<serde_json::Value>();
         let req = &src.clone()[..];
 
-        let mut v = match stream.next() {
+        let v = match stream.next() {
             Some(s) => match s {
                 Ok(t) => t,
                 Err(e) => {
@@ -208,7 +381,13 @@ impl Decoder for VimCodec {
                         ),
                     );
 
-                    return Ok(None);
+                    return Ok(Some(PadreRequest::new(
+                        leading_id(req),
+                        RequestCmd::PadreCmd(PadreCmd::BadRequest(format!(
+                            "Must be valid JSON: {}",
+                            e
+                        ))),
+                    )));
                 }
             },
             None => {
@@ -219,6 +398,121 @@ impl Decoder for VimCodec {
 
         src.split_to(src.len());
 
+        if let Some(e) = duplicate_key_error(req) {
+            util::send_error_and_debug(
+                "Duplicate keys",
+                &format!(
+                    "Can't read '{}': {}",
+                    String::from_utf8_lossy(req).trim_matches(char::from(0)),
+                    e
+                ),
+            );
+
+            return Ok(Some(PadreRequest::new(
+                leading_id(req),
+                RequestCmd::PadreCmd(PadreCmd::BadRequest(format!("Duplicate keys: {}", e))),
+            )));
+        }
+
+        // A batch only queues its requests to be returned in order
+        match self.decode_request(v, req)? {
+            Some(request) => Ok(Some(request)),
+            None => Ok(self.pending.pop_front()),
+        }
+    }
+}
+
+/// Deserializes any JSON, failing if an object anywhere in it has the same key twice
+///
+/// Parsing straight to a `serde_json::Value` would silently keep the last value.
+struct NoDuplicateKeys;
+
+impl<'de> Deserialize<'de> for NoDuplicateKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NoDuplicateKeysVisitor)
+    }
+}
+
+struct NoDuplicateKeysVisitor;
+
+impl<'de> Visitor<'de> for NoDuplicateKeysVisitor {
+    type Value = NoDuplicateKeys;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<NoDuplicateKeys, E> {
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<NoDuplicateKeys, E> {
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<NoDuplicateKeys, E> {
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<NoDuplicateKeys, E> {
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<NoDuplicateKeys, E> {
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_unit<E>(self) -> Result<NoDuplicateKeys, E> {
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<NoDuplicateKeys, A::Error> {
+        while let Some(NoDuplicateKeys) = seq.next_element()? {}
+        Ok(NoDuplicateKeys)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<NoDuplicateKeys, A::Error> {
+        let mut keys = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if !keys.insert(key.clone()) {
+                return Err(de::Error::custom(format!("duplicate key '{}'", key)));
+            }
+            map.next_value::<NoDuplicateKeys>()?;
+        }
+        Ok(NoDuplicateKeys)
+    }
+}
+
+/// The first key found twice in an object anywhere in the request
+fn duplicate_key_error(req: &[u8]) -> Option<String> {
+    match serde_json::Deserializer::from_slice(req)
+        .into_iter::<NoDuplicateKeys>()
+        .next()
+    {
+        Some(Err(e)) => Some(e.to_string()),
+        _ => None,
+    }
+}
+
+/// Read the id from the start of a request that can't be parsed, 0 if it can't be found
+fn leading_id(req: &[u8]) -> u64 {
+    lazy_static! {
+        static ref RE_LEADING_ID: Regex = Regex::new("^\\s*\\[\\s*(\\d+)\\s*,").unwrap();
+    }
+
+    match RE_LEADING_ID.captures(&String::from_utf8_lossy(req)) {
+        Some(cap) => cap[1].parse::<u64>().unwrap_or(0),
+        None => 0,
+    }
+}
+
+impl VimCodec {
+    /// Decode a single `[id,{"cmd":...}]` request
+    fn decode_request(
+        &mut self,
+        mut v: serde_json::Value,
+        req: &[u8],
+    ) -> Result<Option<PadreRequest>, io::Error> {
         if !v.is_array() {
             util::send_error_and_debug(
                 "Can't read JSON",
@@ -294,7 +588,53 @@ impl Decoder for VimCodec {
             }
         };
 
-        let ret = match &cmd[..] {
+        let meta = args.remove("meta");
+
+        let ret = match self.decode_cmd(id, &cmd, &mut args, req) {
+            Ok(None) => {
+                let bad_request = self.bad_request.take().map(|msg| {
+                    PadreRequest::new(id, RequestCmd::PadreCmd(PadreCmd::BadRequest(msg)))
+                });
+                return Ok(match meta {
+                    Some(meta) => bad_request.map(|req| req.with_meta(meta)),
+                    None => bad_request,
+                });
+            }
+            ret => ret,
+        };
+
+        match args.is_empty() {
+            true => {}
+            false => {
+                let mut args_left: Vec<String> = args.iter().map(|(key, _)| key.clone()).collect();
+                args_left.sort();
+                util::send_error_and_debug(
+                    "Bad arguments",
+                    &format!("Bad arguments: {:?}", args_left),
+                );
+                return Ok(None);
+            }
+        };
+
+        // Each request in a batch is answered separately with its own `meta`
+        match meta {
+            Some(meta) if cmd != "batch" => ret.map(|req| req.map(|req| req.with_meta(meta))),
+            _ => ret,
+        }
+    }
+
+    /// Decode the command of a request from its arguments, taking out those it uses
+    ///
+    /// `None` is returned for a request that can't be answered, with `bad_request` set when
+    /// it should be answered with an error.
+    fn decode_cmd(
+        &mut self,
+        id: u64,
+        cmd: &str,
+        args: &mut HashMap<String, serde_json::Value>,
+        req: &[u8],
+    ) -> Result<Option<PadreRequest>, io::Error> {
+        match cmd {
             "ping" => Ok(Some(PadreRequest::new(
                 id,
                 RequestCmd::PadreCmd(PadreCmd::Ping),
@@ -303,91 +643,466 @@ impl Decoder for VimCodec {
                 id,
                 RequestCmd::PadreCmd(PadreCmd::Pings),
             ))),
+            "echo" => {
+                let payload = self.get_json("payload", args);
+                match payload {
+                    Some(p) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::PadreCmd(PadreCmd::Echo(p)),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "capabilities" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::PadreCmd(PadreCmd::Capabilities),
+            ))),
+            "getPid" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::PadreCmd(PadreCmd::GetPid),
+            ))),
+            "status" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::PadreCmd(PadreCmd::Status),
+            ))),
+            "shutdown" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::PadreCmd(PadreCmd::Shutdown),
+            ))),
+            "cancel" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::PadreCmd(PadreCmd::Cancel),
+            ))),
+            "auth" => {
+                let token = self.get_string("token", args);
+                match token {
+                    Some(t) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::PadreCmd(PadreCmd::Auth(t)),
+                    ))),
+                    None => Ok(None),
+                }
+            }
             "run" => Ok(Some(PadreRequest::new(
                 id,
                 RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Run)),
             ))),
-            "stepOver" => Ok(Some(PadreRequest::new(
+            "stepOver" => {
+                let count = match self.get_count(args) {
+                    Some(c) => c,
+                    None => return Ok(None),
+                };
+                let report_each = match self.get_optional_bool("reportEach", args) {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepOver(
+                        count,
+                        report_each,
+                    ))),
+                )))
+            }
+            "finish" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Finish)),
+            ))),
+            "reverseContinue" => Ok(Some(PadreRequest::new(
                 id,
-                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepOver)),
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ReverseContinue)),
             ))),
-            "stepIn" => Ok(Some(PadreRequest::new(
+            "reverseStepOver" => Ok(Some(PadreRequest::new(
                 id,
-                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepIn)),
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ReverseStepOver)),
             ))),
+            "stepIn" => {
+                let count = match self.get_count(args) {
+                    Some(c) => c,
+                    None => return Ok(None),
+                };
+                let report_each = match self.get_optional_bool("reportEach", args) {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepIn(
+                        count,
+                        report_each,
+                    ))),
+                )))
+            }
             "continue" => Ok(Some(PadreRequest::new(
                 id,
                 RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Continue)),
             ))),
+            "interrupt" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Interrupt)),
+            ))),
+            "detach" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Detach)),
+            ))),
             "breakpoint" => {
-                let file_location = self.get_file_location(&mut args);
+                let file_location = match self.get_file_location(args) {
+                    Some(fl) => fl,
+                    None => return Ok(None),
+                };
+                if args.contains_key("logMessage") {
+                    if args.contains_key("hitCondition") {
+                        util::send_error_and_debug(
+                            "Can't understand request",
+                            "A logpoint can't have a 'hitCondition'",
+                        );
+                        return Ok(None);
+                    }
+                    let message = match self.get_string("logMessage", args) {
+                        Some(m) => m,
+                        None => return Ok(None),
+                    };
+                    return Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Logpoint(
+                            file_location,
+                            message,
+                        ))),
+                    )));
+                }
+                let cmd = match args.remove("hitCondition") {
+                    None => DebuggerCmdV1::Breakpoint(file_location),
+                    Some(h) => match h.as_str().and_then(HitCondition::parse) {
+                        Some(hit_condition) => {
+                            DebuggerCmdV1::HitConditionBreakpoint(file_location, hit_condition)
+                        }
+                        None => {
+                            self.bad_argument("hitCondition", "'>=N', '>N', '==N' or '%N'", &h);
+                            return Ok(None);
+                        }
+                    },
+                };
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(cmd)),
+                )))
+            }
+            "disableBreakpoint" => {
+                let file_location = self.get_file_location(args);
                 match file_location {
                     Some(fl) => Ok(Some(PadreRequest::new(
                         id,
-                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Breakpoint(fl))),
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::DisableBreakpoint(
+                            fl,
+                        ))),
                     ))),
-                    None => return Ok(None),
+                    None => Ok(None),
+                }
+            }
+            "enableBreakpoint" => {
+                let file_location = self.get_file_location(args);
+                match file_location {
+                    Some(fl) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::EnableBreakpoint(
+                            fl,
+                        ))),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "validateBreakpoint" => {
+                let file_location = self.get_file_location(args);
+                match file_location {
+                    Some(fl) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(
+                            DebuggerCmdV1::ValidateBreakpoint(fl),
+                        )),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "setNextStatement" => {
+                let file_location = self.get_file_location(args);
+                match file_location {
+                    Some(fl) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::SetNextStatement(
+                            fl,
+                        ))),
+                    ))),
+                    None => Ok(None),
                 }
             }
             "print" => {
-                let variable = self.get_variable(&mut args);
+                let variable = self.get_variable(args);
+                let frame = match self.get_frame(args) {
+                    Some(f) => f,
+                    None => return Ok(None),
+                };
+                let format = match args.remove("format") {
+                    None => None,
+                    Some(f) => match f.as_str().and_then(ValueFormat::parse) {
+                        Some(f) => Some(f),
+                        None => {
+                            self.bad_argument("format", "'hex', 'dec', 'bin' or 'char'", &f);
+                            return Ok(None);
+                        }
+                    },
+                };
                 match variable {
                     Some(v) => Ok(Some(PadreRequest::new(
                         id,
-                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Print(v))),
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Print(
+                            v, frame, format,
+                        ))),
                     ))),
-                    None => return Ok(None),
+                    None => Ok(None),
                 }
             }
-            "getConfig" => {
-                let key = self.get_string("key", &mut args);
-                match key {
-                    Some(k) => Ok(Some(PadreRequest::new(
+            "setCwd" => {
+                let dir = self.get_string("dir", args);
+                match dir {
+                    Some(d) => Ok(Some(PadreRequest::new(
                         id,
-                        RequestCmd::PadreCmd(PadreCmd::GetConfig(k)),
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::SetCwd(d))),
                     ))),
-                    None => return Ok(None),
+                    None => Ok(None),
                 }
             }
-            "setConfig" => {
-                let key = self.get_string("key", &mut args);
-                match key {
-                    Some(k) => {
-                        let value = self.get_i64("value", &mut args);
-                        match value {
-                            Some(v) => Ok(Some(PadreRequest::new(
-                                id,
-                                RequestCmd::PadreCmd(PadreCmd::SetConfig(k, v)),
-                            ))),
-                            None => return Ok(None),
-                        }
-                    }
-                    None => return Ok(None),
+            "getChildren" => {
+                let reference = self.get_string("reference", args);
+                match reference {
+                    Some(reference) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::GetChildren {
+                            reference,
+                        })),
+                    ))),
+                    None => Ok(None),
                 }
             }
-            _ => {
-                util::send_error_and_debug(
-                    "Command unknown",
-                    &format!("Command unknown: '{}'", cmd),
-                );
-                Ok(None)
+            "frameSource" => {
+                let before = match self.get_line_count("before", args) {
+                    Some(n) => n,
+                    None => return Ok(None),
+                };
+                let after = match self.get_line_count("after", args) {
+                    Some(n) => n,
+                    None => return Ok(None),
+                };
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::GetFrameSource {
+                        before,
+                        after,
+                    })),
+                )))
             }
-        };
-
-        match args.is_empty() {
-            true => {}
-            false => {
-                let mut args_left: Vec<String> = args.iter().map(|(key, _)| key.clone()).collect();
-                args_left.sort();
-                util::send_error_and_debug(
-                    "Bad arguments",
-                    &format!("Bad arguments: {:?}", args_left),
-                );
-                return Ok(None);
+            "stackWithLocals" => match self.get_max_frames(args) {
+                Some(max_frames) => Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StackWithLocals {
+                        max_frames,
+                    })),
+                ))),
+                None => Ok(None),
+            },
+            "listSources" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ListSources)),
+            ))),
+            "listModules" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ListModules)),
+            ))),
+            "assert" => {
+                let expression = self.get_string("expression", args);
+                match expression {
+                    Some(e) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Assert(e))),
+                    ))),
+                    None => Ok(None),
+                }
             }
-        };
-
-        ret
+            "addWatch" => {
+                let expression = self.get_string("expression", args);
+                match expression {
+                    Some(e) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::AddWatch(e))),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "typeOf" => {
+                let expression = self.get_string("expression", args);
+                match expression {
+                    Some(e) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::TypeOf(e))),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "readSymbol" => {
+                let name = self.get_string("name", args);
+                match name {
+                    Some(n) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ReadSymbol(n))),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "removeWatch" => {
+                let expression = self.get_string("expression", args);
+                match expression {
+                    Some(e) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::RemoveWatch(e))),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "watch" => {
+                let variable = match self.get_variable(args) {
+                    Some(v) => v,
+                    None => return Ok(None),
+                };
+                let mode = match args.remove("mode") {
+                    None => WatchpointMode::Write,
+                    Some(m) => match m.as_str() {
+                        Some("read") => WatchpointMode::Read,
+                        Some("write") => WatchpointMode::Write,
+                        Some("readwrite") => WatchpointMode::ReadWrite,
+                        _ => {
+                            self.bad_argument("mode", "'read', 'write' or 'readwrite'", &m);
+                            return Ok(None);
+                        }
+                    },
+                };
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Watchpoint(
+                        variable, mode,
+                    ))),
+                )))
+            }
+            "exceptionBreakpoint" => {
+                let caught = match self.get_optional_bool("caught", args) {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+                let uncaught = match self.get_optional_bool("uncaught", args) {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ExceptionBreakpoint {
+                        caught,
+                        uncaught,
+                    })),
+                )))
+            }
+            "clearAllBreakpoints" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ClearAllBreakpoints)),
+            ))),
+            "currentLocation" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::CurrentLocation)),
+            ))),
+            "threads" => Ok(Some(PadreRequest::new(
+                id,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ListThreads)),
+            ))),
+            "selectThread" => {
+                let thread = self.get_i64("thread", args);
+                match thread {
+                    Some(t) if t >= 0 => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::SelectThread(
+                            t as u64,
+                        ))),
+                    ))),
+                    Some(t) => {
+                        self.bad_argument("thread", "a non-negative integer", &t.into());
+                        Ok(None)
+                    }
+                    None => Ok(None),
+                }
+            }
+            "raw" => {
+                let command = self.get_string("command", args);
+                match command {
+                    Some(c) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Raw(c))),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "batch" => {
+                let requests = self.get_json("requests", args);
+                match requests {
+                    Some(serde_json::Value::Array(requests)) => {
+                        for request in requests {
+                            if let Some(r) = self.decode_request(request, req)? {
+                                self.pending.push_back(r);
+                            }
+                        }
+                        Ok(None)
+                    }
+                    Some(r) => {
+                        self.bad_argument("requests", "an array of requests", &r);
+                        Ok(None)
+                    }
+                    None => Ok(None),
+                }
+            }
+            "getConfig" => {
+                let key = self.get_string("key", args);
+                match key {
+                    Some(k) => Ok(Some(PadreRequest::new(
+                        id,
+                        RequestCmd::PadreCmd(PadreCmd::GetConfig(k)),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            "setConfig" => {
+                let key = self.get_string("key", args);
+                match key {
+                    Some(k) => {
+                        let value = self.get_i64("value", args);
+                        match value {
+                            Some(v) => Ok(Some(PadreRequest::new(
+                                id,
+                                RequestCmd::PadreCmd(PadreCmd::SetConfig(k, v)),
+                            ))),
+                            None => Ok(None),
+                        }
+                    }
+                    None => Ok(None),
+                }
+            }
+            _ => {
+                let args = args
+                    .drain()
+                    .collect::<serde_json::Map<String, serde_json::Value>>();
+                Ok(Some(PadreRequest::new(
+                    id,
+                    RequestCmd::PadreCmd(PadreCmd::Unknown(
+                        cmd.to_string(),
+                        serde_json::Value::Object(args),
+                    )),
+                )))
+            }
+        }
     }
 }
 
@@ -420,12 +1135,26 @@ impl Encoder for VimCodec {
 
 #[cfg(test)]
 mod tests {
-    use crate::debugger::{DebuggerCmd, DebuggerCmdV1};
+    use crate::debugger::{
+        DebuggerCmd, DebuggerCmdV1, FileLocation, HitCondition, ValueFormat, Variable,
+        WatchpointMode,
+    };
     use crate::server::{Notification, PadreCmd, PadreRequest, PadreSend, RequestCmd, Response};
 
     use bytes::{BufMut, BytesMut};
     use tokio::codec::{Decoder, Encoder};
 
+    /// The request a badly specified argument is decoded as
+    fn bad_request(id: u64, key: &str, expected: &str) -> Option<PadreRequest> {
+        Some(PadreRequest::new(
+            id,
+            RequestCmd::PadreCmd(PadreCmd::BadRequest(format!(
+                "Badly specified '{}', expected {}",
+                key, expected
+            ))),
+        ))
+    }
+
     #[test]
     fn check_simple_json_decoding() {
         let mut codec = super::VimCodec::new();
@@ -498,6 +1227,1135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_disable_breakpoint_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(60);
+        buf.put(r#"[123,{"cmd":"disableBreakpoint","file":"test.c","line":12}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::DisableBreakpoint(
+                    FileLocation::new("test.c".to_string(), 12)
+                )))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_validate_breakpoint_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(61);
+        buf.put(r#"[123,{"cmd":"validateBreakpoint","file":"test.c","line":12}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ValidateBreakpoint(
+                    FileLocation::new("test.c".to_string(), 12)
+                )))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_set_next_statement_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"setNextStatement","file":"test.c","line":20}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::SetNextStatement(
+                    FileLocation::new("test.c".to_string(), 20)
+                )))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_unknown_command_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[7,{"cmd":"frobnicate","level":11}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(padre_request.id(), 7);
+        match padre_request.cmd() {
+            RequestCmd::PadreCmd(PadreCmd::Unknown(cmd, args)) => {
+                assert_eq!(cmd, "frobnicate");
+                assert_eq!(args, &serde_json::json!({"level": 11}));
+            }
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+
+        // Every known command is decoded as itself, even without the arguments it needs
+        for cmd in super::COMMANDS {
+            let mut buf = BytesMut::new();
+            let req = format!(r#"[1,{{"cmd":"{}"}}]"#, cmd);
+            buf.reserve(req.len());
+            buf.put(req);
+
+            if let Ok(Some(padre_request)) = codec.decode(&mut buf) {
+                if let RequestCmd::PadreCmd(PadreCmd::Unknown(_, _)) = padre_request.cmd() {
+                    panic!("Command {} decoded as unknown", cmd)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_invalid_json_answered_with_id() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[42,{bad}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(padre_request.id(), 42);
+        match padre_request.cmd() {
+            RequestCmd::PadreCmd(PadreCmd::BadRequest(_)) => {}
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+
+        let mut buf = BytesMut::new();
+        let req = r#"{bad}"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(padre_request.id(), 0);
+    }
+
+    #[test]
+    fn check_line_zero_rejected() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(52);
+        buf.put(r#"[123,{"cmd":"breakpoint","file":"test.c","line":0}]"#);
+
+        assert_eq!(
+            bad_request(123, "line", "a positive integer"),
+            codec.decode(&mut buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_hit_condition_breakpoint_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"breakpoint","file":"test.c","line":12,"hitCondition":">=5"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::HitConditionBreakpoint(
+                    FileLocation::new("test.c".to_string(), 12),
+                    HitCondition::AtLeast(5)
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[124,{"cmd":"breakpoint","file":"test.c","line":12,"hitCondition":"<5"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            bad_request(124, "hitCondition", "'>=N', '>N', '==N' or '%N'"),
+            codec.decode(&mut buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_logpoint_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"breakpoint","file":"test.c","line":12,"logMessage":"a is {a}"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Logpoint(
+                    FileLocation::new("test.c".to_string(), 12),
+                    "a is {a}".to_string()
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[124,{"cmd":"breakpoint","file":"test.c","line":12,"logMessage":"a","hitCondition":">5"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(None, codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn check_nested_batch_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[1,{"cmd":"batch","requests":[[2,{"cmd":"ping"}],[3,{"cmd":"batch","requests":[[4,{"cmd":"ping"}],[5,{"cmd":"ping"}]]}],[6,{"cmd":"ping"}]]}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        for id in &[2, 4, 5, 6] {
+            assert_eq!(
+                PadreRequest::new(*id, RequestCmd::PadreCmd(PadreCmd::Ping)),
+                codec.decode(&mut buf).unwrap().unwrap()
+            );
+        }
+        assert_eq!(None, codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn check_batch_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[1,{"cmd":"batch","requests":[[2,{"cmd":"breakpoint","file":"test.c","line":12}],[3,{"cmd":"breakpoint","file":"test.c","line":14}],[4,{"cmd":"run"}]]}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                2,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Breakpoint(
+                    FileLocation::new("test.c".to_string(), 12)
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+        assert_eq!(
+            PadreRequest::new(
+                3,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Breakpoint(
+                    FileLocation::new("test.c".to_string(), 14)
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+        assert_eq!(
+            PadreRequest::new(
+                4,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Run))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+        assert_eq!(None, codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn check_counted_step_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(36);
+        buf.put(r#"[123,{"cmd":"stepOver","count":3}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepOver(3, false)))
+            ),
+            padre_request
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(24);
+        buf.put(r#"[124,{"cmd":"stepIn"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepIn(1, false)))
+            ),
+            padre_request
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[125,{"cmd":"stepOver","count":10,"reportEach":true}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                125,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StepOver(10, true)))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_print_in_frame_decoding() {
+        let mut codec = super::VimCodec::new();
+
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"print","variable":"x"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Print(
+                    Variable::new("x".to_string()),
+                    None,
+                    None
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[124,{"cmd":"print","variable":"x","frame":2}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Print(
+                    Variable::new("x".to_string()),
+                    Some(2),
+                    None
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[125,{"cmd":"print","variable":"x","frame":-1}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            bad_request(125, "frame", "a non-negative integer"),
+            codec.decode(&mut buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_print_format_decoding() {
+        let mut codec = super::VimCodec::new();
+
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"print","variable":"x","format":"hex"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Print(
+                    Variable::new("x".to_string()),
+                    None,
+                    Some(ValueFormat::Hex)
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_select_thread_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(40);
+        buf.put(r#"[123,{"cmd":"selectThread","thread":2}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::SelectThread(2)))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_watch_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(41);
+        buf.put(r#"[123,{"cmd":"addWatch","expression":"a"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::AddWatch("a".to_string())))
+            ),
+            padre_request
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(44);
+        buf.put(r#"[124,{"cmd":"removeWatch","expression":"a"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::RemoveWatch(
+                    "a".to_string()
+                )))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_watchpoint_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(52);
+        buf.put(r#"[123,{"cmd":"watch","variable":"a","mode":"read"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Watchpoint(
+                    Variable::new("a".to_string()),
+                    WatchpointMode::Read
+                )))
+            ),
+            padre_request
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(39);
+        buf.put(r#"[124,{"cmd":"watch","variable":"a"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Watchpoint(
+                    Variable::new("a".to_string()),
+                    WatchpointMode::Write
+                )))
+            ),
+            padre_request
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(53);
+        buf.put(r#"[125,{"cmd":"watch","variable":"a","mode":"never"}]"#);
+
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            bad_request(125, "mode", "'read', 'write' or 'readwrite'")
+        );
+    }
+
+    #[test]
+    fn check_finish_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(23);
+        buf.put(r#"[123,{"cmd":"finish"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Finish))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_status_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(23);
+        buf.put(r#"[123,{"cmd":"status"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(123, RequestCmd::PadreCmd(PadreCmd::Status)),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_shutdown_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(25);
+        buf.put(r#"[123,{"cmd":"shutdown"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(123, RequestCmd::PadreCmd(PadreCmd::Shutdown)),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_reverse_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"reverseContinue"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ReverseContinue))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[124,{"cmd":"reverseStepOver"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ReverseStepOver))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_exception_breakpoint_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(51);
+        buf.put(r#"[123,{"cmd":"exceptionBreakpoint","uncaught":true}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ExceptionBreakpoint {
+                    caught: false,
+                    uncaught: true
+                }))
+            ),
+            padre_request
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(50);
+        buf.put(r#"[124,{"cmd":"exceptionBreakpoint","caught":"yes"}]"#);
+
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            bad_request(124, "caught", "true or false")
+        );
+    }
+
+    #[test]
+    fn check_set_cwd_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"setCwd","dir":"/tmp"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::SetCwd("/tmp".to_string())))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_type_of_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"typeOf","expression":"a.b[1]"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::TypeOf(
+                    "a.b[1]".to_string()
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_read_symbol_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"readSymbol","name":"g_config"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ReadSymbol(
+                    "g_config".to_string()
+                )))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let req = r#"[124,{"cmd":"readSymbol"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(None, codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn check_list_modules_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"listModules"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ListModules))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_list_sources_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"listSources"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ListSources))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_assert_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"assert","expression":"x == 42"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Assert(
+                    "x == 42".to_string()
+                )))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_duplicate_keys_are_a_bad_request() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[42,{"cmd":"breakpoint","file":"test.c","line":1,"line":2}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(padre_request.id(), 42);
+        match padre_request.cmd() {
+            RequestCmd::PadreCmd(PadreCmd::BadRequest(msg)) => {
+                assert!(msg.contains("duplicate key 'line'"), "{}", msg)
+            }
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+
+        let mut buf = BytesMut::new();
+        let req =
+            r#"[43,{"cmd":"batch","requests":[[44,{"cmd":"raw","command":"a","command":"b"}]]}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(padre_request.id(), 43);
+        match padre_request.cmd() {
+            RequestCmd::PadreCmd(PadreCmd::BadRequest(msg)) => {
+                assert!(msg.contains("duplicate key 'command'"), "{}", msg)
+            }
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn check_badly_typed_arguments_rejected() {
+        let requests = [
+            (
+                r#"[1,{"cmd":"breakpoint","file":12,"line":1}]"#,
+                "file",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"breakpoint","file":"test.c","line":"1"}]"#,
+                "line",
+                "a positive integer",
+            ),
+            (
+                r#"[1,{"cmd":"breakpoint","file":"test.c","line":0}]"#,
+                "line",
+                "a positive integer",
+            ),
+            (
+                r#"[1,{"cmd":"breakpoint","file":"test.c","line":1,"hitCondition":5}]"#,
+                "hitCondition",
+                "'>=N', '>N', '==N' or '%N'",
+            ),
+            (
+                r#"[1,{"cmd":"breakpoint","file":"test.c","line":1,"logMessage":true}]"#,
+                "logMessage",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"disableBreakpoint","file":"test.c","line":-1}]"#,
+                "line",
+                "a positive integer",
+            ),
+            (
+                r#"[1,{"cmd":"enableBreakpoint","file":["test.c"],"line":1}]"#,
+                "file",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"validateBreakpoint","file":"test.c","line":1.5}]"#,
+                "line",
+                "a positive integer",
+            ),
+            (
+                r#"[1,{"cmd":"setNextStatement","file":"test.c","line":"1"}]"#,
+                "line",
+                "a positive integer",
+            ),
+            (
+                r#"[1,{"cmd":"stepOver","count":"3"}]"#,
+                "count",
+                "a 64-bit integer",
+            ),
+            (
+                r#"[1,{"cmd":"stepOver","count":0}]"#,
+                "count",
+                "a positive integer",
+            ),
+            (
+                r#"[1,{"cmd":"stepIn","count":2,"reportEach":1}]"#,
+                "reportEach",
+                "true or false",
+            ),
+            (
+                r#"[1,{"cmd":"print","variable":1}]"#,
+                "variable",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"print","variable":"a","frame":-1}]"#,
+                "frame",
+                "a non-negative integer",
+            ),
+            (
+                r#"[1,{"cmd":"print","variable":"a","frame":"0"}]"#,
+                "frame",
+                "a 64-bit integer",
+            ),
+            (
+                r#"[1,{"cmd":"print","variable":"a","format":"oct"}]"#,
+                "format",
+                "'hex', 'dec', 'bin' or 'char'",
+            ),
+            (
+                r#"[1,{"cmd":"print","variable":"a","format":16}]"#,
+                "format",
+                "'hex', 'dec', 'bin' or 'char'",
+            ),
+            (r#"[1,{"cmd":"setCwd","dir":null}]"#, "dir", "a string"),
+            (
+                r#"[1,{"cmd":"getChildren","reference":{}}]"#,
+                "reference",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"frameSource","before":"2"}]"#,
+                "before",
+                "a 64-bit integer",
+            ),
+            (
+                r#"[1,{"cmd":"frameSource","after":-2}]"#,
+                "after",
+                "a non-negative integer",
+            ),
+            (
+                r#"[1,{"cmd":"stackWithLocals","maxFrames":"20"}]"#,
+                "maxFrames",
+                "a 64-bit integer",
+            ),
+            (
+                r#"[1,{"cmd":"assert","expression":false}]"#,
+                "expression",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"addWatch","expression":1}]"#,
+                "expression",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"removeWatch","expression":1}]"#,
+                "expression",
+                "a string",
+            ),
+            (r#"[1,{"cmd":"readSymbol","name":1}]"#, "name", "a string"),
+            (
+                r#"[1,{"cmd":"typeOf","expression":null}]"#,
+                "expression",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"watch","variable":"a","mode":"exec"}]"#,
+                "mode",
+                "'read', 'write' or 'readwrite'",
+            ),
+            (
+                r#"[1,{"cmd":"watch","variable":"a","mode":1}]"#,
+                "mode",
+                "'read', 'write' or 'readwrite'",
+            ),
+            (
+                r#"[1,{"cmd":"exceptionBreakpoint","caught":"yes"}]"#,
+                "caught",
+                "true or false",
+            ),
+            (
+                r#"[1,{"cmd":"exceptionBreakpoint","uncaught":0}]"#,
+                "uncaught",
+                "true or false",
+            ),
+            (
+                r#"[1,{"cmd":"selectThread","thread":-1}]"#,
+                "thread",
+                "a non-negative integer",
+            ),
+            (
+                r#"[1,{"cmd":"selectThread","thread":"1"}]"#,
+                "thread",
+                "a 64-bit integer",
+            ),
+            (
+                r#"[1,{"cmd":"raw","command":["bt"]}]"#,
+                "command",
+                "a string",
+            ),
+            (
+                r#"[1,{"cmd":"batch","requests":{}}]"#,
+                "requests",
+                "an array of requests",
+            ),
+            (r#"[1,{"cmd":"getConfig","key":1}]"#, "key", "a string"),
+            (
+                r#"[1,{"cmd":"setConfig","key":"StepTimeout","value":"1"}]"#,
+                "value",
+                "a 64-bit integer",
+            ),
+            (r#"[1,{"cmd":"auth","token":1}]"#, "token", "a string"),
+        ];
+
+        for (req, key, expected) in requests.iter() {
+            let mut codec = super::VimCodec::new();
+            let mut buf = BytesMut::new();
+            buf.reserve(req.len());
+            buf.put(*req);
+
+            assert_eq!(
+                codec.decode(&mut buf).unwrap(),
+                bad_request(1, key, expected),
+                "{}",
+                req
+            );
+        }
+    }
+
+    #[test]
+    fn check_stack_with_locals_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(100);
+        buf.put(r#"[123,{"cmd":"stackWithLocals","maxFrames":5}]"#);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StackWithLocals {
+                    max_frames: 5
+                }))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        buf.reserve(100);
+        buf.put(r#"[124,{"cmd":"stackWithLocals"}]"#);
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::StackWithLocals {
+                    max_frames: 20
+                }))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        buf.reserve(100);
+        buf.put(r#"[125,{"cmd":"stackWithLocals","maxFrames":0}]"#);
+
+        assert_eq!(
+            bad_request(125, "maxFrames", "a positive integer"),
+            codec.decode(&mut buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_clear_all_breakpoints_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"clearAllBreakpoints"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::ClearAllBreakpoints))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_detach_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"detach"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Detach))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_frame_source_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"frameSource","before":3,"after":10}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::GetFrameSource {
+                    before: 3,
+                    after: 10
+                }))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[124,{"cmd":"frameSource"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            PadreRequest::new(
+                124,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::GetFrameSource {
+                    before: 5,
+                    after: 5
+                }))
+            ),
+            codec.decode(&mut buf).unwrap().unwrap()
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[125,{"cmd":"frameSource","before":-1}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            bad_request(125, "before", "a non-negative integer"),
+            codec.decode(&mut buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_get_children_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"getChildren","reference":"a.b"}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::GetChildren {
+                    reference: "a.b".to_string()
+                }))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_echo_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(45);
+        buf.put(r#"[123,{"cmd":"echo","payload":{"a":[1,"b"]}}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::PadreCmd(PadreCmd::Echo(serde_json::json!({"a":[1,"b"]})))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_raw_command_decoding() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        buf.reserve(47);
+        buf.put(r#"[123,{"cmd":"raw","command":"image list -b"}]"#);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(
+            PadreRequest::new(
+                123,
+                RequestCmd::DebuggerCmd(DebuggerCmd::V1(DebuggerCmdV1::Raw(
+                    "image list -b".to_string()
+                )))
+            ),
+            padre_request
+        );
+    }
+
+    #[test]
+    fn check_meta_round_trips() {
+        let mut codec = super::VimCodec::new();
+        let mut buf = BytesMut::new();
+        let req = r#"[123,{"cmd":"ping","meta":{"tag":"hover","n":[1,2]}}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        let padre_request = codec.decode(&mut buf).unwrap().unwrap();
+        let meta = serde_json::json!({"tag":"hover","n":[1,2]});
+
+        assert_eq!(
+            PadreRequest::new(123, RequestCmd::PadreCmd(PadreCmd::Ping)).with_meta(meta.clone()),
+            padre_request
+        );
+
+        let resp = Response::new(123, serde_json::json!({"status":"OK","ping":"pong"}))
+            .with_meta(padre_request.meta().cloned());
+        codec.encode(PadreSend::Response(resp), &mut buf).unwrap();
+
+        let encoded: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!([123, {"status":"OK","ping":"pong","meta":{"tag":"hover","n":[1,2]}}])
+        );
+
+        let mut buf = BytesMut::new();
+        let req = r#"[124,{"cmd":"batch","requests":[[125,{"cmd":"run","meta":"a"}],[126,{"cmd":"run"}]]}]"#;
+        buf.reserve(req.len());
+        buf.put(req);
+
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().meta(),
+            Some(&serde_json::json!("a"))
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().meta(), None);
+    }
+
     #[test]
     fn check_json_encoding_response() {
         let mut codec = super::VimCodec::new();
@@ -42,9 +42,13 @@ command -nargs=0 PadreRun call padre#debugger#Run()
 command -nargs=0 PadreBreakpoint call padre#debugger#Breakpoint()
 command -nargs=0 PadreStepIn call padre#debugger#StepIn()
 command -nargs=0 PadreStepOver call padre#debugger#StepOver()
+command -nargs=0 PadreFinish call padre#debugger#Finish()
 command -nargs=0 PadreStop call padre#debugger#Stop()
 command -nargs=1 PadrePrintVariable call padre#debugger#PrintVariable(<f-args>)
 command -nargs=0 PadreContinue call padre#debugger#Continue()
+command -nargs=0 PadreInterrupt call padre#debugger#Interrupt()
+command -nargs=1 PadreAddWatch call padre#debugger#AddWatch(<q-args>)
+command -nargs=1 PadreRemoveWatch call padre#debugger#RemoveWatch(<q-args>)
 
 " This is basic vim plugin boilerplate
 call s:restore_cpo()